@@ -4,8 +4,11 @@
 //!
 //! Will have the similar feat as required in [issue#19](https://github.com/refraction-networking/water/issues/19) on the go-side.
 
+pub mod registry;
 pub mod wasm_shared_config;
 
+use serde::{Deserialize, Serialize};
+
 /// WATER configuration
 #[derive(Clone)]
 pub struct WATERConfig {
@@ -22,6 +25,23 @@ pub struct WATERConfig {
     pub client_type: WaterBinType,
 
     pub debug: bool,
+
+    /// How the stdio of the WATM instances is handled, inherited from the Host by default
+    pub stdio: StdioMode,
+
+    /// Ed25519 public keys trusted to sign the WATM binaries, the signature is only verified when not empty
+    pub trusted_keys: Vec<[u8; 32]>,
+
+    /// v1 only, exchange the bytes with the WATM through a buffer in its linear memory instead of pipes,
+    /// when the WATM supports it (see `runtime::v1::shared_buffer`)
+    pub v1_shared_buffer: bool,
+
+    /// Options of the sockets created by the Host for the WATM, the OS defaults are kept when not set
+    pub socket_options: SocketOptions,
+
+    /// Hex encoded sha256 digest the .wasm binary must match (e.g. pinned in a registry manifest),
+    /// checked on the bytes compiled; any binary is loaded when not set
+    pub sha256: Option<String>,
 }
 
 impl WATERConfig {
@@ -38,12 +58,78 @@ impl WATERConfig {
             config_wasm,
             client_type,
             debug,
+            stdio: StdioMode::Inherit,
+            trusted_keys: Vec::new(),
+            v1_shared_buffer: false,
+            socket_options: SocketOptions::default(),
+            sha256: None,
         })
     }
 }
 
+/// How the stdin / stdout / stderr of a WATM instance is handled
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum StdioMode {
+    /// Share the stdio of the Host
+    #[default]
+    Inherit,
+
+    /// Discard everything written, and nothing to read from stdin
+    Null,
+
+    /// Keep the last N bytes of stdout and stderr each in an in-memory ring buffer, retrievable from the client
+    Capture(usize),
+
+    /// Append stdout and stderr to the file at the given path
+    File(String),
+}
+
+/// Options of the sockets the Host creates for a transport: the dialed, listening and accepted ones.
+///
+/// Every option left unset keeps the default of the OS (or of `std::net` for the listeners).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocketOptions {
+    /// TCP_NODELAY, disabling Nagle's algorithm
+    pub nodelay: Option<bool>,
+
+    /// Enable SO_KEEPALIVE with the idle time before the first probe (TCP_KEEPIDLE), in seconds
+    pub keepalive_secs: Option<u64>,
+
+    /// Interval between the keepalive probes (TCP_KEEPINTVL), in seconds, enables SO_KEEPALIVE
+    pub keepalive_interval_secs: Option<u64>,
+
+    /// Number of unanswered keepalive probes before the connection is dropped (TCP_KEEPCNT), enables SO_KEEPALIVE
+    pub keepalive_retries: Option<u32>,
+
+    /// SO_REUSEADDR, enabled on the listeners unless set to false (as `std::net::TcpListener` does)
+    pub reuse_address: Option<bool>,
+
+    /// SO_REUSEPORT
+    pub reuse_port: Option<bool>,
+
+    /// SO_SNDBUF, in bytes
+    pub send_buffer_size: Option<usize>,
+
+    /// SO_RCVBUF, in bytes
+    pub recv_buffer_size: Option<usize>,
+
+    /// Backlog of the listeners, 128 when not set (as `std::net::TcpListener` does)
+    pub listen_backlog: Option<i32>,
+
+    /// Source address the outbound connections are bound to before dialing, the port is picked by the OS
+    pub source_address: Option<std::net::IpAddr>,
+
+    /// SO_MARK, for policy routing (Linux only)
+    pub mark: Option<u32>,
+
+    /// SO_BINDTODEVICE, the name of the interface the sockets are bound to (Linux only)
+    pub bind_device: Option<String>,
+}
+
 /// WATER client type: A enum of types of the client
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum WaterBinType {
     Dial,
     Listen,
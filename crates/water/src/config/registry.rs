@@ -0,0 +1,185 @@
+//! A local registry of WATM packages, resolving a transport by name and version into a ready `WATERConfig`.
+//!
+//! The registry is a directory where every sub-directory holding a `manifest.json` is a package:
+//!
+//! ```text
+//! registry/
+//! └── plain-0.1.0/
+//!     ├── manifest.json
+//!     ├── plain.wasm
+//!     └── config.json
+//! ```
+//!
+//! with the manifest like:
+//!
+//! ```json
+//! {
+//!     "name": "plain",
+//!     "version": "0.1.0",
+//!     "module": "plain.wasm",
+//!     "sha256": "<hex digest of plain.wasm>",
+//!     "roles": ["dial", "listen", "relay"],
+//!     "entry_fn": "_water_worker",
+//!     "config": "config.json"
+//! }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::{SocketOptions, WATERConfig, WaterBinType};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Manifest describing a WATM package in the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: Version,
+
+    /// Path of the .wasm binary, relative to the package directory
+    pub module: String,
+
+    /// Hex encoded sha256 digest of the .wasm binary, checked when loading the resolved module
+    pub sha256: String,
+
+    /// Roles the WATM supports
+    pub roles: Vec<WaterBinType>,
+
+    /// Default entry function
+    pub entry_fn: String,
+
+    /// Default config file for the WATM, relative to the package directory
+    pub config: String,
+
+    /// Options of the sockets created by the Host for the WATM, tuned for the transport
+    #[serde(default)]
+    pub socket_options: SocketOptions,
+}
+
+/// A package found in the registry
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub manifest: Manifest,
+
+    /// Directory of the package
+    pub dir: PathBuf,
+}
+
+impl Package {
+    pub fn module_path(&self) -> PathBuf {
+        self.dir.join(&self.manifest.module)
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        self.dir.join(&self.manifest.config)
+    }
+}
+
+/// The local registry of WATM packages
+pub struct Registry {
+    pub root: PathBuf,
+    packages: Vec<Package>,
+}
+
+impl Registry {
+    /// Load the manifests of all the packages under `root`
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self, anyhow::Error> {
+        let root = root.as_ref().to_path_buf();
+        let mut packages = Vec::new();
+
+        for entry in std::fs::read_dir(&root)
+            .context(format!("failed to read registry {}", root.display()))?
+        {
+            let dir = entry?.path();
+            let manifest_path = dir.join(MANIFEST_FILE);
+            if !manifest_path.is_file() {
+                continue;
+            }
+
+            let manifest = std::fs::read_to_string(&manifest_path)?;
+            let manifest: Manifest = serde_json::from_str(&manifest)
+                .context(format!("failed to parse {}", manifest_path.display()))?;
+
+            packages.push(Package { manifest, dir });
+        }
+
+        packages.sort_by(|a, b| {
+            (&a.manifest.name, &a.manifest.version).cmp(&(&b.manifest.name, &b.manifest.version))
+        });
+
+        Ok(Registry { root, packages })
+    }
+
+    /// All the packages, sorted by name and version
+    pub fn packages(&self) -> &[Package] {
+        &self.packages
+    }
+
+    /// Find the latest version of the package `name` matching the version requirement (e.g. `^0.1`, `*`)
+    pub fn find(&self, name: &str, version_req: &str) -> Result<&Package, anyhow::Error> {
+        let req = VersionReq::parse(version_req)
+            .context(format!("invalid version requirement {}", version_req))?;
+
+        self.packages
+            .iter()
+            .filter(|p| p.manifest.name == name && req.matches(&p.manifest.version))
+            .max_by(|a, b| a.manifest.version.cmp(&b.manifest.version))
+            .ok_or_else(|| anyhow::anyhow!("no WATM package {} matching {}", name, version_req))
+    }
+
+    /// Resolve the package `name` matching `version_req` into a `WATERConfig` for `role`, after checking the role is supported.
+    ///
+    /// The config carries the pinned hash, the module is refused when loaded if the bytes compiled don't match it.
+    pub fn resolve(
+        &self,
+        name: &str,
+        version_req: &str,
+        role: WaterBinType,
+    ) -> Result<WATERConfig, anyhow::Error> {
+        let package = self.find(name, version_req)?;
+        let manifest = &package.manifest;
+
+        // a wrapped connection is driven by the WATM as a dialed one
+        let supported = manifest.roles.contains(&role)
+            || (role == WaterBinType::Wrap && manifest.roles.contains(&WaterBinType::Dial));
+        if !supported {
+            return Err(anyhow::anyhow!(
+                "WATM package {}@{} doesn't support role {:?}",
+                manifest.name,
+                manifest.version,
+                role
+            ));
+        }
+
+        let mut conf = WATERConfig::init(
+            package.module_path().to_string_lossy().into_owned(),
+            manifest.entry_fn.clone(),
+            package.config_path().to_string_lossy().into_owned(),
+            role,
+            false,
+        )?;
+        conf.socket_options = manifest.socket_options.clone();
+        conf.sha256 = Some(manifest.sha256.clone());
+        Ok(conf)
+    }
+}
+
+/// Hex encoded sha256 digest of the file at `path`, as pinned in the manifests
+pub fn sha256_hex<P: AsRef<Path>>(path: P) -> Result<String, anyhow::Error> {
+    let bytes = std::fs::read(path.as_ref())
+        .context(format!("failed to read {}", path.as_ref().display()))?;
+    Ok(digest_hex(&bytes))
+}
+
+/// Hex encoded sha256 digest of `bytes`
+pub fn digest_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
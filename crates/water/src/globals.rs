@@ -8,6 +8,7 @@
 
 pub const WASM_PATH: &str = "./proxy.wasm";
 pub const CONFIG_WASM_PATH: &str = "./config.json";
+pub const REGISTRY_PATH: &str = "./registry";
 
 pub const MAIN: &str = "main";
 pub const VERSION_FN: &str = "_water_version";
@@ -20,6 +21,9 @@ pub const ACCEPT_FN: &str = "_water_accept";
 pub const DIAL_FN: &str = "_water_dial";
 pub const ASSOCIATE_FN: &str = "_water_associate";
 pub const CANCEL_FN: &str = "_water_cancel_with";
+pub const ALLOC_FN: &str = "_water_alloc";
+pub const READ_BUF_FN: &str = "_water_read_buf";
+pub const WRITE_BUF_FN: &str = "_water_write_buf";
 
 pub const RUNTIME_VERSION_MAJOR: i32 = 0x001aaaaa;
 pub const RUNTIME_VERSION: &str = "v0.1-alpha";
@@ -0,0 +1,309 @@
+//! The client of the async mode, the counterpart of `WATERClient` with async methods.
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    task::JoinHandle,
+};
+
+use crate::runtime::{asynchronous::AsyncH2O, stats::StatsSnapshot, *};
+
+/// A v0 WATM instance handling one connection, driven by the Tokio runtime it is used in
+/// ```ignore
+///           UnixSocket          Connection created with Host
+///    Write =>  u2w  +----------------+  w2n
+///            -----> |  WATM worker   | ------>
+///    Caller         |  (Tokio task)  |  n2w    Destination
+///            <----- | Decode/Encode  | <------
+///    Read  =>  w2u  +----------------+
+/// ```
+pub struct AsyncWATERClient {
+    config: WATERConfig,
+
+    /// the core is moved into the worker task when the worker runs
+    core: Option<AsyncH2O>,
+
+    /// the pipe for communicating between Host and WASM
+    caller_io: Option<tokio::net::UnixStream>,
+    /// the pipe for cancelling the worker
+    cancel_io: Option<tokio::net::UnixStream>,
+
+    /// traffic statistics of the connection handled by this client
+    stats: ConnStats,
+
+    /// captured stdout & stderr of the WATM instance, only with `StdioMode::Capture`
+    stdio: Option<CapturedStdio>,
+}
+
+impl AsyncWATERClient {
+    /// `new` is the constructor of `AsyncWATERClient`, only the v0 Dialer, Listener and Relay are supported
+    pub async fn new(conf: WATERConfig) -> Result<Self, anyhow::Error> {
+        info!("[HOST] AsyncWATERClient initializing ...");
+
+        match conf.client_type {
+            WaterBinType::Dial | WaterBinType::Listen | WaterBinType::Relay => {}
+            _ => {
+                return Err(anyhow::anyhow!("Invalid client type for async mode"));
+            }
+        }
+
+        let core = AsyncH2O::init_core(&conf).await?;
+
+        Ok(AsyncWATERClient {
+            config: conf,
+            stats: core.stats.clone(),
+            stdio: core.stdio.clone(),
+            core: Some(core),
+            caller_io: None,
+            cancel_io: None,
+        })
+    }
+
+    /// `stats` returns a snapshot of the traffic statistics of the connection handled by this client
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// `local_addr` returns the address the network side is bound to: the listener of a Listener / Relay
+    /// (with the port picked by the OS when the config asks for port 0), or the connection of a Dialer
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr, anyhow::Error> {
+        self.stats
+            .local_addr()
+            .context("[HOST] AsyncWATERClient is not bound to any address yet")
+    }
+
+    /// `peer_addr` returns the address of the peer of the connection dialed or accepted on the network side
+    pub fn peer_addr(&self) -> Result<std::net::SocketAddr, anyhow::Error> {
+        self.stats
+            .peer_addr()
+            .context("[HOST] AsyncWATERClient is not connected yet")
+    }
+
+    /// `captured_stdout` returns the last bytes written to stdout by the WATM, only with `StdioMode::Capture`
+    pub fn captured_stdout(&self) -> Option<Vec<u8>> {
+        self.stdio.as_ref().map(|stdio| stdio.stdout.contents())
+    }
+
+    /// `captured_stderr` returns the last bytes written to stderr by the WATM, only with `StdioMode::Capture`
+    pub fn captured_stderr(&self) -> Option<Vec<u8>> {
+        self.stdio.as_ref().map(|stdio| stdio.stderr.contents())
+    }
+
+    /// `connect` is the function for `Dialer` to connect to a remote address
+    pub async fn connect(&mut self) -> Result<(), anyhow::Error> {
+        info!("[HOST] AsyncWATERClient connecting ...");
+
+        if self.config.client_type != WaterBinType::Dial {
+            return Err(anyhow::anyhow!("[HOST] This client is not a Dialer"));
+        }
+
+        let caller_io = self
+            .call_with_pipe(DIAL_FN)
+            .await
+            .inspect_err(|_| self.stats.record_error())?;
+        self.caller_io = Some(caller_io);
+
+        self.stats.mark_started();
+        Ok(())
+    }
+
+    /// `connect_to` is the function for `Dialer` to connect to `addr` (address:port) instead of the remote address in the config
+    pub async fn connect_to(&mut self, addr: &str) -> Result<(), anyhow::Error> {
+        info!("[HOST] AsyncWATERClient connecting to {} ...", addr);
+
+        self.core()?.store.data_mut().set_destination(addr)?;
+        self.connect().await
+    }
+
+    /// `listen` is the function for `Listener` and `Relay` to create the Listener and listen on a local addr
+    pub async fn listen(&mut self) -> Result<(), anyhow::Error> {
+        info!("[HOST] AsyncWATERClient creating listener ...");
+
+        let is_relay = match self.config.client_type {
+            WaterBinType::Listen => false,
+            WaterBinType::Relay => true,
+            _ => {
+                return Err(anyhow::anyhow!("[HOST] This client is not a Listener"));
+            }
+        };
+
+        let v0_conf = self.core()?.v0_config()?;
+        let mut v0_conf = v0_conf
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock v0_conf: {}", e))?;
+        v0_conf.create_listener(is_relay)?;
+        self.stats.record_socket_addrs(&v0_conf.listener_socket()?);
+        Ok(())
+    }
+
+    /// `accept` is the function for `Listener` to accept a connection
+    /// called after `listen()`
+    pub async fn accept(&mut self) -> Result<(), anyhow::Error> {
+        info!("[HOST] AsyncWATERClient accepting ...");
+
+        if self.config.client_type != WaterBinType::Listen {
+            return Err(anyhow::anyhow!("[HOST] This client is not a Listener"));
+        }
+
+        let caller_io = self
+            .call_with_pipe(ACCEPT_FN)
+            .await
+            .inspect_err(|_| self.stats.record_error())?;
+        self.caller_io = Some(caller_io);
+
+        self.stats.mark_started();
+        Ok(())
+    }
+
+    /// `associate` is the function for `Relay` to associate a remote connection
+    pub async fn associate(&mut self) -> Result<(), anyhow::Error> {
+        info!("[HOST] AsyncWATERClient relaying ...");
+
+        if self.config.client_type != WaterBinType::Relay {
+            return Err(anyhow::anyhow!("[HOST] This client is not a Relay"));
+        }
+
+        let res = self
+            .core()?
+            .call(ASSOCIATE_FN, &[])
+            .await
+            .inspect_err(|_| self.stats.record_error())?;
+
+        if res[0].unwrap_i32() < 0 {
+            self.stats.record_error();
+            return Err(anyhow::Error::msg(format!(
+                "{} function failed: {}",
+                ASSOCIATE_FN, "connection failed"
+            )));
+        }
+
+        self.stats.mark_started();
+        Ok(())
+    }
+
+    /// `cancel_with` is the function to set the cancel pipe for exiting later
+    pub async fn cancel_with(&mut self) -> Result<(), anyhow::Error> {
+        info!("[HOST] AsyncWATERClient cancel_with ...");
+
+        let cancel_io = self.call_with_pipe(CANCEL_FN).await?;
+        self.cancel_io = Some(cancel_io);
+        Ok(())
+    }
+
+    /// `run_worker` spawns the entry_fn (the worker in WATM) as a task on the current Tokio runtime
+    /// and returns its `JoinHandle`, the client can't call into the WATM anymore afterwards.
+    pub fn run_worker(&mut self) -> Result<JoinHandle<Result<(), anyhow::Error>>, anyhow::Error> {
+        info!(
+            "[HOST] AsyncWATERClient running entry_fn {}...",
+            self.config.entry_fn
+        );
+
+        let mut core = match self.core.take() {
+            Some(core) => core,
+            None => return Err(anyhow::anyhow!("The worker is already running")),
+        };
+
+        let entry_fn = self.config.entry_fn.clone();
+        Ok(tokio::spawn(async move {
+            core.call(&entry_fn, &[]).await?;
+            Ok(())
+        }))
+    }
+
+    /// `cancel` is the function to send thru the cancel_pipe and let the worker exit
+    pub async fn cancel(&mut self) -> Result<(), anyhow::Error> {
+        info!("[HOST] AsyncWATERClient canceling ...");
+
+        match &mut self.cancel_io {
+            // write anything to cancel
+            Some(cancel_io) => cancel_io
+                .write_all(&[0])
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to write to cancel_io: {}", e))?,
+            None => {
+                return Err(anyhow::Error::msg(format!(
+                    "cancel function failed: {}",
+                    "cancel_io is None"
+                )))
+            }
+        }
+
+        self.stats.mark_ended();
+        Ok(())
+    }
+
+    /// `read` is the function to read from the stream
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<i64, anyhow::Error> {
+        debug!("[HOST] AsyncWATERClient reading ...");
+
+        let read_bytes = match &mut self.caller_io {
+            Some(caller_io) => match caller_io.read(buf).await {
+                Ok(n) if n > 0 => Ok(n as i64),
+                Ok(_) => Err(anyhow::Error::msg("Stream closed or read 0 bytes")),
+                Err(e) => Err(anyhow::anyhow!("failed to read from caller_reader: {}", e)),
+            },
+            None => Err(anyhow::Error::msg(format!(
+                "read function failed: {}",
+                "caller_io is None"
+            ))),
+        }
+        .inspect_err(|_| self.stats.record_error())?;
+
+        self.stats.record_caller_read(read_bytes as u64);
+        Ok(read_bytes)
+    }
+
+    /// `write` is the function to write to the stream
+    pub async fn write(&mut self, buf: &[u8]) -> Result<(), anyhow::Error> {
+        debug!("[HOST] AsyncWATERClient writing ...");
+
+        match &mut self.caller_io {
+            Some(caller_io) => caller_io
+                .write_all(buf)
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to write to caller_writer: {}", e)),
+            None => Err(anyhow::Error::msg(format!(
+                "write function failed: {}",
+                "caller_io is None"
+            ))),
+        }
+        .inspect_err(|_| self.stats.record_error())?;
+
+        self.stats.record_caller_write(buf.len() as u64);
+        Ok(())
+    }
+
+    fn core(&mut self) -> Result<&mut AsyncH2O, anyhow::Error> {
+        self.core
+            .as_mut()
+            .ok_or(anyhow::anyhow!("The worker is already running"))
+    }
+
+    /// Create a pipe between the Host and the WATM, and call `function` of the WATM with the fd of its end,
+    /// returning the Host end when the WATM accepts it (with a non-negative result).
+    async fn call_with_pipe(
+        &mut self,
+        function: &str,
+    ) -> Result<tokio::net::UnixStream, anyhow::Error> {
+        let (caller_io, water_io) = std::os::unix::net::UnixStream::pair()?;
+
+        caller_io.set_nonblocking(true)?;
+        let caller_io = tokio::net::UnixStream::from_std(caller_io)?;
+
+        let water_io = wasmtime_wasi::tokio::UnixStream::from_cap_std(
+            cap_std::os::unix::net::UnixStream::from_std(water_io),
+        );
+
+        let core = self.core()?;
+        let water_io_fd = core.push_file(Box::new(water_io))?;
+
+        let res = core.call(function, &[Val::I32(water_io_fd as i32)]).await?;
+        if res[0].unwrap_i32() < 0 {
+            return Err(anyhow::Error::msg(format!(
+                "{} function failed: {}",
+                function, "connection failed"
+            )));
+        }
+
+        Ok(caller_io)
+    }
+}
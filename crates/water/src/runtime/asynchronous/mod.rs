@@ -0,0 +1,189 @@
+//! The async mode of the runtime (with the `async` feature), where the v0 WATMs are driven by Tokio
+//! instead of having a dedicated OS thread each:
+//!
+//! - the engine is created with wasmtime's `async_support`, and every WATM function is called with `call_async`,
+//!   so the worker of a WATM is a future that can be spawned on a Tokio runtime;
+//! - `poll_oneoff` of the WATM is done by the scheduler in `sched`, waiting for the readiness of its
+//!   files as a future instead of blocking the thread;
+//! - `host_dial` & `host_accept` are async host functions connecting / accepting with Tokio.
+//!
+//! Different from the sync runtime, the pipes between the Host and the WATM are still UnixStream pairs here,
+//! since their readiness is awaited with Tokio.
+//!
+//! wasi-tokio does the reads & writes of the WATM with `block_in_place`, so a multi-thread Tokio runtime is required.
+//! Only v0 Dialer, Listener and Relay are supported for now, with one connection per client.
+
+pub mod client;
+pub mod sched;
+
+use std::time::Instant;
+
+use crate::runtime::{v0::config::V0Config, *};
+
+/// The core of a WATM instance in async mode, the counterpart of `H2O` where the store is owned by the core
+/// (and moved into the worker future when the worker runs) instead of being shared behind a Mutex.
+pub struct AsyncH2O {
+    pub version: Version,
+
+    pub engine: Engine,
+    pub instance: Instance,
+    pub store: Store<Host>,
+    pub module: Module,
+
+    /// keeps this instance counted in the active instances metric until the core is dropped
+    pub instance_guard: metrics::ActiveInstance,
+
+    /// traffic statistics of this instance, accessible without the store
+    pub stats: ConnStats,
+
+    /// captured stdout & stderr of this instance, only with `StdioMode::Capture`
+    pub stdio: Option<CapturedStdio>,
+}
+
+impl AsyncH2O {
+    /// generate a new async core instance, calling the `_init` (and the config function if there is one) of the WATM
+    pub async fn init_core(conf: &WATERConfig) -> Result<Self, anyhow::Error> {
+        info!("[HOST] WATERCore async initing...");
+
+        let started = Instant::now();
+
+        let mut wasm_config = wasmtime::Config::new();
+        wasm_config.async_support(true);
+        wasm_config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
+
+        let engine = Engine::new(&wasm_config)?;
+
+        // compiling the module is CPU bound, which would block the runtime
+        let module = {
+            let (engine, conf) = (engine.clone(), conf.clone());
+            tokio::task::spawn_blocking(move || signature::load_module(&engine, &conf)).await??
+        };
+
+        let version = H2O::module_version(conf, &module)?;
+        let v0_conf = match &version {
+            Version::V0(Some(v0_conf)) => Arc::clone(v0_conf),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Only v0 WATMs are supported in async mode for now"
+                ))
+            }
+        };
+
+        let (wasi_ctx, stdio) = stdio::build_wasi_ctx_with_sched(&conf.stdio, sched::sched_ctx())?;
+        let stats = ConnStats::new(&conf.filepath);
+
+        let mut store = Store::new(&engine, Host::default());
+        store.data_mut().preview1_ctx = Some(wasi_ctx);
+        store.data_mut().stats = stats.clone();
+        store.data_mut().socket_options = conf.socket_options.clone();
+        store.data_mut().stdio = stdio.clone();
+
+        let mut linker: Linker<Host> = Linker::new(&engine);
+        wasmtime_wasi::tokio::add_to_linker(&mut linker, |h: &mut Host| {
+            h.preview1_ctx.as_mut().unwrap()
+        })?;
+
+        Self::export_v0_funcs(&mut linker, v0_conf)?;
+
+        // export functions -- version independent
+        version_common::funcs::export_config(&mut linker, conf.config_wasm.clone())?;
+        version_common::funcs::export_log(&mut linker, conf.debug)?;
+
+        let instance = linker.instantiate_async(&mut store, &module).await?;
+
+        let mut core = AsyncH2O {
+            version,
+            engine,
+            instance,
+            store,
+            module,
+            instance_guard: metrics::ActiveInstance::new(),
+            stats,
+            stdio,
+        };
+
+        // call _start function explicitly if there is one exported from the WATM module
+        if core.instance.get_func(&mut core.store, "_start").is_some() {
+            core.call("_start", &[]).await?;
+        }
+
+        core._init().await?;
+        core._process_config(conf).await?;
+
+        metrics::registry().observe_instantiation(started.elapsed());
+        Ok(core)
+    }
+
+    fn export_v0_funcs(
+        linker: &mut Linker<Host>,
+        v0_conf: Arc<std::sync::Mutex<V0Config>>,
+    ) -> Result<(), anyhow::Error> {
+        v0::funcs::export_tcp_connect_async(linker, Arc::clone(&v0_conf))?;
+        v0::funcs::export_accept_async(linker, Arc::clone(&v0_conf))?;
+        v0::funcs::export_defer(linker, v0_conf)?;
+        Ok(())
+    }
+
+    /// Call the function `name` exported by the WATM with `params`, returning its results
+    pub async fn call(&mut self, name: &str, params: &[Val]) -> Result<Vec<Val>, anyhow::Error> {
+        let func = match self.instance.get_func(&mut self.store, name) {
+            Some(func) => func,
+            None => {
+                return Err(anyhow::Error::msg(format!(
+                    "{} function not found in WASM",
+                    name
+                )))
+            }
+        };
+
+        let mut res = vec![Val::I32(0); func.ty(&self.store).results().len()];
+        match func.call_async(&mut self.store, params, &mut res).await {
+            Ok(_) => Ok(res),
+            Err(e) => Err(self.store.data().guest_error(name, e)),
+        }
+    }
+
+    /// Push `file` into the WasiCtx of the WATM, returning its fd
+    pub fn push_file(&mut self, file: Box<dyn WasiFile>) -> Result<u32, anyhow::Error> {
+        let ctx = self
+            .store
+            .data_mut()
+            .preview1_ctx
+            .as_mut()
+            .context("Failed to retrieve preview1_ctx from Host")?;
+
+        Ok(ctx.push_file(file, FileAccessMode::all())?)
+    }
+
+    /// The V0Config of the instance, for the listener to be created in
+    pub fn v0_config(&self) -> Result<Arc<std::sync::Mutex<V0Config>>, anyhow::Error> {
+        match &self.version {
+            Version::V0(Some(v0_conf)) => Ok(Arc::clone(v0_conf)),
+            _ => Err(anyhow::anyhow!("v0_conf is None")),
+        }
+    }
+
+    /// This function is called when the host wants to call _init() in WASM
+    async fn _init(&mut self) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERCore async calling _init from WASM...");
+
+        self.call(INIT_FN, &[]).await?;
+        Ok(())
+    }
+
+    /// Push the config file into the WATM with its config function, skipped when there is none (as with V0)
+    async fn _process_config(&mut self, config: &WATERConfig) -> Result<(), anyhow::Error> {
+        if self.instance.get_func(&mut self.store, CONFIG_FN).is_none() {
+            info!("config function not found -- skipping");
+            return Ok(());
+        }
+
+        let config_file = core::open_config_file(config)?;
+        let config_fd = self.push_file(Box::new(wasmtime_wasi::tokio::File::from_cap_std(
+            config_file,
+        )))?;
+
+        self.call(CONFIG_FN, &[Val::I32(config_fd as i32)]).await?;
+        Ok(())
+    }
+}
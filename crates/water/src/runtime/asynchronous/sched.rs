@@ -0,0 +1,142 @@
+//! The scheduler of the WATMs in async mode, doing `poll_oneoff` by awaiting the readiness of the polled fds with Tokio.
+//!
+//! Different from the one of wasi-tokio, every fd is registered only once with all the interests it is polled for,
+//! since the WATMs built with Tokio (mio) are subscribing to both reading and writing of the same fd in one poll.
+
+use std::os::fd::{AsRawFd, RawFd};
+
+use futures::FutureExt;
+use tokio::io::{unix::AsyncFd, Interest, Ready};
+use wasi_common::{
+    sched::{Duration, Poll, RwEventFlags, Subscription, WasiSched},
+    Error, ErrorExt,
+};
+
+/// The scheduler to build the WasiCtx of the WATMs in async mode with
+pub fn sched_ctx() -> Box<dyn WasiSched> {
+    Box::new(TokioSched)
+}
+
+struct TokioSched;
+
+#[async_trait::async_trait]
+impl WasiSched for TokioSched {
+    async fn poll_oneoff<'a>(&self, poll: &mut Poll<'a>) -> Result<(), Error> {
+        poll_oneoff(poll).await
+    }
+
+    async fn sched_yield(&self) -> Result<(), Error> {
+        tokio::task::yield_now().await;
+        Ok(())
+    }
+
+    async fn sleep(&self, duration: Duration) -> Result<(), Error> {
+        tokio::time::sleep(duration).await;
+        Ok(())
+    }
+}
+
+async fn poll_oneoff<'a>(poll: &mut Poll<'a>) -> Result<(), Error> {
+    if poll.is_empty() {
+        return Ok(());
+    }
+
+    // all the interests of every fd polled
+    let mut interests: Vec<(RawFd, Interest)> = Vec::new();
+    for s in poll.rw_subscriptions() {
+        let (fd, interest) = subscribed_fd(s)?;
+        match interests.iter_mut().find(|(polled, _)| *polled == fd) {
+            Some((_, polled_interest)) => *polled_interest = polled_interest.add(interest),
+            None => interests.push((fd, interest)),
+        }
+    }
+
+    let timeout = poll
+        .earliest_clock_deadline()
+        .map(|t| t.duration_until().unwrap_or(Duration::ZERO));
+
+    let ready = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, wait_ready(&interests)).await {
+            Ok(ready) => ready?,
+            // the clock subscription has its result once the deadline has passed
+            Err(_) => return Ok(()),
+        },
+        None => wait_ready(&interests).await?,
+    };
+
+    for s in poll.rw_subscriptions() {
+        let (fd, interest) = subscribed_fd(s)?;
+        let ready = match ready.iter().find(|(ready_fd, _)| *ready_fd == fd) {
+            Some((_, ready)) => *ready,
+            None => continue,
+        };
+
+        let sub = match s {
+            Subscription::Read(sub) if ready.is_readable() || ready.is_read_closed() => sub,
+            Subscription::Write(sub) if ready.is_writable() || ready.is_write_closed() => sub,
+            _ => continue,
+        };
+
+        let nbytes = match interest.is_readable() {
+            true => std::cmp::max(sub.file.num_ready_bytes()?, 1),
+            false => 0,
+        };
+
+        if ready.is_error() {
+            sub.error(Error::io());
+        } else if ready.is_read_closed() || ready.is_write_closed() {
+            sub.complete(nbytes, RwEventFlags::HANGUP);
+        } else {
+            sub.complete(nbytes, RwEventFlags::empty());
+        }
+    }
+
+    Ok(())
+}
+
+fn subscribed_fd(s: &Subscription<'_>) -> Result<(RawFd, Interest), Error> {
+    let (file, interest) = match s {
+        Subscription::Read(sub) => (sub.file, Interest::READABLE),
+        Subscription::Write(sub) => (sub.file, Interest::WRITABLE),
+        Subscription::MonotonicClock(_) => unreachable!(),
+    };
+
+    let fd = file
+        .pollable()
+        .ok_or(Error::invalid_argument().context("file is not pollable"))?;
+    Ok((fd.as_raw_fd(), interest))
+}
+
+/// Wait for any of the fds to be ready, returning all the ones ready by then with their readiness
+async fn wait_ready(interests: &[(RawFd, Interest)]) -> Result<Vec<(RawFd, Ready)>, Error> {
+    let mut fds = Vec::with_capacity(interests.len());
+    for (fd, interest) in interests {
+        // SAFETY: the fds are owned by the files subscribed in `poll`, which outlive the AsyncFds dropped in here
+        match unsafe { AsyncFd::register_with_interest(*fd, *interest) } {
+            Ok(async_fd) => fds.push((async_fd, *interest)),
+            Err(e) => match e.into_parts().1 {
+                // not supported by epoll since it is always ready (e.g. a regular file)
+                e if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    return Ok(vec![(*fd, Ready::ALL)]);
+                }
+                e => return Err(e.into()),
+            },
+        }
+    }
+
+    // the readiness is not cleared, it is checked again for all the fds below
+    let _ = futures::future::select_all(
+        fds.iter()
+            .map(|(async_fd, interest)| Box::pin(async_fd.ready(*interest))),
+    )
+    .await
+    .0?;
+
+    let mut ready = Vec::new();
+    for (async_fd, interest) in &fds {
+        if let Some(guard) = async_fd.ready(*interest).now_or_never() {
+            ready.push((*async_fd.get_ref(), guard?.ready()));
+        }
+    }
+    Ok(ready)
+}
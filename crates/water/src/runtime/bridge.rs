@@ -0,0 +1,179 @@
+//! Bridging a connection of the Host (e.g. one accepted from a local application) with a WATM,
+//! relaying the bytes in both directions at once until the connection is closed.
+//!
+//! A v0 WATM is bridged through its pipe while its worker is running. A v1 WATM has no worker, its
+//! `_water_read` / `_water_write` are called from the bridge whenever the network / the connection is readable,
+//! so the store is never held waiting for one side while the other has something to send.
+
+use std::{
+    net::{Shutdown, TcpStream},
+    os::fd::AsRawFd,
+};
+
+use crate::runtime::{transport::WATERTransportTrait, watm_socket::WatmSocket, *};
+
+/// Size of the buffer used for each direction
+const BRIDGE_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Bridge `conn` with a connected / accepted v0 or v1 `transport`, see [`bridge`] and [`bridge_v1`]
+pub fn bridge_transport<T: WATERTransportTrait + ?Sized>(
+    transport: &mut T,
+    conn: TcpStream,
+    stats: &ConnStats,
+) -> Result<(), anyhow::Error> {
+    match transport.get_core().version {
+        Version::V0(_) => {
+            let caller_io = transport
+                .get_caller_io()
+                .as_ref()
+                .context("[HOST] Not connected / accepted yet")?;
+            bridge(caller_io, conn, stats)
+        }
+        Version::V1 => {
+            // the connection dialed by the Host or accepted by the WATM
+            let network = transport
+                .get_core()
+                .interrupt
+                .network()
+                .context("[HOST] Only a connected / accepted v1 WATM can be bridged")?;
+            bridge_v1(transport, &network, conn, stats)
+        }
+        _ => Err(anyhow::anyhow!(
+            "[HOST] Only v0 and v1 WATMs can be bridged"
+        )),
+    }
+}
+
+/// Relay between `conn` and `caller_io` until both directions reach EOF, the EOF of one side is passed to the other
+/// as a half-close. Bytes are counted in `stats` as the caller side of the connection.
+pub fn bridge(
+    caller_io: &PipeStream,
+    conn: TcpStream,
+    stats: &ConnStats,
+) -> Result<(), anyhow::Error> {
+    let mut pipe_reader = caller_io.try_clone()?;
+    let mut pipe_writer = caller_io.try_clone()?;
+    let mut conn_reader = conn.try_clone()?;
+    let mut conn_writer = conn;
+
+    // conn -> WATM on another thread, WATM -> conn on this one
+    let upload_stats = stats.clone();
+    let upload = std::thread::spawn(move || -> std::io::Result<()> {
+        let mut buf = vec![0u8; BRIDGE_BUFFER_SIZE];
+        loop {
+            let n = conn_reader.read(&mut buf)?;
+            if n == 0 {
+                pipe_writer.shutdown_write();
+                return Ok(());
+            }
+            pipe_writer.write_all(&buf[..n])?;
+            upload_stats.record_caller_write(n as u64);
+        }
+    });
+
+    let download = (|| -> std::io::Result<()> {
+        let mut buf = vec![0u8; BRIDGE_BUFFER_SIZE];
+        loop {
+            let n = pipe_reader.read(&mut buf)?;
+            if n == 0 {
+                return conn_writer.shutdown(Shutdown::Write);
+            }
+            conn_writer.write_all(&buf[..n])?;
+            stats.record_caller_read(n as u64);
+        }
+    })();
+
+    // the WATM is gone, nothing the application sends can be delivered anymore
+    if download.is_err() {
+        let _ = conn_writer.shutdown(Shutdown::Both);
+    }
+
+    let upload = upload
+        .join()
+        .map_err(|_| anyhow::anyhow!("[HOST] bridge upload thread panicked"))?;
+
+    download.context("[HOST] bridge failed relaying from the WATM")?;
+    upload.context("[HOST] bridge failed relaying to the WATM")?;
+    Ok(())
+}
+
+/// Relay between `conn` and a v1 `transport` whose connection to the network is `network`, until the WATM reaches EOF.
+/// v1 has no way to pass a half-close to the WATM, the EOF of `conn` is passed to the network by shutting down the
+/// writing side of `network` instead, and what the WATM still has to read is relayed to `conn` until its EOF.
+/// Bytes are counted in `stats` as the caller side of the connection.
+pub fn bridge_v1<T: WATERTransportTrait + ?Sized>(
+    transport: &mut T,
+    network: &WatmSocket,
+    mut conn: TcpStream,
+    stats: &ConnStats,
+) -> Result<(), anyhow::Error> {
+    let mut conn_buf = vec![0u8; BRIDGE_BUFFER_SIZE];
+    let mut watm_buf = Vec::new();
+    let mut conn_eof = false;
+
+    loop {
+        let (conn_ready, network_ready) = wait_readable((!conn_eof).then_some(&conn), network)?;
+
+        if conn_ready {
+            let n = conn
+                .read(&mut conn_buf)
+                .context("[HOST] bridge failed reading the connection")?;
+            if n == 0 {
+                network.shutdown(Shutdown::Write);
+                conn_eof = true;
+                continue;
+            }
+            transport.write(&conn_buf[..n])?;
+            stats.record_caller_write(n as u64);
+        }
+
+        if network_ready {
+            let n = transport.read(&mut watm_buf)?;
+            if n == 0 {
+                let _ = conn.shutdown(Shutdown::Write);
+                return Ok(());
+            }
+            conn.write_all(&watm_buf)
+                .context("[HOST] bridge failed writing the connection")?;
+            stats.record_caller_read(n as u64);
+        }
+    }
+}
+
+/// Block until `conn` (unless `None`) or `network` is readable (or closed), returns the readiness of each.
+/// `network` closed by the WATM is ready, for its `_water_read` to report it.
+fn wait_readable(
+    conn: Option<&TcpStream>,
+    network: &WatmSocket,
+) -> Result<(bool, bool), anyhow::Error> {
+    // only polled while the WATM isn't running, it can't close the fd meanwhile
+    let network_fd = match network.raw_fd() {
+        Some(fd) => fd,
+        None => return Ok((false, true)),
+    };
+    let mut fds = [
+        libc::pollfd {
+            // a negative fd is ignored by poll
+            fd: conn.map_or(-1, |conn| conn.as_raw_fd()),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: network_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    loop {
+        let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if res >= 0 {
+            return Ok((fds[0].revents != 0, fds[1].revents != 0));
+        }
+
+        let e = std::io::Error::last_os_error();
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(anyhow::Error::from(e).context("[HOST] bridge failed polling"));
+        }
+    }
+}
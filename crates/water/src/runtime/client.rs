@@ -4,9 +4,15 @@
 //!
 //! `WATERClientType` is an enum type that holds different types of clients
 
-use crate::runtime::*;
+use crate::runtime::{
+    core::{CompiledWatm, WrappedConn},
+    *,
+};
+use incoming::{Incoming, IncomingStream};
 use listener::WATERListenerTrait;
 use relay::WATERRelayTrait;
+use runner::RunnerHandle;
+use stats::StatsSnapshot;
 use stream::WATERStreamTrait;
 
 /// `WATERClientType` Definition: A enum type to hold different types of clients
@@ -21,7 +27,7 @@ pub enum WATERClientType {
     Relay(Box<dyn WATERRelayTrait>),
 
     /// `Runner`: create 1 WATM instance with the given `.wasm` binary to run the `entry_fn`
-    Runner(WATERRunner<Host>), // This is a customized runner -- not like any stream; currently can run v1 relay (shadowsocks client)
+    Runner(Box<WATERRunner<Host>>), // This is a customized runner -- not like any stream; currently can run v1 relay (shadowsocks client)
 }
 
 /// `WATERClient` is used as the object for entering and managing the WASM runtime
@@ -30,19 +36,59 @@ pub struct WATERClient {
 
     pub config: WATERConfig,
     pub stream: WATERClientType,
+
+    /// the WATM of `config` compiled (which tells the V2 ones apart), the instances of the next connections are created from it
+    watm: CompiledWatm,
+
+    /// the config (and its WATM compiled) used before the last `swap_module`, to roll back to if the new module fails later
+    fallback: Option<(WATERConfig, CompiledWatm)>,
+
+    /// traffic statistics of the connection handled by this client
+    stats: ConnStats,
+
+    /// captured stdout & stderr of the WATM instance, only with `StdioMode::Capture`
+    stdio: Option<CapturedStdio>,
 }
 
 impl WATERClient {
     /// `new` is the constructor of `WATERClient`
     /// it checks the client type and the version to create the corresponding `WATERClientType`
     pub fn new(conf: WATERConfig) -> Result<Self, anyhow::Error> {
+        let watm = CompiledWatm::compile_owned(&conf)?;
+        Self::instantiate(conf, &watm)
+    }
+
+    /// `from_compiled` creates the client with a new instance of `watm`, the WATM of `conf` compiled once for many clients
+    /// (e.g. a dialer per connection of a proxy) instead of compiling it for each of them.
+    ///
+    /// A Runner is interrupted with the epoch of an engine of its own, so it can't be created from a shared `watm`.
+    pub fn from_compiled(conf: WATERConfig, watm: &CompiledWatm) -> Result<Self, anyhow::Error> {
+        if conf.client_type == WaterBinType::Runner {
+            return Err(anyhow::anyhow!(
+                "[HOST] A Runner can't be created from a shared compiled WATM"
+            ));
+        }
+
+        Self::instantiate(conf, watm)
+    }
+
+    /// Create the client with a new instance of `watm`, by the client type and the version
+    fn instantiate(conf: WATERConfig, watm: &CompiledWatm) -> Result<Self, anyhow::Error> {
         info!("[HOST] WATERClient initializing ...");
 
-        let mut core = H2O::init_core(&conf)?;
+        if watm.is_component() {
+            return Self::new_v2(conf, watm);
+        }
+
+        let mut core = H2O::instantiate(&conf, watm)?;
         core._prepare(&conf)?;
 
+        let stats = core.stats.clone();
+        let stdio = core.stdio.clone();
+
         let water = match conf.client_type {
-            WaterBinType::Dial => {
+            // `Wrap` is a dialer whose connection is provided by the application with `wrap`
+            WaterBinType::Dial | WaterBinType::Wrap => {
                 let stream = match core.version {
                     Version::V0(_) => Box::new(v0::stream::WATERStream::init(&conf, core)?)
                         as Box<dyn WATERStreamTrait>,
@@ -82,7 +128,7 @@ impl WATERClient {
             }
             WaterBinType::Runner => {
                 let runner = WATERRunner::init(&conf, core)?;
-                WATERClientType::Runner(runner)
+                WATERClientType::Runner(Box::new(runner))
             }
             _ => {
                 return Err(anyhow::anyhow!("Invalid client type"));
@@ -91,58 +137,341 @@ impl WATERClient {
 
         Ok(WATERClient {
             config: conf,
+            watm: watm.clone(),
+            fallback: None,
             debug: false,
             stream: water,
+            stats,
+            stdio,
+        })
+    }
+
+    /// Create the client for a V2 WATM, which is a component instead of a core module
+    fn new_v2(conf: WATERConfig, watm: &CompiledWatm) -> Result<Self, anyhow::Error> {
+        let core = v2::V2Core::instantiate(&conf, watm)?;
+        let stats = core.stats.clone();
+
+        let water = match conf.client_type {
+            WaterBinType::Dial => {
+                WATERClientType::Dialer(Box::new(v2::stream::WATERStream::init(&conf, core)?))
+            }
+            WaterBinType::Listen => {
+                WATERClientType::Listener(Box::new(v2::listener::WATERListener::init(&conf, core)?))
+            }
+            WaterBinType::Relay => {
+                WATERClientType::Relay(Box::new(v2::relay::WATERRelay::init(&conf, core)?))
+            }
+            _ => {
+                return Err(anyhow::anyhow!("Invalid client type for V2"));
+            }
+        };
+
+        Ok(WATERClient {
+            config: conf,
+            watm: watm.clone(),
+            fallback: None,
+            debug: false,
+            stream: water,
+            stats,
+            stdio: None,
         })
     }
 
     /// keep_listen is the function that is called when user wants to accept a newly income connection,
     /// it creates a new WASM instance and migrate the previous listener to it. -- v0_plus listener and relay for now.
+    ///
+    /// If the instance can't be created from the current module (e.g. the stdio file of a hot-swapped one can't be opened),
+    /// it falls back to the module used before the last `swap_module`.
     pub fn keep_listen(&mut self) -> Result<Self, anyhow::Error> {
         info!("[HOST] WATERClient keep listening...",);
 
-        let water = match &mut self.stream {
-            WATERClientType::Listener(ref mut listener) => WATERClientType::Listener(Box::new(
-                v0::listener::WATERListener::migrate_listener(&self.config, listener.get_core())?,
-            )
-                as Box<dyn WATERListenerTrait>),
-            WATERClientType::Relay(ref mut relay) => WATERClientType::Relay(Box::new(
-                v0::relay::WATERRelay::migrate_listener(&self.config, relay.get_core())?,
-            )
-                as Box<dyn WATERRelayTrait>),
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "[HOST] This client is neither a Listener nor a Relay"
-                ));
-            }
+        let (water, stats, stdio) = match self.migrate(&self.config.clone(), &self.watm.clone()) {
+            Ok(migrated) => migrated,
+            Err(e) => match self.fallback.take() {
+                Some((fallback, fallback_watm)) => {
+                    info!(
+                        "[HOST] WATERClient failed to migrate to {}, rolling back to {}: {}",
+                        self.config.filepath, fallback.filepath, e
+                    );
+                    let migrated = self.migrate(&fallback, &fallback_watm)?;
+                    self.config = fallback;
+                    self.watm = fallback_watm;
+                    migrated
+                }
+                None => return Err(e),
+            },
         };
 
         Ok(WATERClient {
             config: self.config.clone(),
+            watm: self.watm.clone(),
+            fallback: self.fallback.clone(),
             debug: self.debug,
             stream: water,
+            stats,
+            stdio,
         })
     }
 
+    /// `swap_module` hot-swaps the WATM module (and its config) of a v0 `Listener` or `Relay` without stopping listening:
+    /// the connections already accepted keep running on the old module, and the new ones will use the new module.
+    ///
+    /// The new module is validated by migrating the listener to it first, if that fails the client keeps
+    /// using the old module and the error is returned.
+    pub fn swap_module(&mut self, conf: WATERConfig) -> Result<(), anyhow::Error> {
+        info!(
+            "[HOST] WATERClient swapping module to {} ...",
+            conf.filepath
+        );
+
+        if conf.client_type != self.config.client_type {
+            return Err(anyhow::anyhow!(
+                "[HOST] Can't swap to a module for client type {:?}",
+                conf.client_type
+            ));
+        }
+
+        let (water, stats, stdio) = match CompiledWatm::compile_owned(&conf)
+            .and_then(|watm| Ok((self.migrate(&conf, &watm)?, watm)))
+        {
+            Ok((migrated, watm)) => {
+                self.fallback = Some((
+                    std::mem::replace(&mut self.config, conf),
+                    std::mem::replace(&mut self.watm, watm),
+                ));
+                migrated
+            }
+            Err(e) => {
+                info!(
+                    "[HOST] WATERClient failed to validate {}, keep using {}: {}",
+                    conf.filepath, self.config.filepath, e
+                );
+                return Err(e.context(format!("Failed to validate WATM module {}", conf.filepath)));
+            }
+        };
+
+        // the instance hasn't handled any connection yet, so it can be replaced by the validated one right away
+        if self.stats.snapshot().started_at.is_none() {
+            self.stream = water;
+            self.stats = stats;
+            self.stdio = stdio;
+        }
+
+        Ok(())
+    }
+
+    /// Create a new WATM instance of `watm`, the module in `conf` compiled, with the listener migrated to it
+    fn migrate(
+        &mut self,
+        conf: &WATERConfig,
+        watm: &CompiledWatm,
+    ) -> Result<(WATERClientType, ConnStats, Option<CapturedStdio>), anyhow::Error> {
+        // the listener of a V2 WATM is owned by the WATM itself, there is no fd to migrate from or to
+        if self.watm.is_component() || watm.is_component() {
+            return Err(anyhow::anyhow!(
+                "[HOST] Can't migrate the listener to / from a V2 WATM"
+            ));
+        }
+
+        match &mut self.stream {
+            WATERClientType::Listener(ref mut listener) => {
+                let listener =
+                    v0::listener::WATERListener::migrate_listener(conf, watm, listener.get_core())?;
+                let stats = listener.core.stats.clone();
+                let stdio = listener.core.stdio.clone();
+                Ok((
+                    WATERClientType::Listener(Box::new(listener) as Box<dyn WATERListenerTrait>),
+                    stats,
+                    stdio,
+                ))
+            }
+            WATERClientType::Relay(ref mut relay) => {
+                let relay = v0::relay::WATERRelay::migrate_listener(conf, watm, relay.get_core())?;
+                let stats = relay.core.stats.clone();
+                let stdio = relay.core.stdio.clone();
+                Ok((
+                    WATERClientType::Relay(Box::new(relay) as Box<dyn WATERRelayTrait>),
+                    stats,
+                    stdio,
+                ))
+            }
+            _ => Err(anyhow::anyhow!(
+                "[HOST] This client is neither a Listener nor a Relay"
+            )),
+        }
+    }
+
     pub fn set_debug(&mut self, debug: bool) {
         self.debug = debug;
     }
 
+    /// `stats` returns a snapshot of the traffic statistics of the connection handled by this client
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    /// `local_addr` returns the address the network side is bound to: the listener of a Listener / Relay
+    /// (with the port picked by the OS when the config asks for port 0), or the connection of a Dialer
+    pub fn local_addr(&self) -> Result<std::net::SocketAddr, anyhow::Error> {
+        self.stats
+            .local_addr()
+            .context("[HOST] WATERClient is not bound to any address yet")
+    }
+
+    /// `peer_addr` returns the address of the peer of the connection dialed or accepted on the network side,
+    /// the last one accepted for a v1 Listener handling several connections
+    pub fn peer_addr(&self) -> Result<std::net::SocketAddr, anyhow::Error> {
+        self.stats
+            .peer_addr()
+            .context("[HOST] WATERClient is not connected yet")
+    }
+
+    /// `try_clone_listener` returns a handle to the listener of a v0 `Listener` / `Relay` after `listen()`,
+    /// shared with the instances migrated by `keep_listen` (e.g. to shut it down from another thread)
+    pub fn try_clone_listener(&mut self) -> Result<std::net::TcpListener, anyhow::Error> {
+        let core = match &mut self.stream {
+            WATERClientType::Listener(listener) => listener.get_core(),
+            WATERClientType::Relay(relay) => relay.get_core(),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "[HOST] This client is neither a Listener nor a Relay"
+                ))
+            }
+        };
+
+        match &core.version {
+            Version::V0(Some(v0_conf)) => {
+                let v0_conf = v0_conf
+                    .lock()
+                    .map_err(|e| anyhow::anyhow!("Failed to lock v0_conf: {}", e))?;
+                let listener = v0_conf.listener_socket()?.try_clone_to_owned()?;
+                Ok(std::net::TcpListener::from(listener))
+            }
+            _ => Err(anyhow::anyhow!(
+                "[HOST] Only the listener of a v0 WATM can be cloned"
+            )),
+        }
+    }
+
+    /// `captured_stdout` returns the last bytes written to stdout by the WATM, only with `StdioMode::Capture`
+    pub fn captured_stdout(&self) -> Option<Vec<u8>> {
+        self.stdio.as_ref().map(|stdio| stdio.stdout.contents())
+    }
+
+    /// `captured_stderr` returns the last bytes written to stderr by the WATM, only with `StdioMode::Capture`
+    pub fn captured_stderr(&self) -> Option<Vec<u8>> {
+        self.stdio.as_ref().map(|stdio| stdio.stderr.contents())
+    }
+
     /// `connect` is the function for `Dialer` to connect to a remote address
     pub fn connect(&mut self) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERClient connecting ...");
 
         match &mut self.stream {
             WATERClientType::Dialer(dialer) => {
-                dialer.connect(&self.config)?;
+                dialer
+                    .connect(&self.config)
+                    .inspect_err(|_| self.stats.record_error())?;
             }
             _ => {
                 return Err(anyhow::anyhow!("[HOST] This client is not a Dialer"));
             }
         }
+
+        self.stats.mark_started();
         Ok(())
     }
 
+    /// `connect_to` is the function for `Dialer` to connect to `addr` (address:port) instead of the remote address in the config,
+    /// so one configured WATM can be used for connections to any destination
+    pub fn connect_to(&mut self, addr: &str) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERClient connecting to {} ...", addr);
+
+        // the streams of a V2 WATM are owned by the WATM itself
+        if self.watm.is_component() {
+            return Err(anyhow::anyhow!(
+                "[HOST] Can't pick the destination of a V2 WATM"
+            ));
+        }
+
+        match &mut self.stream {
+            WATERClientType::Dialer(dialer) => {
+                let mut store = dialer
+                    .get_core()
+                    .store
+                    .lock()
+                    .map_err(|e| anyhow::Error::msg(format!("Failed to lock store: {}", e)))?;
+                store.data_mut().set_destination(addr)?;
+            }
+            _ => {
+                return Err(anyhow::anyhow!("[HOST] This client is not a Dialer"));
+            }
+        }
+
+        self.connect()
+    }
+
+    /// `wrap` is the function for `Dialer` (and `Wrap`) to run the WATM over `conn`, a connection the application
+    /// already has (from its own pool, inherited from systemd, ...), instead of dialing one.
+    /// The Host pushes `conn` into the WATM as the network side, and the transformed stream is then used as with `connect`.
+    pub fn wrap(&mut self, conn: std::net::TcpStream) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERClient wrapping {:?} ...", conn.peer_addr());
+        self.wrap_conn(WrappedConn::Tcp(conn))
+    }
+
+    /// `wrap` for any kind of socket the WATM can drive
+    fn wrap_conn(&mut self, conn: WrappedConn) -> Result<(), anyhow::Error> {
+        // the streams of a V2 WATM are owned by the WATM itself
+        if self.watm.is_component() {
+            return Err(anyhow::anyhow!(
+                "[HOST] Can't wrap a connection with a V2 WATM"
+            ));
+        }
+
+        match &mut self.stream {
+            WATERClientType::Dialer(dialer) => {
+                let mut store = dialer
+                    .get_core()
+                    .store
+                    .lock()
+                    .map_err(|e| anyhow::Error::msg(format!("Failed to lock store: {}", e)))?;
+                store.data_mut().wrapped = Some(Arc::new(conn));
+            }
+            _ => {
+                return Err(anyhow::anyhow!("[HOST] This client is not a Dialer"));
+            }
+        }
+
+        self.connect()
+    }
+
+    /// `wrap_io` is `wrap` for any duplex given as its `reader` and `writer` halves (a TLS session, a pipe, ...):
+    /// the WATM gets one end of a socket pair as the network side, the other end is copied to and from the duplex.
+    pub fn wrap_io<R, W>(&mut self, mut reader: R, mut writer: W) -> Result<(), anyhow::Error>
+    where
+        R: Read + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let (network, mut app_write) = std::os::unix::net::UnixStream::pair()?;
+        let mut app_read = app_write.try_clone()?;
+
+        std::thread::spawn(move || {
+            if let Err(e) = std::io::copy(&mut reader, &mut app_write) {
+                info!("[HOST] WATERClient wrapped reader failed: {}", e);
+            }
+            let _ = app_write.shutdown(std::net::Shutdown::Write);
+        });
+        std::thread::spawn(move || {
+            if let Err(e) = std::io::copy(&mut app_read, &mut writer).and_then(|_| writer.flush()) {
+                info!("[HOST] WATERClient wrapped writer failed: {}", e);
+            }
+        });
+
+        // the end of the socket pair is driven by the WATM as any connected socket
+        info!("[HOST] WATERClient wrapping a duplex ...");
+        self.wrap_conn(WrappedConn::Unix(network))
+    }
+
     /// `listen` is the function for `Listener` and `Relay` to create the Listener and listen on a local addr
     pub fn listen(&mut self) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERClient creating listener ...");
@@ -167,12 +496,16 @@ impl WATERClient {
 
         match &mut self.stream {
             WATERClientType::Relay(relay) => {
-                relay.associate(&self.config)?;
+                relay
+                    .associate(&self.config)
+                    .inspect_err(|_| self.stats.record_error())?;
             }
             _ => {
                 return Err(anyhow::anyhow!("[HOST] This client is not a Relay"));
             }
         }
+
+        self.stats.mark_started();
         Ok(())
     }
 
@@ -183,15 +516,89 @@ impl WATERClient {
 
         match &mut self.stream {
             WATERClientType::Listener(listener) => {
-                listener.accept(&self.config)?;
+                listener
+                    .accept(&self.config)
+                    .inspect_err(|_| self.stats.record_error())?;
             }
             _ => {
                 return Err(anyhow::anyhow!("[HOST] This client is not a Listener"));
             }
         }
+
+        self.stats.mark_started();
         Ok(())
     }
 
+    /// `accept_next` is the function for `Listener` to accept the next connection (after `listen()`) and return it
+    /// as a new client ready to read / write / tunnel, while this one keeps listening for the next ones.
+    ///
+    /// A v0 instance accepting is migrated to a new one (as `keep_listen` does) which keeps the listener,
+    /// a v1 connection is accepted by a new instance sharing the listener bound by `listen()`.
+    pub fn accept_next(&mut self) -> Result<Self, anyhow::Error> {
+        let is_v1 = match &mut self.stream {
+            WATERClientType::Listener(listener) => {
+                matches!(listener.get_core().version, Version::V1)
+            }
+            _ => {
+                return Err(anyhow::anyhow!("[HOST] This client is not a Listener"));
+            }
+        };
+
+        if is_v1 {
+            return self.accept_v1();
+        }
+
+        // the instance is used up even if the accept failed, the next one is accepted by a new one
+        let accepted = self.accept();
+        let next_water_client = self.keep_listen()?;
+        let water_client = std::mem::replace(self, next_water_client);
+        accepted.map(|_| water_client)
+    }
+
+    /// Accept the next connection of a v1 `Listener` with a new instance sharing the listener of this one
+    fn accept_v1(&mut self) -> Result<Self, anyhow::Error> {
+        let listener = match &mut self.stream {
+            WATERClientType::Listener(listener) => listener
+                .get_core()
+                .store
+                .lock()
+                .map_err(|e| anyhow::Error::msg(format!("Failed to lock store: {}", e)))?
+                .data()
+                .listener
+                .clone(),
+            _ => None,
+        };
+
+        // a new instance of the WATM compiled for this one
+        let mut water_client = WATERClient::instantiate(self.config.clone(), &self.watm)?;
+        water_client.debug = self.debug;
+        if let WATERClientType::Listener(accepting) = &mut water_client.stream {
+            // a shared listener is dup'ed when the WATM asks for it in `create_listen`
+            accepting
+                .get_core()
+                .store
+                .lock()
+                .map_err(|e| anyhow::Error::msg(format!("Failed to lock store: {}", e)))?
+                .data_mut()
+                .listener = listener;
+        }
+
+        water_client.accept()?;
+        Ok(water_client)
+    }
+
+    /// `incoming` returns an iterator over the connections accepted by a `Listener` after `listen()`,
+    /// each yielded as a new client by `accept_next`
+    pub fn incoming(&mut self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+
+    /// `into_incoming_stream` turns a `Listener` (after `listen()`) into an async `Stream` of the connections accepted,
+    /// the blocking accepts run on a thread of their own, one connection ahead of the consumer at most
+    pub fn into_incoming_stream(self) -> IncomingStream {
+        IncomingStream::spawn(self)
+    }
+
     /// `run_worker` is the function to run the entry_fn(a worker in WATM) in a separate thread and return the thread handle
     /// it will return a `JoinHandle` for the caller to manage the thread -- used by v0_plus
     pub fn run_worker(
@@ -214,7 +621,10 @@ impl WATERClient {
 
         match &mut self.stream {
             WATERClientType::Runner(runner) => {
-                runner.run(&self.config)?;
+                self.stats.mark_started();
+                let res = runner.run(&self.config);
+                self.stats.mark_ended();
+                res.inspect_err(|_| self.stats.record_error())?;
             }
             WATERClientType::Dialer(dialer) => {
                 dialer.run_entry_fn(&self.config)?;
@@ -229,7 +639,15 @@ impl WATERClient {
         Ok(())
     }
 
-    /// `cancel_with` is the function to set the cancel pipe for exiting later -- v0_plus
+    /// `runner_handle` returns the handle to stop a `Runner` from another thread while `execute` is running its entry_fn
+    pub fn runner_handle(&self) -> Result<RunnerHandle, anyhow::Error> {
+        match &self.stream {
+            WATERClientType::Runner(runner) => Ok(runner.handle()),
+            _ => Err(anyhow::anyhow!("This client is not a Runner")),
+        }
+    }
+
+    /// `cancel_with` is the function to set the cancel pipe for exiting later -- v0_plus, nothing to set up for v1 and Runner
     pub fn cancel_with(&mut self) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERClient cancel_with ...");
 
@@ -243,15 +661,14 @@ impl WATERClient {
             WATERClientType::Relay(relay) => {
                 relay.cancel_with(&self.config)?;
             }
-            _ => {
-                // for now this is only implemented for v0 dialer
-                return Err(anyhow::anyhow!("This client is not a v0 supported client"));
-            }
+            // a Runner is stopped with its handle, nothing to set up
+            WATERClientType::Runner(_) => {}
         }
         Ok(())
     }
 
-    /// `cancel` is the function to send thru the cancel_pipe and let the thread running the worker to exit -- v0_plus
+    /// `cancel` is the function to send thru the cancel_pipe and let the thread running the worker to exit -- v0_plus,
+    /// v1 instances are interrupted by the Host and their connection shut down, a Runner is stopped
     pub fn cancel(&mut self) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERClient canceling ...");
 
@@ -265,26 +682,88 @@ impl WATERClient {
             WATERClientType::Relay(relay) => {
                 relay.cancel(&self.config)?;
             }
-            _ => {
-                // for now this is only implemented for v0 dialer
-                return Err(anyhow::anyhow!("This client is not a v0 Dialer"));
+            WATERClientType::Runner(runner) => {
+                runner.handle().stop();
             }
         }
+
+        self.stats.mark_ended();
         Ok(())
     }
 
+    /// `bridge` relays the bytes between `conn` and the WATM in both directions until the connection is closed,
+    /// for a v0 `Dialer` or `Listener` whose worker is running (see `run_worker`), or a connected v1 `Dialer`
+    pub fn bridge(&mut self, conn: std::net::TcpStream) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERClient bridging ...");
+
+        // the streams of a V2 WATM are owned by the WATM itself
+        if self.watm.is_component() {
+            return Err(anyhow::anyhow!("[HOST] Can't bridge a V2 WATM"));
+        }
+
+        match &mut self.stream {
+            WATERClientType::Dialer(dialer) => {
+                bridge::bridge_transport(dialer.as_mut(), conn, &self.stats)
+            }
+            WATERClientType::Listener(listener) => {
+                bridge::bridge_transport(listener.as_mut(), conn, &self.stats)
+            }
+            _ => Err(anyhow::anyhow!(
+                "[HOST] Only a Dialer or Listener can be bridged"
+            )),
+        }
+        .inspect_err(|_| self.stats.record_error())
+    }
+
+    /// `tunnel` serves `conn` with a connected `Dialer` / accepted `Listener` until the connection is closed,
+    /// running the worker of a v0 WATM (and stopping it at the end) around the `bridge`
+    pub fn tunnel(&mut self, conn: std::net::TcpStream) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERClient tunneling ...");
+
+        let is_v0 = match &mut self.stream {
+            WATERClientType::Dialer(dialer) => {
+                matches!(dialer.get_core().version, Version::V0(_))
+            }
+            WATERClientType::Listener(listener) => {
+                matches!(listener.get_core().version, Version::V0(_))
+            }
+            _ => false,
+        };
+        if !is_v0 {
+            return self.bridge(conn);
+        }
+
+        self.cancel_with()?;
+        let handle_water = self.run_worker()?;
+
+        let bridged = self.bridge(conn);
+
+        // the worker may still be waiting on the side that is not closed
+        if !handle_water.is_finished() {
+            let _ = self.cancel();
+        }
+        match handle_water.join() {
+            Ok(res) => res.context("Running _water_worker ERROR")?,
+            Err(_) => return Err(anyhow::anyhow!("Failed to join _water_worker thread")),
+        }
+
+        bridged
+    }
+
     /// `read` is the function to read from the stream
     pub fn read(&mut self, buf: &mut Vec<u8>) -> Result<i64, anyhow::Error> {
         info!("[HOST] WATERClient reading ...");
 
         let read_bytes = match &mut self.stream {
-            WATERClientType::Dialer(dialer) => dialer.read(buf)?,
-            WATERClientType::Listener(listener) => listener.read(buf)?,
+            WATERClientType::Dialer(dialer) => dialer.read(buf),
+            WATERClientType::Listener(listener) => listener.read(buf),
             _ => {
                 return Err(anyhow::anyhow!("This client is not supporting read"));
             }
-        };
+        }
+        .inspect_err(|_| self.stats.record_error())?;
 
+        self.stats.record_caller_read(read_bytes as u64);
         Ok(read_bytes)
     }
 
@@ -293,16 +772,15 @@ impl WATERClient {
         info!("[HOST] WATERClient writing ...");
 
         match &mut self.stream {
-            WATERClientType::Dialer(dialer) => {
-                dialer.write(buf)?;
-            }
-            WATERClientType::Listener(listener) => {
-                listener.write(buf)?;
-            }
+            WATERClientType::Dialer(dialer) => dialer.write(buf),
+            WATERClientType::Listener(listener) => listener.write(buf),
             _ => {
                 return Err(anyhow::anyhow!("This client is not supporting write"));
             }
         }
+        .inspect_err(|_| self.stats.record_error())?;
+
+        self.stats.record_caller_write(buf.len() as u64);
         Ok(())
     }
 }
@@ -1,18 +1,127 @@
 //! This is the core of the runtime, which is responsible for loading the WASM module and
 //! initializing the runtime. It also provides the interface for the host to interact with the runtime.
 
-use std::sync::Mutex;
+use std::{sync::Mutex, time::Instant};
 
 use crate::runtime::*;
 
-/// Host is storing the WasiCtx that we are using, and for the later features will also support the WasiThreadsCtx
+/// Host is storing the WasiCtx that we are using, and the WasiThreadsCtx with the `multithread` feature
 #[derive(Default, Clone)]
 pub struct Host {
     pub preview1_ctx: Option<wasmtime_wasi::WasiCtx>,
+
+    /// traffic statistics of the connection handled by this WATM instance, shared with the H2O core
+    pub stats: ConnStats,
+
+    /// captured stdout & stderr of this WATM instance, only with `StdioMode::Capture`
+    pub stdio: Option<CapturedStdio>,
+
+    /// the (address, port) to dial instead of the remote in the config, set by `WATERClient::connect_to`
+    pub destination: Option<(String, u16)>,
+
+    /// the connection provided by `WATERClient::wrap` / `wrap_io`, pushed into the WATM by the dial functions instead of dialing
+    pub wrapped: Option<Arc<WrappedConn>>,
+
+    /// the listener bound by the v1 `WATERListener::listen`, pushed into the WATM by `create_listen` instead of binding
+    pub listener: Option<Arc<std::net::TcpListener>>,
+
+    /// options of the sockets created by the Host exported functions of v1
+    pub socket_options: crate::config::SocketOptions,
+
+    /// interrupt of this WATM instance, checked by its scheduler
+    pub interrupt: Interrupt,
+
     #[cfg(feature = "multithread")]
     pub wasi_threads: Option<Arc<WasiThreadsCtx<Host>>>,
 }
 
+impl Host {
+    /// Take the connection provided by `WATERClient::wrap` if any, it is only handed to the WATM once
+    pub fn take_wrapped(&mut self) -> Result<Option<WrappedConn>, anyhow::Error> {
+        take_socket(&mut self.wrapped, WrappedConn::try_clone)
+    }
+
+    /// Dial `addr` (address:port, an IPv6 address in brackets) instead of the remote in the config
+    pub fn set_destination(&mut self, addr: &str) -> Result<(), anyhow::Error> {
+        let invalid =
+            || anyhow::anyhow!("[HOST] Invalid destination {}, expected address:port", addr);
+
+        let (host, port) = addr.rsplit_once(':').ok_or_else(invalid)?;
+        let port = port.parse::<u16>().map_err(|_| invalid())?;
+        let unbracketed_v6 = host.contains(':') && !(host.starts_with('[') && host.ends_with(']'));
+        if host.is_empty() || unbracketed_v6 {
+            return Err(invalid());
+        }
+
+        self.destination = Some((host.to_string(), port));
+        Ok(())
+    }
+
+    /// Take the listener bound by the Host if any, it is only handed to the WATM once
+    pub fn take_listener(&mut self) -> Result<Option<std::net::TcpListener>, anyhow::Error> {
+        take_socket(&mut self.listener, std::net::TcpListener::try_clone)
+    }
+
+    /// Turn the error of calling `function` in the WATM into the one returned by the Host,
+    /// a [`trap::GuestTrap`] when the WATM failed running, counted in the metrics and with the tail of the captured stderr attached.
+    pub fn guest_error(&self, function: &str, e: anyhow::Error) -> anyhow::Error {
+        trap::guest_error(function, e, self.stdio.as_ref())
+    }
+}
+
+/// A connection provided by the application, kept as the socket it is for the WATM to drive it as such
+pub enum WrappedConn {
+    Tcp(std::net::TcpStream),
+
+    /// e.g. the end of the socket pair of `WATERClient::wrap_io`
+    Unix(std::os::unix::net::UnixStream),
+}
+
+impl WrappedConn {
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        match self {
+            WrappedConn::Tcp(tcp) => tcp.try_clone().map(WrappedConn::Tcp),
+            WrappedConn::Unix(unix) => unix.try_clone().map(WrappedConn::Unix),
+        }
+    }
+
+    /// The socket file to push into the WATM
+    pub fn into_wasi_file(self) -> Box<dyn WasiFile> {
+        match self {
+            WrappedConn::Tcp(tcp) => {
+                wasmtime_wasi::net::Socket::from(TcpStream::from_std(tcp)).into()
+            }
+            WrappedConn::Unix(unix) => {
+                wasmtime_wasi::net::Socket::from(cap_std::os::unix::net::UnixStream::from_std(unix))
+                    .into()
+            }
+        }
+    }
+}
+
+impl std::os::fd::AsFd for WrappedConn {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        match self {
+            WrappedConn::Tcp(tcp) => tcp.as_fd(),
+            WrappedConn::Unix(unix) => unix.as_fd(),
+        }
+    }
+}
+
+/// Take the socket out of `slot`, the Host may have been cloned while holding it and then a dup of it is returned
+fn take_socket<T>(
+    slot: &mut Option<Arc<T>>,
+    try_clone: impl FnOnce(&T) -> std::io::Result<T>,
+) -> Result<Option<T>, anyhow::Error> {
+    match slot.take() {
+        Some(socket) => match Arc::try_unwrap(socket) {
+            Ok(socket) => Ok(Some(socket)),
+            Err(socket) => Ok(Some(try_clone(&socket)?)),
+        },
+        None => Ok(None),
+    }
+}
+
 /// This is the core of the runtime, which stores the necessary components for a WASM runtime and the version of the WATM module.
 #[derive(Clone)]
 pub struct H2O<Host> {
@@ -23,31 +132,139 @@ pub struct H2O<Host> {
     pub instance: Instance,
     pub store: Arc<Mutex<Store<Host>>>,
     pub module: Module,
+
+    /// keeps this instance counted in the active instances metric until the last clone is dropped
+    pub instance_guard: Arc<metrics::ActiveInstance>,
+
+    /// traffic statistics of this instance, accessible without locking the store
+    pub stats: ConnStats,
+
+    /// captured stdout & stderr of this instance, only with `StdioMode::Capture`
+    pub stdio: Option<CapturedStdio>,
+
+    /// interrupt of this instance, requested without locking the store (which the running function holds)
+    pub interrupt: Interrupt,
 }
 
-impl H2O<Host> {
-    /// generate a new H2O core instance
-    pub fn init_core(conf: &WATERConfig) -> Result<Self, anyhow::Error> {
-        info!("[HOST] WATERCore H2O initing...");
+/// A WATM compiled once, which the instances of many clients are created from (e.g. one per connection accepted)
+/// without compiling it again
+#[derive(Clone)]
+pub struct CompiledWatm {
+    pub engine: Engine,
+    pub compiled: Compiled,
 
-        let wasm_config = wasmtime::Config::new();
+    /// whether the instances are interrupted by bumping the epoch of the engine, only for the Runner owning it
+    epoch_interruption: bool,
+}
+
+/// The compiled WATM binary: a core module (v0 / v1) or a component (V2)
+#[derive(Clone)]
+pub enum Compiled {
+    Module(Module),
+    Component(component::Component),
+}
+
+impl CompiledWatm {
+    /// Compile the WATM at `conf.filepath` for the clients created with `WATERClient::from_compiled`
+    pub fn compile(conf: &WATERConfig) -> Result<Self, anyhow::Error> {
+        Self::compile_with(conf, false)
+    }
+
+    /// Compile the WATM at `conf.filepath` for a single client, a Runner is interrupted by bumping the epoch
+    /// of the engine as well; not with wasi-threads, which gives the threads spawned stores of their own
+    /// without an epoch deadline
+    pub(crate) fn compile_owned(conf: &WATERConfig) -> Result<Self, anyhow::Error> {
+        let epoch_interruption =
+            conf.client_type == WaterBinType::Runner && !cfg!(feature = "multithread");
+        Self::compile_with(conf, epoch_interruption)
+    }
+
+    fn compile_with(conf: &WATERConfig, epoch_interruption: bool) -> Result<Self, anyhow::Error> {
+        info!("[HOST] WATERCore compiling {} ...", conf.filepath);
+
+        let mut wasm_config = wasmtime::Config::new();
+        // symbolize the backtraces of the traps with the DWARF info of the WATM, see `trap::GuestTrap`
+        wasm_config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
+
+        if v2::is_component(&conf.filepath)? {
+            wasm_config.wasm_component_model(true);
+
+            let engine = Engine::new(&wasm_config)?;
+            let component = component::Component::new(&engine, signature::read_module(conf)?)?;
+            return Ok(CompiledWatm {
+                engine,
+                compiled: Compiled::Component(component),
+                epoch_interruption: false,
+            });
+        }
 
         #[cfg(feature = "multithread")]
         {
             wasm_config.wasm_threads(true);
         }
 
+        wasm_config.epoch_interruption(epoch_interruption);
+
         let engine = Engine::new(&wasm_config)?;
+        let module = signature::load_module(&engine, conf)?;
+
+        Ok(CompiledWatm {
+            engine,
+            compiled: Compiled::Module(module),
+            epoch_interruption,
+        })
+    }
 
-        let module = Module::from_file(&engine, &conf.filepath)?;
+    /// Whether the WATM is a component, which is the V2 WATM
+    pub fn is_component(&self) -> bool {
+        matches!(self.compiled, Compiled::Component(_))
+    }
+}
 
+impl H2O<Host> {
+    /// generate a new H2O core instance
+    pub fn init_core(conf: &WATERConfig) -> Result<Self, anyhow::Error> {
+        Self::instantiate(conf, &CompiledWatm::compile_owned(conf)?)
+    }
+
+    /// generate a new H2O core instance of the WATM compiled in `watm`
+    pub fn instantiate(conf: &WATERConfig, watm: &CompiledWatm) -> Result<Self, anyhow::Error> {
+        info!("[HOST] WATERCore H2O initing...");
+
+        let started = Instant::now();
+
+        let module = match &watm.compiled {
+            Compiled::Module(module) => module.clone(),
+            Compiled::Component(_) => {
+                return Err(anyhow::anyhow!(
+                    "V2 WATM is a component, it is instantiated as a V2Core"
+                ))
+            }
+        };
+
+        let engine = watm.engine.clone();
         let linker: Linker<Host> = Linker::new(&engine);
 
         // linker.allow_unknown_exports(true);
 
         let host = Host::default();
-        let store = Store::new(&engine, host);
+        let mut store = Store::new(&engine, host);
+
+        if watm.epoch_interruption {
+            // the epoch is only bumped by `Interrupt::request`
+            store.set_epoch_deadline(1);
+            store.epoch_deadline_trap();
+        }
+
+        let version = Self::module_version(conf, &module)?;
 
+        let core = Self::create_core(conf, linker, store, module, engine, Some(version))?;
+        metrics::registry().observe_instantiation(started.elapsed());
+        Ok(core)
+    }
+
+    /// Find the version of the WATM module from its exported version global, configured for `conf` (V0 only for now)
+    pub fn module_version(conf: &WATERConfig, module: &Module) -> Result<Version, anyhow::Error> {
         let mut error_occured = None;
 
         // Get the version global from WATM
@@ -72,14 +289,11 @@ impl H2O<Host> {
         });
 
         // MUST have a version -- otherwise return error
-        if version.is_none() {
-            if let Some(e) = error_occured {
-                return Err(e);
-            }
-            return Err(anyhow::Error::msg("WATM module version not found"));
+        match (version, error_occured) {
+            (Some(version), _) => Ok(version),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(anyhow::Error::msg("WATM module version not found")),
         }
-
-        Self::create_core(conf, linker, store, module, engine, version)
     }
 
     pub fn create_core(
@@ -90,7 +304,15 @@ impl H2O<Host> {
         engine: Engine,
         version: Option<Version>,
     ) -> Result<Self, anyhow::Error> {
-        store.data_mut().preview1_ctx = Some(WasiCtxBuilder::new().inherit_stdio().build());
+        let interrupt = Interrupt::new(&engine);
+        let (wasi_ctx, stdio) = stdio::build_wasi_ctx(&conf.stdio, &interrupt)?;
+        store.data_mut().preview1_ctx = Some(wasi_ctx);
+        store.data_mut().stdio = stdio.clone();
+        store.data_mut().interrupt = interrupt.clone();
+
+        let stats = ConnStats::new(&conf.filepath);
+        store.data_mut().stats = stats.clone();
+        store.data_mut().socket_options = conf.socket_options.clone();
 
         if store.data().preview1_ctx.is_none() {
             return Err(anyhow::anyhow!(
@@ -100,21 +322,6 @@ impl H2O<Host> {
 
         wasmtime_wasi::add_to_linker(&mut linker, |h: &mut Host| h.preview1_ctx.as_mut().unwrap())?;
 
-        /// initialization for WASI-multithread -- currently not completed / used (v1+ feature)
-        #[cfg(feature = "multithread")]
-        {
-            store.data_mut().wasi_threads = Some(Arc::new(WasiThreadsCtx::new(
-                module.clone(),
-                Arc::new(linker.clone()),
-            )?));
-
-            wasmtime_wasi_threads::add_to_linker(&mut linker, &store, &module, |h: &mut Host| {
-                h.wasi_threads
-                    .as_ref()
-                    .context("Failed to get ref of wasi_threads from Host")?
-            })?;
-        }
-
         // export functions -- version dependent -- has to be done before instantiate
         match &version {
             // V0 export functions
@@ -136,15 +343,38 @@ impl H2O<Host> {
                 v1::funcs::export_tcp_connect(&mut linker)?;
                 v1::funcs::export_tcplistener_create(&mut linker)?;
             }
+            // V2 WATMs are components, which are not loaded as a core module
+            Some(Version::V2) => {
+                return Err(anyhow::anyhow!(
+                    "V2 WATM has to be a component implementing the water:watm world"
+                ));
+            }
             // add export funcs for other versions here
             _ => {
-                unimplemented!("This version is not supported yet")
+                return Err(anyhow::anyhow!("This version is not supported yet"));
             }
         }
 
         // export functions -- version independent
         {
             version_common::funcs::export_config(&mut linker, conf.config_wasm.clone())?;
+            version_common::funcs::export_log(&mut linker, conf.debug)?;
+        }
+
+        // initialization for WASI-threads -- has to be done after all the other functions are exported,
+        // since every spawned thread is a new instance of the module created from this linker
+        #[cfg(feature = "multithread")]
+        {
+            wasmtime_wasi_threads::add_to_linker(&mut linker, &store, &module, |h: &mut Host| {
+                h.wasi_threads
+                    .as_ref()
+                    .expect("wasi_threads in Host is None")
+            })?;
+
+            store.data_mut().wasi_threads = Some(Arc::new(WasiThreadsCtx::new(
+                module.clone(),
+                Arc::new(linker.clone()),
+            )?));
         }
 
         // linker.define_unknown_imports_as_traps(&module)?;
@@ -158,12 +388,7 @@ impl H2O<Host> {
             let mut res = vec![Val::null(); func.ty(&store).results().len()];
             match func.call(&mut store, &[], &mut res) {
                 Ok(_) => {}
-                Err(e) => {
-                    return Err(anyhow::Error::msg(format!(
-                        "failed to call _start function: {}",
-                        e
-                    )))
-                }
+                Err(e) => return Err(store.data().guest_error("_start", e)),
             }
         }
 
@@ -180,14 +405,24 @@ impl H2O<Host> {
             instance,
             store: Arc::new(Mutex::new(store)),
             module,
+            stats,
+            stdio,
+            interrupt,
+            instance_guard: Arc::new(metrics::ActiveInstance::new()),
         })
     }
 
     // This function is for migrating the v0 core for listener and relay
-    // to handle every new connection is creating a new separate core (as v0 spec)
-    pub fn v0_migrate_core(conf: &WATERConfig, core: &H2O<Host>) -> Result<Self, anyhow::Error> {
+    // to handle every new connection is creating a new separate core (as v0 spec) of `watm`, the WATM of `conf` compiled
+    pub fn v0_migrate_core(
+        conf: &WATERConfig,
+        watm: &CompiledWatm,
+        core: &H2O<Host>,
+    ) -> Result<Self, anyhow::Error> {
         info!("[HOST] WATERCore H2O v0_migrating...");
 
+        let started = Instant::now();
+
         // reseting the listener accepted_fd or the relay's accepted_fd & dial_fd
         // when migrating from existed listener / relay
         let version = match &core.version {
@@ -215,23 +450,46 @@ impl H2O<Host> {
             }
         };
 
-        // NOTE: Some of the followings can reuse the existing core, leave to later explore
-        let wasm_config = wasmtime::Config::new();
-
-        #[cfg(feature = "multithread")]
-        {
-            wasm_config.wasm_threads(true);
-        }
-
-        let engine = Engine::new(&wasm_config)?;
+        // the new instance is created from the WATM already compiled
+        let engine = watm.engine.clone();
         let linker: Linker<Host> = Linker::new(&engine);
 
-        let module = Module::from_file(&engine, &conf.filepath)?;
+        // the listener / relay can only be migrated to another v0 WATM (e.g. when hot-swapping the module)
+        let module = match &watm.compiled {
+            Compiled::Module(module)
+                if module.exports().any(|export| {
+                    matches!(Version::parse(export.name()), Some(Version::V0(_)))
+                }) =>
+            {
+                module.clone()
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "WATM module {} is not a v0 module, can't migrate to it",
+                    conf.filepath
+                ));
+            }
+        };
 
         let host = Host::default();
         let store = Store::new(&engine, host);
 
-        Self::create_core(conf, linker, store, module, engine, Some(version))
+        let core = Self::create_core(conf, linker, store, module, engine, Some(version))?;
+        // the migrated listener keeps the address it is bound to
+        core.record_v0_listener_addr()?;
+        metrics::registry().observe_instantiation(started.elapsed());
+        Ok(core)
+    }
+
+    /// Record the address the v0 listener / relay is bound to in the stats of this instance
+    pub fn record_v0_listener_addr(&self) -> Result<(), anyhow::Error> {
+        if let Version::V0(Some(v0_conf)) = &self.version {
+            let v0_conf = v0_conf
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock v0_conf: {}", e))?;
+            self.stats.record_socket_addrs(&v0_conf.listener_socket()?);
+        }
+        Ok(())
     }
 
     pub fn _prepare(&mut self, conf: &WATERConfig) -> Result<(), anyhow::Error> {
@@ -260,7 +518,7 @@ impl H2O<Host> {
         let mut res = vec![Val::I64(0); init_fn.ty(&*store).results().len()];
         match init_fn.call(&mut *store, &[], &mut res) {
             Ok(_) => {}
-            Err(e) => return Err(anyhow::Error::msg(format!("init function failed: {}", e))),
+            Err(e) => return Err(store.data().guest_error(INIT_FN, e)),
         }
 
         Ok(())
@@ -290,20 +548,7 @@ impl H2O<Host> {
             }
         };
 
-        // Obtain the directory path and file name from config_wasm
-        let full_path = Path::new(&config.config_wasm);
-        let parent_dir = full_path
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("config_wasm does not have a parent directory"))?; // Assumes config_wasm has a parent directory
-        let file_name = full_path
-            .file_name()
-            .and_then(|os_str| os_str.to_str())
-            .ok_or_else(|| anyhow::anyhow!("file_name is not valid UTF-8"))?; // Assumes file_name is valid UTF-8
-
-        // Open the parent directory
-        let dir = Dir::open_ambient_dir(parent_dir, ambient_authority())?;
-
-        let wasi_file = dir.open_with(file_name, OpenOptions::new().read(true).write(true))?;
+        let wasi_file = open_config_file(config)?;
 
         let wasi_file = wasmtime_wasi::sync::file::File::from_cap_std(wasi_file);
 
@@ -319,14 +564,27 @@ impl H2O<Host> {
         let params = vec![Val::I32(config_fd); config_fn.ty(&*store).params().len()];
         match config_fn.call(&mut *store, &params, &mut []) {
             Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "_process_config function in WASM failed: {}",
-                    e
-                )))
-            }
+            Err(e) => return Err(store.data().guest_error(CONFIG_FN, e)),
         }
 
         Ok(())
     }
 }
+
+/// Open the config file of the WATM (`config_wasm`) to be pushed into it
+pub fn open_config_file(config: &WATERConfig) -> Result<cap_std::fs::File, anyhow::Error> {
+    // Obtain the directory path and file name from config_wasm
+    let full_path = Path::new(&config.config_wasm);
+    let parent_dir = full_path
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("config_wasm does not have a parent directory"))?; // Assumes config_wasm has a parent directory
+    let file_name = full_path
+        .file_name()
+        .and_then(|os_str| os_str.to_str())
+        .ok_or_else(|| anyhow::anyhow!("file_name is not valid UTF-8"))?; // Assumes file_name is valid UTF-8
+
+    // Open the parent directory
+    let dir = Dir::open_ambient_dir(parent_dir, ambient_authority())?;
+
+    Ok(dir.open_with(file_name, OpenOptions::new().read(true).write(true))?)
+}
@@ -0,0 +1,278 @@
+//! An HTTP/1.1 proxy front-end for the WATER dialers, for the applications only speaking HTTP proxy
+//! (package managers, JVM apps, ...).
+//!
+//! Each accepted client is handed to its own thread, which reads the request, creates a new `WATERClient` dialer
+//! with the config of the server (a new instance of the WATM compiled once by the server), connects it to the
+//! destination requested and tunnels the client through it:
+//!
+//! - `CONNECT host:port` is answered with `200 Connection Established` and the connection is tunneled as is.
+//! - an absolute-form `GET http://host/path` is sent on in origin-form (`GET /path`) with `Connection: close`,
+//!   then the response is relayed back until either side closes.
+//!
+//! `502 Bad Gateway` is returned when the dial fails, `504 Gateway Timeout` when the WATM doesn't connect in time.
+
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    time::Duration,
+};
+
+use crate::runtime::{client::WATERClient, core::CompiledWatm, *};
+
+/// The longest request head accepted
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// Default time for the WATM to connect to the destination before `504 Gateway Timeout`
+pub const DEFAULT_DIAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Headers only meant for the proxy, not sent on with a `GET`
+const HOP_BY_HOP_HEADERS: [&str; 4] = [
+    "connection",
+    "proxy-connection",
+    "proxy-authorization",
+    "keep-alive",
+];
+
+/// An HTTP proxy server tunneling each request through a new WATER dialer created with `conf`
+pub struct HttpProxyServer {
+    listener: TcpListener,
+    conf: WATERConfig,
+    watm: CompiledWatm,
+    dial_timeout: Duration,
+}
+
+impl HttpProxyServer {
+    /// Listen on `addr` for the HTTP proxy clients, `conf` is the config of the dialers
+    pub fn bind(addr: impl ToSocketAddrs, conf: WATERConfig) -> Result<Self, anyhow::Error> {
+        let watm = CompiledWatm::compile(&conf)?;
+        let listener = TcpListener::bind(addr).context("[HOST] HTTP proxy failed to bind")?;
+
+        Ok(HttpProxyServer {
+            listener,
+            conf,
+            watm,
+            dial_timeout: DEFAULT_DIAL_TIMEOUT,
+        })
+    }
+
+    /// Answer `504 Gateway Timeout` when connecting to the destination takes longer than `dial_timeout`
+    pub fn with_dial_timeout(mut self, dial_timeout: Duration) -> Self {
+        self.dial_timeout = dial_timeout;
+        self
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr, anyhow::Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept the clients and serve each of them on a new thread, only returns if the listener fails
+    pub fn serve(&self) -> Result<(), anyhow::Error> {
+        info!(
+            "[HOST] HTTP proxy listening on {}",
+            self.listener.local_addr()?
+        );
+
+        loop {
+            let (conn, peer) = self.listener.accept()?;
+
+            let conf = self.conf.clone();
+            let watm = self.watm.clone();
+            let dial_timeout = self.dial_timeout;
+            std::thread::spawn(
+                move || match serve_client(conf, &watm, dial_timeout, conn) {
+                    Ok(_) => info!("[HOST] HTTP proxy client {} closed", peer),
+                    Err(e) => info!("[HOST] HTTP proxy client {} failed: {:#}", peer, e),
+                },
+            );
+        }
+    }
+}
+
+/// A request read from the client
+struct Request {
+    method: String,
+    target: String,
+    version: String,
+    headers: Vec<(String, String)>,
+}
+
+/// Serve one HTTP proxy client: read the request, dial with a new WATERClient instance of `watm`, then tunnel
+/// the client through it
+pub fn serve_client(
+    conf: WATERConfig,
+    watm: &CompiledWatm,
+    dial_timeout: Duration,
+    mut conn: TcpStream,
+) -> Result<(), anyhow::Error> {
+    let request = match read_request(&mut conn) {
+        Ok(request) => request,
+        Err(e) => {
+            let _ = respond(&mut conn, "400 Bad Request");
+            return Err(e);
+        }
+    };
+
+    // the head to send on through the tunnel, nothing for CONNECT
+    let (destination, head) = match request.method.as_str() {
+        "CONNECT" => (request.target.clone(), None),
+        "GET" => match origin_form(&request) {
+            Some((destination, head)) => (destination, Some(head)),
+            None => {
+                respond(&mut conn, "400 Bad Request")?;
+                return Err(anyhow::anyhow!(
+                    "[HOST] HTTP proxy GET target is not absolute: {}",
+                    request.target
+                ));
+            }
+        },
+        method => {
+            respond(&mut conn, "405 Method Not Allowed")?;
+            return Err(anyhow::anyhow!(
+                "[HOST] HTTP proxy unsupported method {}",
+                method
+            ));
+        }
+    };
+
+    info!("[HOST] HTTP proxy {} {}", request.method, destination);
+
+    // the instance is created before the dial is timed, only connecting to the destination has to finish in time
+    let dialed = match WATERClient::from_compiled(conf, watm) {
+        Ok(water_client) => dial(water_client, destination.clone(), dial_timeout),
+        Err(e) => Ok(Err(e)),
+    };
+
+    let mut water_client = match dialed {
+        Ok(Ok(water_client)) => water_client,
+        Ok(Err(e)) => {
+            let _ = respond(&mut conn, "502 Bad Gateway");
+            return Err(e.context(format!(
+                "[HOST] HTTP proxy failed to dial for {}",
+                destination
+            )));
+        }
+        Err(_) => {
+            let _ = respond(&mut conn, "504 Gateway Timeout");
+            return Err(anyhow::anyhow!(
+                "[HOST] HTTP proxy timed out dialing for {}",
+                destination
+            ));
+        }
+    };
+
+    match head {
+        Some(head) => water_client.write(head.as_bytes())?,
+        None => respond(&mut conn, "200 Connection Established")?,
+    }
+
+    water_client.tunnel(conn)
+}
+
+/// Connect `water_client` to `destination` on another thread, giving up on it after `dial_timeout`
+fn dial(
+    mut water_client: WATERClient,
+    destination: String,
+    dial_timeout: Duration,
+) -> Result<Result<WATERClient, anyhow::Error>, mpsc::RecvTimeoutError> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let dialed = water_client.connect_to(&destination).map(|_| water_client);
+        // nobody is waiting anymore after a timeout, the client is dropped
+        let _ = tx.send(dialed);
+    });
+
+    rx.recv_timeout(dial_timeout)
+}
+
+/// Read the request line and headers, up to the empty line
+fn read_request(conn: &mut TcpStream) -> Result<Request, anyhow::Error> {
+    // byte by byte, not to read past the head into what is tunneled
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD_SIZE {
+            return Err(anyhow::anyhow!("[HOST] HTTP proxy request head too large"));
+        }
+        if conn.read(&mut byte)? == 0 {
+            return Err(anyhow::anyhow!(
+                "[HOST] HTTP proxy client closed before the request"
+            ));
+        }
+        head.push(byte[0]);
+    }
+
+    let head = String::from_utf8(head).context("[HOST] HTTP proxy invalid request head")?;
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            (method, target, version)
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "[HOST] HTTP proxy invalid request line: {}",
+                request_line
+            ))
+        }
+    };
+
+    let headers = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.split_once(':') {
+            Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+            None => Err(anyhow::anyhow!(
+                "[HOST] HTTP proxy invalid header: {}",
+                line
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Request {
+        method: method.to_string(),
+        target: target.to_string(),
+        version: version.to_string(),
+        headers,
+    })
+}
+
+/// The destination (host:port) of an absolute-form `GET` and its head to send on in origin-form
+fn origin_form(request: &Request) -> Option<(String, String)> {
+    let url = url::Url::parse(&request.target).ok()?;
+    if url.scheme() != "http" {
+        return None;
+    }
+    let destination = format!("{}:{}", url.host_str()?, url.port_or_known_default()?);
+
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    let mut head = format!("GET {} {}\r\n", path, request.version);
+    for (name, value) in &request.headers {
+        if !HOP_BY_HOP_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+    }
+    // one request per tunnel, the following ones would be sent on in absolute-form otherwise
+    head.push_str("Connection: close\r\n\r\n");
+
+    Some((destination, head))
+}
+
+/// Respond with an empty body and `status` (e.g. `502 Bad Gateway`)
+fn respond(conn: &mut TcpStream, status: &str) -> Result<(), anyhow::Error> {
+    let response = match status.starts_with("200") {
+        // the tunnel follows right after
+        true => format!("HTTP/1.1 {}\r\n\r\n", status),
+        false => format!(
+            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            status
+        ),
+    };
+    conn.write_all(response.as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+//! The connections accepted by a WATER `Listener`, as a blocking iterator ([`Incoming`]) or an async
+//! `Stream` ([`IncomingStream`]), each of them yielded as a new `WATERClient` ready to read / write / tunnel.
+//!
+//! The instances handling the connections are created by `WATERClient::accept_next`, so the listener keeps
+//! listening while the connections already yielded are served.
+
+use std::{
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+
+use futures::{channel::mpsc, executor::block_on, SinkExt, Stream};
+
+use crate::runtime::{client::WATERClient, *};
+
+/// An iterator over the connections accepted by a `Listener`, returned by `WATERClient::incoming`.
+///
+/// It never returns `None`, as `std::net::TcpListener::incoming` does.
+pub struct Incoming<'a> {
+    pub(crate) listener: &'a mut WATERClient,
+}
+
+impl Iterator for Incoming<'_> {
+    type Item = Result<WATERClient, anyhow::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.listener.accept_next())
+    }
+}
+
+/// An async `Stream` of the connections accepted by a `Listener`, returned by `WATERClient::into_incoming_stream`.
+///
+/// The listener is moved to a thread accepting the connections, which exits once the stream is dropped
+/// and the connection being accepted (if any) has arrived.
+pub struct IncomingStream {
+    receiver: mpsc::Receiver<Result<WATERClient, anyhow::Error>>,
+}
+
+impl IncomingStream {
+    pub(crate) fn spawn(mut listener: WATERClient) -> Self {
+        // the thread blocks on sending, so it doesn't accept more than one connection ahead of the consumer
+        let (mut sender, receiver) = mpsc::channel(0);
+
+        std::thread::spawn(move || {
+            for accepted in listener.incoming() {
+                if block_on(sender.send(accepted)).is_err() {
+                    info!("[HOST] IncomingStream dropped, stop accepting");
+                    break;
+                }
+            }
+        });
+
+        IncomingStream { receiver }
+    }
+}
+
+impl Stream for IncomingStream {
+    type Item = Result<WATERClient, anyhow::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
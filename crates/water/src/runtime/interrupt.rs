@@ -0,0 +1,139 @@
+//! Interrupting a WATM instance of the sync runtime from another thread, while the store is locked by
+//! the thread running one of its functions (e.g. the entry function of a `Runner`, which may never return).
+//!
+//! Once requested, the scheduler of the instance traps the WATM the next time it polls, sleeps or yields,
+//! waking it up if it is already waiting there. The instances created with epoch interruption enabled
+//! (`Runner`) are also trapped when running wasm code only, by bumping the epoch of their engine.
+//! The connections registered with `shutdown_on_request` are shut down, for the WATM blocked reading them to return.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, MutexGuard,
+};
+
+use crate::runtime::{pipe::sched::Waiter, watm_socket::WatmSocket, *};
+
+/// The interrupt of a WATM instance, shared by its Host (checked by the scheduler) and the handles stopping it
+#[derive(Clone, Default)]
+pub struct Interrupt {
+    inner: Arc<InterruptInner>,
+}
+
+#[derive(Default)]
+struct InterruptInner {
+    requested: AtomicBool,
+
+    /// the engine of the instance, its epoch is bumped on a request
+    engine: Option<Engine>,
+
+    /// the threads of the instance waiting in the scheduler, woken up on a request
+    waiters: Mutex<Vec<Arc<Waiter>>>,
+
+    /// the connections of the instance not closed by the WATM yet, shut down on a request
+    sockets: Mutex<Vec<WatmSocket>>,
+}
+
+/// The error trapping a WATM once it is interrupted
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WATM instance interrupted by the Host")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// Whether `e` returned by a function of the WATM is the trap of its interrupt (raised by the scheduler or by the epoch
+/// bumped), and not an error of the WATM racing with the request
+pub fn is_interrupted(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| cause.is::<Interrupted>())
+        || e.downcast_ref::<Interrupted>().is_some()
+        || e.downcast_ref::<Trap>() == Some(&Trap::Interrupt)
+}
+
+impl Interrupt {
+    /// The interrupt of an instance created with `engine`
+    pub fn new(engine: &Engine) -> Self {
+        Interrupt {
+            inner: Arc::new(InterruptInner {
+                engine: Some(engine.clone()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Interrupt the instance, it can't run any of its functions afterwards
+    pub fn request(&self) {
+        if self.inner.requested.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        info!("[HOST] WATM instance interrupt requested");
+
+        // does nothing to the instances without epoch interruption
+        if let Some(engine) = &self.inner.engine {
+            engine.increment_epoch();
+        }
+
+        for waiter in self.lock_waiters().iter() {
+            waiter.wake();
+        }
+
+        for socket in lock(&self.inner.sockets).drain(..) {
+            socket.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// Trap the WATM calling into the scheduler if the interrupt has been requested
+    pub(crate) fn check(&self) -> Result<(), wasi_common::Error> {
+        match self.is_requested() {
+            true => Err(wasi_common::Error::trap(anyhow::Error::new(Interrupted))),
+            false => Ok(()),
+        }
+    }
+
+    /// Register `waiter` to be woken up on a request, checking for one made before
+    pub(crate) fn subscribe(&self, waiter: &Arc<Waiter>) -> Result<(), wasi_common::Error> {
+        self.lock_waiters().push(Arc::clone(waiter));
+        self.check()
+    }
+
+    pub(crate) fn unsubscribe(&self, waiter: &Arc<Waiter>) {
+        self.lock_waiters().retain(|w| !Arc::ptr_eq(w, waiter));
+    }
+
+    /// Shut down `socket` on a request (right away if one was made), the WATM may be blocked reading it
+    /// where the scheduler can't wake it up. The sockets closed by the WATM meanwhile are forgotten.
+    pub(crate) fn shutdown_on_request(&self, socket: WatmSocket) {
+        let mut sockets = lock(&self.inner.sockets);
+        match self.is_requested() {
+            true => socket.shutdown(std::net::Shutdown::Both),
+            false => {
+                sockets.retain(|socket| !socket.is_closed());
+                sockets.push(socket);
+            }
+        }
+    }
+
+    /// The connection of the instance to the network: the last one dialed / accepted the WATM didn't close
+    pub fn network(&self) -> Option<WatmSocket> {
+        lock(&self.inner.sockets)
+            .iter()
+            .rev()
+            .find(|socket| !socket.is_closed())
+            .cloned()
+    }
+
+    fn lock_waiters(&self) -> MutexGuard<'_, Vec<Arc<Waiter>>> {
+        lock(&self.inner.waiters)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,521 @@
+//! Process-wide metrics registry for the WATER runtime.
+//!
+//! The registry is updated by the runtime itself (instantiation, dials from the Host exported functions,
+//! guest traps, pools), and the per-module byte counters are collected from [`stats`](super::stats)
+//! at render time. It can be rendered in the Prometheus text exposition format and handed to a
+//! [`MetricsSink`], or served over HTTP on a local port with [`serve`] for Prometheus to scrape.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use socket2::SockRef;
+use tracing::{debug, info};
+
+use crate::runtime::stats;
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+/// How long a scraper may take to send its request / read the response before it is dropped
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bounds (in seconds) of the instantiation latency histogram buckets
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Get the process-wide metrics registry
+pub fn registry() -> &'static Metrics {
+    &METRICS
+}
+
+/// Where the rendered metrics go, e.g. a file, stdout or a push gateway implemented by the embedding app
+pub trait MetricsSink: Send + Sync {
+    fn export(&self, rendered: &str) -> Result<(), anyhow::Error>;
+}
+
+/// A sink writing the rendered metrics into any writer (file, stdout, ...)
+pub struct WriterSink<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> WriterSink<W> {
+    pub fn new(writer: W) -> Self {
+        WriterSink {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl<W: Write + Send> MetricsSink for WriterSink<W> {
+    fn export(&self, rendered: &str) -> Result<(), anyhow::Error> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock writer: {}", e))?;
+        writer.write_all(rendered.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// cumulative counts are computed at render time, so each bucket only counts its own range
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// Occupancy of one pool of WATM instances (e.g. the connections of a relay server)
+#[derive(Default)]
+pub struct PoolGauge {
+    occupancy: AtomicI64,
+    capacity: AtomicI64,
+}
+
+impl PoolGauge {
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity as i64, Ordering::Relaxed);
+    }
+
+    /// Take one slot of the pool, which is released when the returned guard is dropped
+    pub fn acquire(self: &Arc<Self>) -> PoolSlot {
+        self.occupancy.fetch_add(1, Ordering::Relaxed);
+        PoolSlot {
+            pool: Arc::clone(self),
+        }
+    }
+
+    pub fn occupancy(&self) -> i64 {
+        self.occupancy.load(Ordering::Relaxed)
+    }
+}
+
+/// A slot taken from a [`PoolGauge`]
+pub struct PoolSlot {
+    pool: Arc<PoolGauge>,
+}
+
+impl Drop for PoolSlot {
+    fn drop(&mut self) {
+        self.pool.occupancy.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Guard counting one alive WATM instance, held by the H2O core
+pub struct ActiveInstance(());
+
+impl ActiveInstance {
+    pub fn new() -> Self {
+        METRICS.active_instances.fetch_add(1, Ordering::Relaxed);
+        ActiveInstance(())
+    }
+}
+
+impl Default for ActiveInstance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ActiveInstance {
+    fn drop(&mut self) {
+        METRICS.active_instances.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The metrics registry, use [`registry`] to get the process-wide one
+#[derive(Default)]
+pub struct Metrics {
+    active_instances: AtomicI64,
+    instantiation_latency: Mutex<Histogram>,
+
+    dial_successes: AtomicU64,
+    /// error code -> count
+    dial_failures: Mutex<BTreeMap<String, u64>>,
+
+    /// function name -> count
+    guest_traps: Mutex<BTreeMap<String, u64>>,
+
+    pools: Mutex<BTreeMap<String, Arc<PoolGauge>>>,
+}
+
+impl Metrics {
+    /// Record how long it took to create (compile + instantiate + start) a WATM instance
+    pub fn observe_instantiation(&self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let mut histogram = self
+            .instantiation_latency
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            histogram.buckets[i] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += secs;
+    }
+
+    pub fn record_dial_success(&self) {
+        self.dial_successes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dial_failure(&self, e: &anyhow::Error) {
+        let mut failures = self.dial_failures.lock().unwrap_or_else(|e| e.into_inner());
+        *failures.entry(error_code(e)).or_default() += 1;
+    }
+
+    /// Record the error returned by calling `function` in the WATM, only traps are counted
+    pub fn record_guest_error(&self, function: &str, e: &anyhow::Error) {
+        if e.downcast_ref::<wasmtime::Trap>().is_none() {
+            return;
+        }
+
+        let mut traps = self.guest_traps.lock().unwrap_or_else(|e| e.into_inner());
+        *traps.entry(function.to_string()).or_default() += 1;
+    }
+
+    /// Get (or create) the gauge of the pool named `name`
+    pub fn pool(&self, name: &str) -> Arc<PoolGauge> {
+        let mut pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(pools.entry(name.to_string()).or_default())
+    }
+
+    pub fn active_instances(&self) -> i64 {
+        self.active_instances.load(Ordering::Relaxed)
+    }
+
+    /// Render all the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "water_active_instances",
+            "gauge",
+            "Number of WATM instances currently alive.",
+        );
+        let _ = writeln!(
+            out,
+            "water_active_instances {}",
+            self.active_instances.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "water_instantiation_duration_seconds",
+            "histogram",
+            "Time taken to compile, instantiate and start a WATM instance.",
+        );
+        {
+            let histogram = self
+                .instantiation_latency
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "water_instantiation_duration_seconds_bucket{{le=\"{}\"}} {}",
+                    le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "water_instantiation_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "water_instantiation_duration_seconds_sum {}",
+                histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "water_instantiation_duration_seconds_count {}",
+                histogram.count
+            );
+        }
+
+        header(
+            &mut out,
+            "water_dials_total",
+            "counter",
+            "Outbound connections dialed by the Host for WATMs, by result and error code.",
+        );
+        let _ = writeln!(
+            out,
+            "water_dials_total{{result=\"success\",code=\"ok\"}} {}",
+            self.dial_successes.load(Ordering::Relaxed)
+        );
+        for (code, count) in self
+            .dial_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "water_dials_total{{result=\"failure\",code=\"{}\"}} {}",
+                escape(code),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "water_guest_traps_total",
+            "counter",
+            "Traps raised by WATMs, by the invoked function.",
+        );
+        for (function, count) in self
+            .guest_traps
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "water_guest_traps_total{{function=\"{}\"}} {}",
+                escape(function),
+                count
+            );
+        }
+
+        let mut modules = stats::all_module_stats();
+        modules.sort_by(|a, b| a.module.cmp(&b.module));
+
+        header(
+            &mut out,
+            "water_bytes_total",
+            "counter",
+            "Bytes transferred thru WATMs, by module, side (caller / network) and direction.",
+        );
+        for m in modules.iter() {
+            let module = escape(&m.module);
+            for (side, direction, value) in [
+                ("caller", "write", m.caller_bytes_written),
+                ("caller", "read", m.caller_bytes_read),
+                ("network", "send", m.network_bytes_sent),
+                ("network", "recv", m.network_bytes_received),
+            ] {
+                let _ = writeln!(
+                    out,
+                    "water_bytes_total{{module=\"{}\",side=\"{}\",direction=\"{}\"}} {}",
+                    module, side, direction, value
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "water_connections_total",
+            "counter",
+            "Connections established thru WATMs, by module.",
+        );
+        for m in modules.iter() {
+            let _ = writeln!(
+                out,
+                "water_connections_total{{module=\"{}\"}} {}",
+                escape(&m.module),
+                m.connections
+            );
+        }
+
+        let pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+
+        header(
+            &mut out,
+            "water_pool_occupancy",
+            "gauge",
+            "Number of WATM instances in use in a pool.",
+        );
+        for (name, pool) in pools.iter() {
+            let _ = writeln!(
+                out,
+                "water_pool_occupancy{{pool=\"{}\"}} {}",
+                escape(name),
+                pool.occupancy.load(Ordering::Relaxed)
+            );
+        }
+
+        header(
+            &mut out,
+            "water_pool_capacity",
+            "gauge",
+            "Maximum number of WATM instances in a pool.",
+        );
+        for (name, pool) in pools.iter() {
+            let _ = writeln!(
+                out,
+                "water_pool_capacity{{pool=\"{}\"}} {}",
+                escape(name),
+                pool.capacity.load(Ordering::Relaxed)
+            );
+        }
+
+        out
+    }
+
+    /// Render the metrics and hand them to the sink
+    pub fn export(&self, sink: &dyn MetricsSink) -> Result<(), anyhow::Error> {
+        sink.export(&self.render())
+    }
+}
+
+/// A running HTTP server exposing the process-wide metrics, see [`serve`]
+pub struct MetricsServer {
+    pub local_addr: SocketAddr,
+    pub handle: JoinHandle<()>,
+
+    /// a clone of the listener accepting in `handle`, shut down to wake it up when stopping
+    listener: TcpListener,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl MetricsServer {
+    /// Stop accepting scrapes and wait for the server thread to exit,
+    /// the scrapes already accepted are still answered
+    pub fn stop(self) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATER metrics server stopping ...");
+
+        self.shutdown.store(true, Ordering::SeqCst);
+        SockRef::from(&self.listener)
+            .shutdown(Shutdown::Read)
+            .context("[HOST] metrics server failed to shut down the listener")?;
+
+        self.handle
+            .join()
+            .map_err(|_| anyhow::anyhow!("[HOST] metrics server thread panicked"))
+    }
+}
+
+/// Serve the process-wide metrics in the Prometheus text format over HTTP on `addr`,
+/// every request gets the freshly rendered metrics whatever its path is.
+///
+/// Each scrape is answered on a thread of its own, and dropped if it is slower than [`SCRAPE_TIMEOUT`].
+pub fn serve(addr: &str) -> Result<MetricsServer, anyhow::Error> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let shutdown = Arc::new(AtomicBool::new(false));
+
+    info!(
+        "[HOST] WATER metrics served on http://{}/metrics",
+        local_addr
+    );
+
+    let handle = {
+        let (listener, shutdown) = (listener.try_clone()?, Arc::clone(&shutdown));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                match stream {
+                    Ok(stream) => {
+                        std::thread::spawn(move || respond(stream));
+                    }
+                    Err(e) => debug!("[HOST] metrics server failed to accept: {}", e),
+                }
+            }
+        })
+    };
+
+    Ok(MetricsServer {
+        local_addr,
+        handle,
+        listener,
+        shutdown,
+    })
+}
+
+/// Answer a single scrape with the freshly rendered metrics
+fn respond(mut stream: TcpStream) {
+    if let Err(e) = stream
+        .set_read_timeout(Some(SCRAPE_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(SCRAPE_TIMEOUT)))
+    {
+        debug!("[HOST] metrics server failed to set the timeouts: {}", e);
+        return;
+    }
+
+    // drain the request head, the response doesn't depend on it
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    loop {
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) if line == "\r\n" || line == "\n" => break,
+            Ok(_) => line.clear(),
+            Err(e) => {
+                debug!("[HOST] metrics server failed to read the request: {}", e);
+                return;
+            }
+        }
+    }
+
+    let body = registry().render();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        debug!("[HOST] metrics server failed to respond: {}", e);
+    }
+}
+
+/// Map an error to a short code used as a metric label, e.g. `connection_refused`
+pub fn error_code(e: &anyhow::Error) -> String {
+    for cause in e.chain() {
+        if let Some(io_err) = cause.downcast_ref::<std::io::Error>() {
+            return snake_case(&format!("{:?}", io_err.kind()));
+        }
+        if cause.downcast_ref::<wasmtime::Trap>().is_some() {
+            return "trap".into();
+        }
+    }
+    "other".into()
+}
+
+fn snake_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 4);
+    for (i, c) in s.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i != 0 {
+                out.push('_');
+            }
+            out.push(c.to_ascii_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value as required by the Prometheus text format
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
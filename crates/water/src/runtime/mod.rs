@@ -1,23 +1,40 @@
 //! This module contains the runtime implementation of using WASM, including the host, core, and related interaction operations.
 
 // =================== MODULES ===================
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod bridge;
 pub mod client;
 pub mod core;
+pub mod http_proxy;
+pub mod incoming;
+pub mod interrupt;
 pub mod listener;
+pub mod metrics;
 pub mod net;
+pub mod pipe;
 pub mod relay;
+pub mod relay_server;
 pub mod runner;
+pub mod signature;
+pub mod socket;
+pub mod socks5;
+pub mod stats;
+pub mod stdio;
 pub mod stream;
+pub mod supervisor;
 pub mod transport;
+pub mod trap;
 pub mod v0;
 pub mod v1;
+pub mod v2;
 pub mod version;
 pub mod version_common;
+pub mod watm_socket;
 
 // =================== STD Imports ===================
 use std::{
     io::{Read, Write},
-    os::unix::io::{AsRawFd, FromRawFd},
     path::Path,
     sync::Arc,
 };
@@ -28,24 +45,29 @@ use cap_std::{
     ambient_authority,
     fs::OpenOptions,
     net::{TcpListener, TcpStream},
-    os::unix::net::UnixStream,
 };
 use tracing::{debug, info};
 use wasi_common::{file::FileAccessMode, WasiCtx, WasiFile};
 use wasmtime::*;
-use wasmtime_wasi::sync::{Dir, WasiCtxBuilder};
+use wasmtime_wasi::sync::Dir;
+#[cfg(feature = "multithread")]
+use wasmtime_wasi_threads::WasiThreadsCtx;
 
 // =================== CURRENT CRATE IMPORTS ===================
 use crate::{
     config::{WATERConfig, WaterBinType},
     globals::{
-        ACCEPT_FN, ASSOCIATE_FN, CANCEL_FN, CONFIG_FN, DIAL_FN, INIT_FN, READER_FN,
-        WATER_BRIDGING_FN, WRITER_FN,
+        ACCEPT_FN, ALLOC_FN, ASSOCIATE_FN, CANCEL_FN, CONFIG_FN, DIAL_FN, INIT_FN, READER_FN,
+        READ_BUF_FN, WATER_BRIDGING_FN, WRITER_FN, WRITE_BUF_FN,
     },
 };
 
 // =================== MODULES' DEPENDENCIES ===================
 use self::core::{Host, H2O};
+use self::interrupt::Interrupt;
 use self::net::{ConnectFile, File, ListenFile};
+use self::pipe::PipeStream;
 use self::runner::WATERRunner;
+use self::stats::ConnStats;
+use self::stdio::CapturedStdio;
 use self::version::Version;
@@ -0,0 +1,428 @@
+//! In-process pipes between the Host and the WATM instances, replacing the UnixStream pairs:
+//! the bytes are moved through a bounded in-memory buffer instead of the kernel, and the WATM end
+//! is pushed into the WasiCtx as a [`WasiFile`] owned by the WATM (closed when the WATM closes it).
+//!
+//! The pipes are not backed by an fd, so the WATMs polling them need the scheduler in [`sched`],
+//! which is the one every WasiCtx of the sync runtime is built with.
+
+pub mod sched;
+
+use std::{
+    any::Any,
+    collections::VecDeque,
+    io::{IoSlice, IoSliceMut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex, MutexGuard,
+    },
+};
+
+use wasi_common::{
+    file::{FdFlags, FileType, RiFlags, RoFlags, SdFlags, SiFlags},
+    Error, ErrorExt,
+};
+
+use self::sched::Waiter;
+use crate::runtime::*;
+
+/// Number of bytes buffered in each direction of a pipe before the writer blocks (or gets `WouldBlock`),
+/// about the default buffer size of a UnixStream.
+pub const PIPE_CAPACITY: usize = 256 * 1024;
+
+/// One direction of a pipe, with a single reader and a single writer
+struct Channel {
+    state: Mutex<ChannelState>,
+    changed: Condvar,
+}
+
+struct ChannelState {
+    buf: VecDeque<u8>,
+    reader_closed: bool,
+    writer_closed: bool,
+
+    /// the schedulers polling this channel, woken up on any change
+    waiters: Vec<Arc<Waiter>>,
+}
+
+impl ChannelState {
+    fn readable(&self) -> bool {
+        !self.buf.is_empty() || self.writer_closed
+    }
+
+    fn writable(&self) -> bool {
+        self.buf.len() < PIPE_CAPACITY || self.reader_closed
+    }
+
+    fn readiness(&self, read: bool) -> Readiness {
+        match read {
+            true if self.writer_closed => Readiness::HangUp(self.buf.len() as u64),
+            true if !self.buf.is_empty() => Readiness::Ready(self.buf.len() as u64),
+            false if self.reader_closed => Readiness::HangUp(0),
+            false if self.writable() => Readiness::Ready(0),
+            _ => Readiness::NotReady,
+        }
+    }
+}
+
+impl Channel {
+    fn new() -> Arc<Self> {
+        Arc::new(Channel {
+            state: Mutex::new(ChannelState {
+                buf: VecDeque::new(),
+                reader_closed: false,
+                writer_closed: false,
+                waiters: Vec::new(),
+            }),
+            changed: Condvar::new(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ChannelState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wake up the blocked reader / writer and the schedulers polling the channel
+    fn notify(&self, state: &mut ChannelState) {
+        self.changed.notify_all();
+        for waiter in state.waiters.drain(..) {
+            waiter.wake();
+        }
+    }
+
+    fn read(&self, bufs: &mut [IoSliceMut<'_>], nonblocking: bool) -> std::io::Result<usize> {
+        let mut state = self.lock();
+        while !state.readable() {
+            if nonblocking {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        // VecDeque<u8> reads from the front, copying its slices at once
+        let read = state.buf.read_vectored(bufs)?;
+
+        if read > 0 {
+            self.notify(&mut state);
+        }
+        Ok(read)
+    }
+
+    fn write(&self, bufs: &[IoSlice<'_>], nonblocking: bool) -> std::io::Result<usize> {
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Ok(0);
+        }
+
+        let mut state = self.lock();
+        while !state.writable() {
+            if nonblocking {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            state = self.changed.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+
+        if state.reader_closed {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+
+        let mut written = 0;
+        for buf in bufs {
+            let n = std::cmp::min(buf.len(), PIPE_CAPACITY - state.buf.len());
+            state.buf.extend(&buf[..n]);
+            written += n;
+        }
+
+        self.notify(&mut state);
+        Ok(written)
+    }
+
+    fn peek(&self, buf: &mut [u8]) -> usize {
+        let state = self.lock();
+        let (front, back) = state.buf.as_slices();
+        let n = std::cmp::min(buf.len(), front.len());
+        buf[..n].copy_from_slice(&front[..n]);
+        let m = std::cmp::min(buf.len() - n, back.len());
+        buf[n..n + m].copy_from_slice(&back[..m]);
+        n + m
+    }
+
+    fn ready_bytes(&self) -> usize {
+        self.lock().buf.len()
+    }
+
+    fn close_reader(&self) {
+        let mut state = self.lock();
+        state.reader_closed = true;
+        // nobody is reading what is left anymore
+        state.buf = VecDeque::new();
+        self.notify(&mut state);
+    }
+
+    fn close_writer(&self) {
+        let mut state = self.lock();
+        state.writer_closed = true;
+        self.notify(&mut state);
+    }
+}
+
+/// The readiness of a pipe for a subscription in `poll_oneoff`
+pub(crate) enum Readiness {
+    NotReady,
+    /// ready with the number of bytes available to read (0 for writing)
+    Ready(u64),
+    /// the other end is closed
+    HangUp(u64),
+}
+
+/// An end of an in-process pipe, a TcpStream liked stream between the Host and a WATM:
+/// the Host keeps one end for reading / writing, the other end is pushed into the WATM as a file.
+pub struct PipeStream {
+    /// the channel this end reads from, None for a write-only end
+    rx: Option<Arc<Channel>>,
+    /// the channel this end writes to, None for a read-only end
+    tx: Option<Arc<Channel>>,
+
+    nonblocking: AtomicBool,
+
+    /// shared by the clones of this end, closing the channels when the last of them is dropped
+    _closer: Arc<Closer>,
+}
+
+/// Closes the channels of an end of a pipe when dropped
+struct Closer {
+    rx: Option<Arc<Channel>>,
+    tx: Option<Arc<Channel>>,
+}
+
+impl Drop for Closer {
+    fn drop(&mut self) {
+        if let Some(rx) = &self.rx {
+            rx.close_reader();
+        }
+        if let Some(tx) = &self.tx {
+            tx.close_writer();
+        }
+    }
+}
+
+impl PipeStream {
+    fn new(rx: Option<Arc<Channel>>, tx: Option<Arc<Channel>>) -> Self {
+        PipeStream {
+            _closer: Arc::new(Closer {
+                rx: rx.clone(),
+                tx: tx.clone(),
+            }),
+            rx,
+            tx,
+            nonblocking: AtomicBool::new(false),
+        }
+    }
+
+    /// Create another handle of this end, like `UnixStream::try_clone`, e.g. to read and write from different threads.
+    /// The end is closed once this handle and all the others are dropped.
+    pub fn try_clone(&self) -> std::io::Result<PipeStream> {
+        Ok(PipeStream {
+            rx: self.rx.clone(),
+            tx: self.tx.clone(),
+            nonblocking: AtomicBool::new(self.nonblocking.load(Ordering::Relaxed)),
+            _closer: Arc::clone(&self._closer),
+        })
+    }
+
+    /// Create a pair of connected ends, what is written to one end can be read from the other, both ways
+    pub fn pair() -> (PipeStream, PipeStream) {
+        let (a2b, b2a) = (Channel::new(), Channel::new());
+        (
+            PipeStream::new(Some(Arc::clone(&b2a)), Some(Arc::clone(&a2b))),
+            PipeStream::new(Some(a2b), Some(b2a)),
+        )
+    }
+
+    /// Create a one-way pipe, returning the (read_end, write_end)
+    pub fn simplex() -> (PipeStream, PipeStream) {
+        let channel = Channel::new();
+        (
+            PipeStream::new(Some(Arc::clone(&channel)), None),
+            PipeStream::new(None, Some(channel)),
+        )
+    }
+
+    /// Moves this end into or out of nonblocking mode, where reading / writing returns `WouldBlock` instead of waiting
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+    }
+
+    /// Close the writing half of this end, the other end then reads EOF once the buffered bytes are read
+    pub fn shutdown_write(&self) {
+        if let Some(tx) = &self.tx {
+            tx.close_writer();
+        }
+    }
+
+    fn rx(&self) -> std::io::Result<&Channel> {
+        self.rx.as_deref().ok_or(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the pipe is write-only",
+        ))
+    }
+
+    fn tx(&self) -> std::io::Result<&Channel> {
+        self.tx.as_deref().ok_or(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the pipe is read-only",
+        ))
+    }
+
+    fn read_vectored_inner(&self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.rx()?
+            .read(bufs, self.nonblocking.load(Ordering::Relaxed))
+    }
+
+    fn write_vectored_inner(&self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.tx()?
+            .write(bufs, self.nonblocking.load(Ordering::Relaxed))
+    }
+
+    fn polled_channel(&self, read: bool) -> Result<&Channel, Error> {
+        match (read, &self.rx, &self.tx) {
+            (true, Some(rx), _) => Ok(rx),
+            (false, _, Some(tx)) => Ok(tx),
+            _ => Err(Error::badf().context("the pipe end is not open for what is polled")),
+        }
+    }
+
+    /// Check the readiness for reading (or writing) and register `waiter` to be woken up on any change
+    pub(crate) fn subscribe(&self, waiter: &Arc<Waiter>, read: bool) -> Result<Readiness, Error> {
+        let mut state = self.polled_channel(read)?.lock();
+        state.waiters.push(Arc::clone(waiter));
+        Ok(state.readiness(read))
+    }
+
+    /// Check the readiness for reading (or writing)
+    pub(crate) fn readiness(&self, read: bool) -> Result<Readiness, Error> {
+        Ok(self.polled_channel(read)?.lock().readiness(read))
+    }
+
+    /// Remove the `waiter` registered by `subscribe`
+    pub(crate) fn unsubscribe(&self, waiter: &Arc<Waiter>) {
+        for channel in self.rx.iter().chain(self.tx.iter()) {
+            channel
+                .lock()
+                .waiters
+                .retain(|registered| !Arc::ptr_eq(registered, waiter));
+        }
+    }
+}
+
+impl Read for PipeStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.read_vectored_inner(&mut [IoSliceMut::new(buf)])
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> std::io::Result<usize> {
+        self.read_vectored_inner(bufs)
+    }
+}
+
+impl Write for PipeStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_vectored_inner(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> std::io::Result<usize> {
+        self.write_vectored_inner(bufs)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for PipeStream {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    // the WATMs are treating the pipe as the UnixStream it replaces
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::SocketStream)
+    }
+
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        match self.nonblocking.load(Ordering::Relaxed) {
+            true => Ok(FdFlags::NONBLOCK),
+            false => Ok(FdFlags::empty()),
+        }
+    }
+
+    async fn set_fdflags(&mut self, fdflags: FdFlags) -> Result<(), Error> {
+        if fdflags == FdFlags::NONBLOCK {
+            self.set_nonblocking(true);
+        } else if fdflags.is_empty() {
+            self.set_nonblocking(false);
+        } else {
+            return Err(Error::invalid_argument().context("cannot set anything else than NONBLOCK"));
+        }
+        Ok(())
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        Ok(self.read_vectored_inner(bufs)? as u64)
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        Ok(self.write_vectored_inner(bufs)? as u64)
+    }
+
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        Ok(self.rx()?.peek(buf) as u64)
+    }
+
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        if ri_flags == RiFlags::RECV_PEEK {
+            return match ri_data.iter_mut().next() {
+                Some(first) => Ok((self.rx()?.peek(first) as u64, RoFlags::empty())),
+                None => Ok((0, RoFlags::empty())),
+            };
+        } else if !ri_flags.is_empty() {
+            return Err(Error::not_supported());
+        }
+
+        Ok((self.read_vectored_inner(ri_data)? as u64, RoFlags::empty()))
+    }
+
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        if !si_flags.is_empty() {
+            return Err(Error::not_supported());
+        }
+
+        Ok(self.write_vectored_inner(si_data)? as u64)
+    }
+
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        if how.contains(SdFlags::RD) {
+            if let Some(rx) = &self.rx {
+                rx.close_reader();
+            }
+        }
+        if how.contains(SdFlags::WR) {
+            self.shutdown_write();
+        }
+        Ok(())
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        match &self.rx {
+            Some(rx) => Ok(rx.ready_bytes() as u64),
+            None => Ok(0),
+        }
+    }
+}
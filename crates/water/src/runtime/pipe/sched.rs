@@ -0,0 +1,334 @@
+//! The scheduler of the WATMs in the sync runtime, doing `poll_oneoff` for both the in-process pipes
+//! and the files backed by an fd (e.g. the network connections).
+//!
+//! The WATM thread waits on a Condvar while it is polling only pipes. Once fds are polled as well,
+//! it blocks in `poll(2)` with a wake-up socket of its own thread added, which the pipes write to on a change
+//! (only the wake-up goes through the kernel there, the bytes never do).
+//!
+//! A WATM interrupted by the Host (see `runtime::interrupt`) is trapped the next time it calls into the scheduler.
+
+use std::{
+    cell::RefCell,
+    os::fd::{AsRawFd, RawFd},
+    os::unix::net::UnixStream,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use wasi_common::{
+    sched::{Poll, RwEventFlags, Subscription, WasiSched},
+    Error, ErrorExt,
+};
+
+use crate::runtime::{
+    interrupt::Interrupt,
+    pipe::{PipeStream, Readiness},
+    *,
+};
+
+/// The scheduler to build the WasiCtx of the WATMs in the sync runtime with, trapping them once `interrupt` is requested
+pub fn sched_ctx(interrupt: Interrupt) -> Box<dyn WasiSched> {
+    Box::new(PipeSched { interrupt })
+}
+
+struct PipeSched {
+    interrupt: Interrupt,
+}
+
+#[async_trait::async_trait]
+impl WasiSched for PipeSched {
+    async fn poll_oneoff<'a>(&self, poll: &mut Poll<'a>) -> Result<(), Error> {
+        poll_oneoff(poll, &self.interrupt)
+    }
+
+    async fn sched_yield(&self) -> Result<(), Error> {
+        self.interrupt.check()?;
+        std::thread::yield_now();
+        Ok(())
+    }
+
+    async fn sleep(&self, duration: Duration) -> Result<(), Error> {
+        // sleeping on a Condvar, for an interrupt to wake the thread up
+        let waiter = Waiter::new(None);
+        let subscribed = self.interrupt.subscribe(&waiter);
+        if subscribed.is_ok() {
+            waiter.wait(Some(duration));
+        }
+        self.interrupt.unsubscribe(&waiter);
+        subscribed?;
+        self.interrupt.check()
+    }
+}
+
+thread_local! {
+    /// the wake-up socket pair of the thread, created the first time it polls pipes together with fds
+    static WAKE_IO: RefCell<Option<(UnixStream, Arc<UnixStream>)>> = const { RefCell::new(None) };
+}
+
+/// A thread waiting in `poll_oneoff`, registered in the pipes it polls to be woken up on any change
+pub struct Waiter {
+    woken: Mutex<bool>,
+    cond: Condvar,
+
+    /// the writing end of the wake-up socket, when the thread is blocked in `poll(2)`
+    wake_io: Option<Arc<UnixStream>>,
+}
+
+impl Waiter {
+    pub(crate) fn new(wake_io: Option<Arc<UnixStream>>) -> Arc<Self> {
+        Arc::new(Waiter {
+            woken: Mutex::new(false),
+            cond: Condvar::new(),
+            wake_io,
+        })
+    }
+
+    pub(crate) fn wake(&self) {
+        let mut woken = self.woken.lock().unwrap_or_else(|e| e.into_inner());
+        if *woken {
+            return;
+        }
+        *woken = true;
+        self.cond.notify_all();
+
+        if let Some(wake_io) = &self.wake_io {
+            // a full socket already has a wake-up pending
+            let _ = (&**wake_io).write(&[1]);
+        }
+    }
+
+    pub(crate) fn wait(&self, timeout: Option<Duration>) {
+        let woken = self.woken.lock().unwrap_or_else(|e| e.into_inner());
+        let _woken = match timeout {
+            Some(timeout) => {
+                self.cond
+                    .wait_timeout_while(woken, timeout, |woken| !*woken)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0
+            }
+            None => self
+                .cond
+                .wait_while(woken, |woken| !*woken)
+                .unwrap_or_else(|e| e.into_inner()),
+        };
+    }
+}
+
+/// A subscription of `poll_oneoff`, sorted by whether it is for a pipe or a file with an fd
+enum Polled<'a> {
+    Pipe(&'a PipeStream, bool),
+    Fd(RawFd, bool),
+}
+
+fn poll_oneoff(poll: &mut Poll<'_>, interrupt: &Interrupt) -> Result<(), Error> {
+    interrupt.check()?;
+    if poll.is_empty() {
+        return Ok(());
+    }
+
+    let mut polled = Vec::new();
+    for s in poll.rw_subscriptions() {
+        let (file, read) = match s {
+            Subscription::Read(sub) => (sub.file, true),
+            Subscription::Write(sub) => (sub.file, false),
+            Subscription::MonotonicClock(_) => unreachable!(),
+        };
+
+        match file.as_any().downcast_ref::<PipeStream>() {
+            Some(pipe) => polled.push(Polled::Pipe(pipe, read)),
+            None => {
+                let fd = file
+                    .pollable()
+                    .ok_or(Error::invalid_argument().context("file is not pollable"))?;
+                polled.push(Polled::Fd(fd.as_raw_fd(), read));
+            }
+        }
+    }
+
+    let deadline = poll
+        .earliest_clock_deadline()
+        .map(|clock| Instant::now() + clock.duration_until().unwrap_or(Duration::ZERO));
+
+    // (the readiness of the pipes, the revents of the fds), in the order of the subscriptions
+    let (pipes, revents) = loop {
+        let (pipes, revents) = wait_ready(&polled, deadline, interrupt)?;
+        interrupt.check()?;
+
+        let ready = pipes
+            .iter()
+            .any(|readiness| !matches!(readiness, Readiness::NotReady))
+            || revents.iter().any(|revents| *revents != 0);
+        let timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+        // woken up by a pipe that is not ready for what is polled (e.g. written when polled for writing)
+        if ready || timed_out {
+            break (pipes, revents);
+        }
+    };
+
+    let (mut pipes, mut revents) = (pipes.into_iter(), revents.into_iter());
+    for (s, polled) in poll.rw_subscriptions().zip(&polled) {
+        let sub = match s {
+            Subscription::Read(sub) | Subscription::Write(sub) => sub,
+            Subscription::MonotonicClock(_) => unreachable!(),
+        };
+
+        match polled {
+            Polled::Pipe(_, _) => match pipes.next() {
+                Some(Readiness::Ready(nbytes)) => sub.complete(nbytes, RwEventFlags::empty()),
+                Some(Readiness::HangUp(nbytes)) => sub.complete(nbytes, RwEventFlags::HANGUP),
+                _ => {}
+            },
+            Polled::Fd(_, read) => {
+                let revents = revents.next().unwrap_or(0);
+                let nbytes = match read {
+                    true => std::cmp::max(sub.file.num_ready_bytes()?, 1),
+                    false => 0,
+                };
+
+                if revents & libc::POLLNVAL != 0 {
+                    sub.error(Error::badf());
+                } else if revents & libc::POLLERR != 0 {
+                    sub.error(Error::io());
+                } else if revents & libc::POLLHUP != 0 {
+                    sub.complete(nbytes, RwEventFlags::HANGUP);
+                } else if revents != 0 {
+                    sub.complete(nbytes, RwEventFlags::empty());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Wait until any of the `polled` is ready or the `deadline` has passed,
+/// returning the readiness of the pipes and the revents of the fds (each in the order of `polled`).
+fn wait_ready(
+    polled: &[Polled<'_>],
+    deadline: Option<Instant>,
+    interrupt: &Interrupt,
+) -> Result<(Vec<Readiness>, Vec<libc::c_short>), Error> {
+    let with_fds = polled.iter().any(|p| matches!(p, Polled::Fd(_, _)));
+
+    let (wake_fd, wake_io) = match with_fds {
+        true => {
+            let (wake_fd, wake_io) = wake_io()?;
+            (Some(wake_fd), Some(wake_io))
+        }
+        false => (None, None),
+    };
+    let waiter = Waiter::new(wake_io);
+
+    let mut pipes = Vec::new();
+    let mut pollfds = Vec::new();
+    for p in polled {
+        match p {
+            Polled::Pipe(pipe, read) => pipes.push(pipe.subscribe(&waiter, *read)?),
+            Polled::Fd(fd, read) => pollfds.push(libc::pollfd {
+                fd: *fd,
+                events: match read {
+                    true => libc::POLLIN,
+                    false => libc::POLLOUT,
+                },
+                revents: 0,
+            }),
+        }
+    }
+
+    let pipe_ready = pipes
+        .iter()
+        .any(|readiness| !matches!(readiness, Readiness::NotReady));
+    let timeout = match pipe_ready {
+        true => Some(Duration::ZERO),
+        false => deadline.map(|deadline| deadline.saturating_duration_since(Instant::now())),
+    };
+
+    let result = interrupt.subscribe(&waiter).and_then(|_| match wake_fd {
+        Some(wake_fd) => poll_fds(&mut pollfds, wake_fd, timeout),
+        None => {
+            waiter.wait(timeout);
+            Ok(())
+        }
+    });
+
+    interrupt.unsubscribe(&waiter);
+    for p in polled {
+        if let Polled::Pipe(pipe, _) = p {
+            pipe.unsubscribe(&waiter);
+        }
+    }
+    result?;
+
+    // the readiness of the pipes might have changed while waiting, check them again
+    let mut pipes = Vec::new();
+    for p in polled {
+        if let Polled::Pipe(pipe, read) = p {
+            pipes.push(pipe.readiness(*read)?);
+        }
+    }
+
+    Ok((pipes, pollfds.iter().map(|pollfd| pollfd.revents).collect()))
+}
+
+/// `poll(2)` the fds together with the wake-up socket `wake_fd`, draining the wake-up socket afterwards
+fn poll_fds(
+    pollfds: &mut Vec<libc::pollfd>,
+    wake_fd: RawFd,
+    timeout: Option<Duration>,
+) -> Result<(), Error> {
+    pollfds.push(libc::pollfd {
+        fd: wake_fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+
+    let timeout = match timeout {
+        // rounding up, not to wake up right before the deadline
+        Some(timeout) => {
+            std::cmp::min(timeout.as_nanos().div_ceil(1_000_000), i32::MAX as u128) as i32
+        }
+        None => -1,
+    };
+
+    let result = loop {
+        match unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout) } {
+            n if n >= 0 => break Ok(()),
+            _ => match std::io::Error::last_os_error() {
+                e if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                e => break Err(e.into()),
+            },
+        }
+    };
+
+    if let Some(wake) = pollfds.pop() {
+        if wake.revents != 0 {
+            WAKE_IO.with(|wake_io| {
+                if let Some((wake_rx, _)) = &*wake_io.borrow() {
+                    let mut buf = [0; 64];
+                    while matches!((&*wake_rx).read(&mut buf), Ok(n) if n > 0) {}
+                }
+            });
+        }
+    }
+
+    result
+}
+
+/// The wake-up socket pair of the current thread, as (the fd polled, the end written to wake the thread up)
+fn wake_io() -> Result<(RawFd, Arc<UnixStream>), Error> {
+    WAKE_IO.with(|wake_io| {
+        let mut wake_io = wake_io.borrow_mut();
+        if wake_io.is_none() {
+            let (wake_rx, wake_tx) = UnixStream::pair()?;
+            wake_rx.set_nonblocking(true)?;
+            wake_tx.set_nonblocking(true)?;
+            *wake_io = Some((wake_rx, Arc::new(wake_tx)));
+        }
+
+        match &*wake_io {
+            Some((wake_rx, wake_tx)) => Ok((wake_rx.as_raw_fd(), Arc::clone(wake_tx))),
+            None => unreachable!(),
+        }
+    })
+}
@@ -0,0 +1,258 @@
+//! A relay server driving a v0 WATER `Relay` for many clients: it keeps accepting on the listener of the WATM,
+//! and each association (an accepted client and its connection to the remote) is handled by its own WATM instance,
+//! migrated from the listening one with `keep_listen`. An association failing only drops its own instance,
+//! the server keeps serving the others.
+//!
+//! The number of associations running at once can be limited, when the limit is reached the server stops accepting
+//! until one of them ends, leaving the new clients waiting in the backlog of the listener.
+//! A [`RelayShutdown`] handle stops the server from another thread, canceling the workers still running.
+
+use std::{
+    collections::HashMap,
+    net::{Shutdown, SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use socket2::SockRef;
+
+use crate::runtime::{client::WATERClient, *};
+
+/// Time to wait before creating the instance of the next association again after it failed
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// A relay server running one WATM instance per association of the `Relay` created with `conf`
+pub struct RelayServer {
+    /// the listening instance, which the instances of the associations are migrated from
+    water_client: WATERClient,
+    max_connections: Option<usize>,
+    state: Arc<RelayState>,
+}
+
+/// A handle to stop the [`RelayServer`] it was taken from
+#[derive(Clone)]
+pub struct RelayShutdown {
+    state: Arc<RelayState>,
+}
+
+/// State shared between the server, the threads waiting on the workers and the shutdown handles
+struct RelayState {
+    /// the clients whose worker is running, by association id
+    active: Mutex<ActiveRelays>,
+
+    /// notified when an association ends or the shutdown is requested
+    changed: Condvar,
+
+    shutdown: AtomicBool,
+
+    /// a handle to the listener of the WATM, shut down to wake up the accept
+    listener: TcpListener,
+}
+
+#[derive(Default)]
+struct ActiveRelays {
+    next_id: u64,
+    clients: HashMap<u64, WATERClient>,
+}
+
+impl RelayServer {
+    /// Create the `Relay` with `conf` and listen on the local address of its config
+    pub fn listen(conf: WATERConfig) -> Result<Self, anyhow::Error> {
+        if conf.client_type != WaterBinType::Relay {
+            return Err(anyhow::anyhow!(
+                "[HOST] RelayServer needs a Relay config, got {:?}",
+                conf.client_type
+            ));
+        }
+
+        let mut water_client = WATERClient::new(conf)?;
+        water_client.listen()?;
+        let listener = water_client.try_clone_listener()?;
+
+        Ok(RelayServer {
+            water_client,
+            max_connections: None,
+            state: Arc::new(RelayState {
+                active: Mutex::new(ActiveRelays::default()),
+                changed: Condvar::new(),
+                shutdown: AtomicBool::new(false),
+                listener,
+            }),
+        })
+    }
+
+    /// Run at most `max` associations at once, the next clients wait in the backlog until one of them ends
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max.max(1));
+        self
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr, anyhow::Error> {
+        self.water_client.local_addr()
+    }
+
+    /// A handle to stop the server, e.g. from a signal handler or another thread
+    pub fn shutdown_handle(&self) -> RelayShutdown {
+        RelayShutdown {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// Number of the associations currently running
+    pub fn active_connections(&self) -> usize {
+        self.state.lock_active().clients.len()
+    }
+
+    /// Accept and relay the clients until the shutdown is requested, then cancel the workers still running
+    /// and wait for them to exit
+    pub fn serve(&mut self) -> Result<(), anyhow::Error> {
+        info!("[HOST] RelayServer listening on {}", self.local_addr()?);
+
+        let res = self.serve_relays();
+        self.state.stop_workers();
+        res
+    }
+
+    fn serve_relays(&mut self) -> Result<(), anyhow::Error> {
+        loop {
+            if !self.state.wait_for_slot(self.max_connections) {
+                return Ok(());
+            }
+
+            // each association is handled by a new instance, dropped if anything fails before its worker runs
+            let mut water_client = match self.water_client.keep_listen() {
+                Ok(water_client) => water_client,
+                Err(e) => {
+                    info!(
+                        "[HOST] RelayServer failed to create the instance of the next association: {:#}",
+                        e
+                    );
+                    if !self.state.pause(RETRY_DELAY) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+
+            let associated = water_client.associate();
+            if self.state.is_shutdown() {
+                return Ok(());
+            }
+
+            let handle_water = match associated
+                .context("failed to associate")
+                .and_then(|_| water_client.cancel_with())
+                .and_then(|_| water_client.run_worker())
+            {
+                Ok(handle_water) => handle_water,
+                Err(e) => {
+                    info!("[HOST] RelayServer association failed: {:#}", e);
+                    continue;
+                }
+            };
+            let id = self.state.track(water_client);
+
+            let state = Arc::clone(&self.state);
+            std::thread::spawn(move || {
+                match handle_water.join() {
+                    Ok(Ok(_)) => info!("[HOST] RelayServer association {} closed", id),
+                    Ok(Err(e)) => info!("[HOST] RelayServer association {} failed: {:#}", id, e),
+                    Err(_) => info!("[HOST] RelayServer association {} panicked", id),
+                }
+                state.untrack(id);
+            });
+        }
+    }
+}
+
+impl RelayShutdown {
+    /// Stop accepting, and make `serve` cancel the running associations and return
+    pub fn shutdown(&self) -> Result<(), anyhow::Error> {
+        info!("[HOST] RelayServer shutting down ...");
+
+        self.state.shutdown.store(true, Ordering::SeqCst);
+        // notify with the lock held, so a server checking the flag before waiting can't miss it
+        drop(self.state.lock_active());
+        self.state.changed.notify_all();
+
+        // wakes up the accept blocked in the WATM, the clients still in the backlog are reset
+        SockRef::from(&self.state.listener)
+            .shutdown(Shutdown::Read)
+            .context("[HOST] RelayServer failed to shut down the listener")
+    }
+}
+
+impl RelayState {
+    fn lock_active(&self) -> MutexGuard<'_, ActiveRelays> {
+        // the map stays consistent even if a thread panicked holding the lock
+        self.active.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// Wait for `delay` unless the shutdown is requested meanwhile, returns false if it was
+    fn pause(&self, delay: Duration) -> bool {
+        let active = self.lock_active();
+        if self.is_shutdown() {
+            return false;
+        }
+        let _ = self
+            .changed
+            .wait_timeout_while(active, delay, |_| !self.is_shutdown());
+        !self.is_shutdown()
+    }
+
+    /// Block until fewer than `max` associations are running, returns false if the shutdown was requested
+    fn wait_for_slot(&self, max: Option<usize>) -> bool {
+        let mut active = self.lock_active();
+        loop {
+            if self.is_shutdown() {
+                return false;
+            }
+            match max {
+                Some(max) if active.clients.len() >= max => {
+                    active = self.changed.wait(active).unwrap_or_else(|e| e.into_inner());
+                }
+                _ => return true,
+            }
+        }
+    }
+
+    fn track(&self, water_client: WATERClient) -> u64 {
+        let mut active = self.lock_active();
+        let id = active.next_id;
+        active.next_id += 1;
+        active.clients.insert(id, water_client);
+        id
+    }
+
+    fn untrack(&self, id: u64) {
+        let water_client = self.lock_active().clients.remove(&id);
+        self.changed.notify_all();
+        // dropping the instance outside of the lock
+        drop(water_client);
+    }
+
+    /// Cancel the running associations, and wait for their workers to exit
+    fn stop_workers(&self) {
+        let mut active = self.lock_active();
+        for (id, water_client) in active.clients.iter_mut() {
+            if let Err(e) = water_client.cancel() {
+                info!(
+                    "[HOST] RelayServer failed to cancel association {}: {:#}",
+                    id, e
+                );
+            }
+        }
+
+        while !active.clients.is_empty() {
+            active = self.changed.wait(active).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
@@ -7,8 +7,27 @@ pub struct WATERRunner<Host> {
     pub core: H2O<Host>, // core WASM runtime (engine, linker, instance, store, module)
 }
 
+/// A handle to stop a `WATERRunner` from another thread while it is running, returned by `WATERRunner::handle`
+#[derive(Clone)]
+pub struct RunnerHandle {
+    interrupt: Interrupt,
+}
+
+impl RunnerHandle {
+    /// Stop the runner: the WATM is trapped the next time it polls / sleeps, or right away if it is running wasm code only,
+    /// and `run` returns `Ok`. The runner can't run again once stopped.
+    pub fn stop(&self) {
+        info!("[HOST] WATERRunner stopping...");
+        self.interrupt.request();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.interrupt.is_requested()
+    }
+}
+
 impl WATERRunner<Host> {
-    /// Run the entry function
+    /// Run the entry function, until it returns or the runner is stopped with its `RunnerHandle`
     pub fn run(&mut self, conf: &WATERConfig) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERRunner running...");
 
@@ -29,12 +48,23 @@ impl WATERRunner<Host> {
             ))?;
         match fnc.call(&mut *store, &[], &mut []) {
             Ok(_) => {}
-            Err(e) => return Err(anyhow::Error::msg(format!("run function failed: {}", e))),
+            // the trap of a stopped runner is the way it exits
+            Err(e) if interrupt::is_interrupted(&e) => {
+                info!("[HOST] WATERRunner stopped");
+            }
+            Err(e) => return Err(store.data().guest_error(&conf.entry_fn, e)),
         }
 
         Ok(())
     }
 
+    /// The handle to stop this runner, usable while `run` holds the store
+    pub fn handle(&self) -> RunnerHandle {
+        RunnerHandle {
+            interrupt: self.core.interrupt.clone(),
+        }
+    }
+
     pub fn init(_conf: &WATERConfig, core: H2O<Host>) -> Result<Self, anyhow::Error> {
         info!("[HOST] WATERRunner init...");
 
@@ -0,0 +1,198 @@
+//! Ed25519 signature verification of the WATM binaries before they are loaded.
+//!
+//! Verification is enabled by setting `trusted_keys` in the [`WATERConfig`], then a module is only loaded
+//! when it carries a signature of one of the trusted keys, either:
+//! - embedded in a custom section named [`SIGNATURE_SECTION`], signing the module without that section, or
+//! - detached in a file next to the module with `.sig` appended to its path, signing the whole module.
+//!
+//! Both signatures are the raw 64 bytes of an Ed25519 signature.
+
+use std::fmt;
+
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use crate::{config::registry, runtime::*};
+
+/// Name of the custom section carrying the embedded signature
+pub const SIGNATURE_SECTION: &str = "water-signature";
+
+/// Extension appended to the module path for the detached signature
+pub const SIGNATURE_EXT: &str = "sig";
+
+/// Error returned when a WATM binary is refused by the signature verification
+#[derive(Debug)]
+pub enum SignatureError {
+    /// Neither an embedded nor a detached signature is found for the module
+    Unsigned(String),
+
+    /// The signature doesn't match the module with any of the trusted keys (e.g. the module was tampered)
+    Invalid(String),
+
+    /// A trusted key is not a valid Ed25519 public key
+    MalformedKey,
+
+    /// The module binary or its signature can't be parsed
+    Malformed(String),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Unsigned(path) => write!(f, "WATM module {} is not signed", path),
+            SignatureError::Invalid(path) => write!(
+                f,
+                "WATM module {} is not signed by any of the trusted keys",
+                path
+            ),
+            SignatureError::MalformedKey => write!(f, "trusted key is not a valid Ed25519 key"),
+            SignatureError::Malformed(reason) => write!(f, "malformed WATM signature: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// Read the WATM module at `conf.filepath` and compile it, verifying its signature first if `conf.trusted_keys` is set
+/// and its digest if `conf.sha256` is set
+pub fn load_module(engine: &Engine, conf: &WATERConfig) -> Result<Module, anyhow::Error> {
+    Module::new(engine, read_module(conf)?)
+}
+
+/// Read the WATM binary (core module or component) at `conf.filepath`, verifying its signature if `conf.trusted_keys` is set
+/// and its digest if `conf.sha256` is set, on the same bytes returned to be compiled
+pub fn read_module(conf: &WATERConfig) -> Result<Vec<u8>, anyhow::Error> {
+    let bytes = std::fs::read(&conf.filepath)
+        .context(format!("failed to read WATM module {}", conf.filepath))?;
+
+    if let Some(expected) = &conf.sha256 {
+        let digest = registry::digest_hex(&bytes);
+        if !digest.eq_ignore_ascii_case(expected) {
+            return Err(anyhow::anyhow!(
+                "sha256 mismatch for {}: expected {}, got {}",
+                conf.filepath,
+                expected,
+                digest
+            ));
+        }
+    }
+
+    if !conf.trusted_keys.is_empty() {
+        verify(&conf.filepath, &bytes, &conf.trusted_keys)?;
+        info!("[HOST] WATERCore verified signature of {}", conf.filepath);
+    }
+
+    Ok(bytes)
+}
+
+/// Verify the embedded or detached signature of the module at `path` with contents `bytes`
+pub fn verify(path: &str, bytes: &[u8], trusted_keys: &[[u8; 32]]) -> Result<(), SignatureError> {
+    let keys = trusted_keys
+        .iter()
+        .map(VerifyingKey::from_bytes)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| SignatureError::MalformedKey)?;
+
+    let (signature, message) = match extract_embedded(bytes)? {
+        Some((signature, stripped)) => (signature, stripped),
+        None => match std::fs::read(format!("{}.{}", path, SIGNATURE_EXT)) {
+            Ok(signature) => (signature, bytes.to_vec()),
+            Err(_) => return Err(SignatureError::Unsigned(path.to_string())),
+        },
+    };
+
+    let signature = Signature::from_slice(&signature)
+        .map_err(|_| SignatureError::Malformed("signature is not 64 bytes".into()))?;
+
+    if keys
+        .iter()
+        .any(|key| key.verify_strict(&message, &signature).is_ok())
+    {
+        Ok(())
+    } else {
+        Err(SignatureError::Invalid(path.to_string()))
+    }
+}
+
+/// Append the signature (of `module`) as a custom section to `module`, as done by the build pipeline
+pub fn embed_signature(module: &[u8], signature: &[u8; 64]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    write_leb128(&mut payload, SIGNATURE_SECTION.len() as u32);
+    payload.extend_from_slice(SIGNATURE_SECTION.as_bytes());
+    payload.extend_from_slice(signature);
+
+    let mut signed = module.to_vec();
+    signed.push(0); // custom section id
+    write_leb128(&mut signed, payload.len() as u32);
+    signed.extend_from_slice(&payload);
+    signed
+}
+
+/// (signature, module without the signature section)
+type EmbeddedSignature = (Vec<u8>, Vec<u8>);
+
+/// Find the signature custom section, returns the signature and the module without that section
+fn extract_embedded(bytes: &[u8]) -> Result<Option<EmbeddedSignature>, SignatureError> {
+    // the component model / text format modules are left to the detached signature
+    if bytes.len() < 8 || &bytes[0..4] != b"\0asm" {
+        return Ok(None);
+    }
+
+    let malformed = || SignatureError::Malformed("truncated WASM section".into());
+
+    let mut pos = 8;
+    while pos < bytes.len() {
+        let start = pos;
+        let id = bytes[pos];
+        pos += 1;
+        let size = read_leb128(bytes, &mut pos).ok_or_else(malformed)? as usize;
+        let end = pos.checked_add(size).ok_or_else(malformed)?;
+        if end > bytes.len() {
+            return Err(malformed());
+        }
+
+        if id == 0 {
+            let mut name_pos = pos;
+            let name_len = read_leb128(bytes, &mut name_pos).ok_or_else(malformed)? as usize;
+            let name_end = name_pos.checked_add(name_len).ok_or_else(malformed)?;
+            if name_end > end {
+                return Err(malformed());
+            }
+
+            if &bytes[name_pos..name_end] == SIGNATURE_SECTION.as_bytes() {
+                let signature = bytes[name_end..end].to_vec();
+                let mut stripped = bytes[..start].to_vec();
+                stripped.extend_from_slice(&bytes[end..]);
+                return Ok(Some((signature, stripped)));
+            }
+        }
+
+        pos = end;
+    }
+
+    Ok(None)
+}
+
+fn read_leb128(bytes: &[u8], pos: &mut usize) -> Option<u32> {
+    let mut result: u32 = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        result |= ((byte & 0x7f) as u32).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(result);
+        }
+    }
+    None
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,170 @@
+//! Creation of the sockets the Host makes for the WATM (dialed, listening and accepted ones),
+//! with the [`SocketOptions`] of the transport applied.
+
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+
+use crate::config::SocketOptions;
+
+/// Backlog of the listeners when not configured, the same as `std::net::TcpListener::bind`
+const DEFAULT_LISTEN_BACKLOG: i32 = 128;
+
+/// Connect to `addr` with the options, trying each address it resolves to as `TcpStream::connect` does
+pub fn connect<A: ToSocketAddrs>(addr: A, opts: &SocketOptions) -> io::Result<TcpStream> {
+    each_addr(addr, |addr| {
+        let socket = dialer(addr, opts)?;
+        socket.connect(&addr.into())?;
+        Ok(socket.into())
+    })
+}
+
+/// Connect to `addr` with the options without blocking the runtime, returns a blocking std stream
+#[cfg(feature = "async")]
+pub async fn connect_async(addr: &str, opts: &SocketOptions) -> io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in tokio::net::lookup_host(addr).await? {
+        let socket = dialer(addr, opts)?;
+        socket.set_nonblocking(true)?;
+        let socket = tokio::net::TcpSocket::from_std_stream(socket.into());
+        match socket.connect(addr).await {
+            Ok(conn) => {
+                let conn = conn.into_std()?;
+                conn.set_nonblocking(false)?;
+                return Ok(conn);
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(unresolved))
+}
+
+/// Listen on `addr` with the options, trying each address it resolves to as `TcpListener::bind` does
+pub fn listen<A: ToSocketAddrs>(addr: A, opts: &SocketOptions) -> io::Result<TcpListener> {
+    each_addr(addr, |addr| {
+        let socket = new_socket(addr, opts)?;
+        socket.set_reuse_address(opts.reuse_address.unwrap_or(true))?;
+        socket.bind(&addr.into())?;
+        socket.listen(opts.listen_backlog.unwrap_or(DEFAULT_LISTEN_BACKLOG))?;
+        Ok(socket.into())
+    })
+}
+
+/// Apply the per-connection options to a connection accepted from a listener created by [`listen`]
+pub fn apply_accepted(stream: &TcpStream, opts: &SocketOptions) -> io::Result<()> {
+    apply_connection(&SockRef::from(stream), opts)
+}
+
+/// A socket ready to dial `addr`, bound to the source address if one is configured
+fn dialer(addr: SocketAddr, opts: &SocketOptions) -> io::Result<Socket> {
+    let socket = new_socket(addr, opts)?;
+    if let Some(reuse) = opts.reuse_address {
+        socket.set_reuse_address(reuse)?;
+    }
+    if let Some(ip) = opts.source_address {
+        socket.bind(&SocketAddr::new(ip, 0).into())?;
+    }
+    Ok(socket)
+}
+
+/// A new TCP socket for `addr` with the options which have to be set before binding / connecting
+fn new_socket(addr: SocketAddr, opts: &SocketOptions) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+
+    if let Some(reuse) = opts.reuse_port {
+        socket.set_reuse_port(reuse)?;
+    }
+    if let Some(mark) = opts.mark {
+        set_mark(&socket, mark)?;
+    }
+    if let Some(device) = &opts.bind_device {
+        bind_device(&socket, device)?;
+    }
+
+    apply_connection(&socket, opts)?;
+    Ok(socket)
+}
+
+/// The options of each connection: nodelay, keepalive and buffer sizes
+fn apply_connection(socket: &Socket, opts: &SocketOptions) -> io::Result<()> {
+    if let Some(nodelay) = opts.nodelay {
+        socket.set_tcp_nodelay(nodelay)?;
+    }
+
+    if opts.keepalive_secs.is_some()
+        || opts.keepalive_interval_secs.is_some()
+        || opts.keepalive_retries.is_some()
+    {
+        let mut keepalive = TcpKeepalive::new();
+        if let Some(secs) = opts.keepalive_secs {
+            keepalive = keepalive.with_time(Duration::from_secs(secs));
+        }
+        if let Some(secs) = opts.keepalive_interval_secs {
+            keepalive = keepalive.with_interval(Duration::from_secs(secs));
+        }
+        if let Some(retries) = opts.keepalive_retries {
+            keepalive = keepalive.with_retries(retries);
+        }
+        socket.set_tcp_keepalive(&keepalive)?;
+    }
+
+    if let Some(size) = opts.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = opts.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_mark(socket: &Socket, mark: u32) -> io::Result<()> {
+    socket.set_mark(mark)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn set_mark(_socket: &Socket, _mark: u32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_MARK is only supported on Linux",
+    ))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &Socket, device: &str) -> io::Result<()> {
+    socket.bind_device(Some(device.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn bind_device(_socket: &Socket, _device: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "SO_BINDTODEVICE is only supported on Linux",
+    ))
+}
+
+/// Run `f` on each address `addr` resolves to, until one succeeds
+fn each_addr<A: ToSocketAddrs, T>(
+    addr: A,
+    mut f: impl FnMut(SocketAddr) -> io::Result<T>,
+) -> io::Result<T> {
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        match f(addr) {
+            Ok(t) => return Ok(t),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(unresolved))
+}
+
+fn unresolved() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "could not resolve to any addresses",
+    )
+}
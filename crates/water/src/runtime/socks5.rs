@@ -0,0 +1,245 @@
+//! A SOCKS5 front-end (RFC 1928) for the WATER dialers, so any transport can be used by the applications speaking SOCKS5
+//! (browsers, curl, ...) without the WATM implementing SOCKS itself.
+//!
+//! Each accepted client is handed to its own thread, which does the handshake (with the optional username / password
+//! auth of RFC 1929), creates a new `WATERClient` dialer with the config of the server, connects it to the destination
+//! requested and tunnels the client through it. The WATM is compiled once by the server, each dialer is a new instance of it.
+//! Only `CONNECT` is supported.
+
+use std::{
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+};
+
+use crate::runtime::{client::WATERClient, core::CompiledWatm, *};
+
+const SOCKS_VERSION: u8 = 0x05;
+
+/// Version of the username / password sub-negotiation (RFC 1929)
+const AUTH_VERSION: u8 = 0x01;
+
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NO_ACCEPTABLE: u8 = 0xff;
+
+const CMD_CONNECT: u8 = 0x01;
+
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+const REPLY_SUCCEEDED: u8 = 0x00;
+const REPLY_GENERAL_FAILURE: u8 = 0x01;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REPLY_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+/// Username and password the clients have to authenticate with
+#[derive(Debug, Clone)]
+pub struct Socks5Auth {
+    pub username: String,
+    pub password: String,
+}
+
+/// A SOCKS5 server tunneling each connection through a new WATER dialer created with `conf`
+pub struct Socks5Server {
+    listener: TcpListener,
+    conf: WATERConfig,
+    watm: CompiledWatm,
+    auth: Option<Arc<Socks5Auth>>,
+}
+
+impl Socks5Server {
+    /// Listen on `addr` for the SOCKS5 clients, `conf` is the config of the dialers
+    pub fn bind(addr: impl ToSocketAddrs, conf: WATERConfig) -> Result<Self, anyhow::Error> {
+        let watm = CompiledWatm::compile(&conf)?;
+        let listener = TcpListener::bind(addr).context("[HOST] SOCKS5 server failed to bind")?;
+
+        Ok(Socks5Server {
+            listener,
+            conf,
+            watm,
+            auth: None,
+        })
+    }
+
+    /// Require the clients to authenticate with `username` and `password`
+    pub fn with_auth(mut self, username: String, password: String) -> Self {
+        self.auth = Some(Arc::new(Socks5Auth { username, password }));
+        self
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr, anyhow::Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept the clients and serve each of them on a new thread, only returns if the listener fails
+    pub fn serve(&self) -> Result<(), anyhow::Error> {
+        info!(
+            "[HOST] SOCKS5 server listening on {}",
+            self.listener.local_addr()?
+        );
+
+        loop {
+            let (conn, peer) = self.listener.accept()?;
+
+            let conf = self.conf.clone();
+            let watm = self.watm.clone();
+            let auth = self.auth.clone();
+            std::thread::spawn(
+                move || match serve_client(conf, &watm, auth.as_deref(), conn) {
+                    Ok(_) => info!("[HOST] SOCKS5 client {} closed", peer),
+                    Err(e) => info!("[HOST] SOCKS5 client {} failed: {:#}", peer, e),
+                },
+            );
+        }
+    }
+}
+
+/// Serve one SOCKS5 client: handshake, dial with a new WATERClient instance of `watm`, then tunnel the client through it
+pub fn serve_client(
+    conf: WATERConfig,
+    watm: &CompiledWatm,
+    auth: Option<&Socks5Auth>,
+    mut conn: TcpStream,
+) -> Result<(), anyhow::Error> {
+    handshake(&mut conn, auth)?;
+    let destination = read_request(&mut conn)?;
+
+    info!("[HOST] SOCKS5 CONNECT {}", destination);
+
+    let dialed = WATERClient::from_compiled(conf, watm).and_then(|mut water_client| {
+        water_client.connect_to(&destination)?;
+        Ok(water_client)
+    });
+    let mut water_client = match dialed {
+        Ok(water_client) => water_client,
+        Err(e) => {
+            let _ = reply(&mut conn, REPLY_GENERAL_FAILURE);
+            return Err(e.context(format!("[HOST] SOCKS5 failed to dial for {}", destination)));
+        }
+    };
+    reply(&mut conn, REPLY_SUCCEEDED)?;
+
+    water_client.tunnel(conn)
+}
+
+/// Negotiate the auth method, and authenticate the client if `auth` is required
+fn handshake(conn: &mut TcpStream, auth: Option<&Socks5Auth>) -> Result<(), anyhow::Error> {
+    let mut header = [0u8; 2];
+    conn.read_exact(&mut header)?;
+    if header[0] != SOCKS_VERSION {
+        return Err(anyhow::anyhow!(
+            "[HOST] SOCKS5 unsupported version {}",
+            header[0]
+        ));
+    }
+
+    let mut methods = vec![0u8; header[1] as usize];
+    conn.read_exact(&mut methods)?;
+
+    let method = match auth {
+        Some(_) => METHOD_USERNAME_PASSWORD,
+        None => METHOD_NO_AUTH,
+    };
+    if !methods.contains(&method) {
+        conn.write_all(&[SOCKS_VERSION, METHOD_NO_ACCEPTABLE])?;
+        return Err(anyhow::anyhow!(
+            "[HOST] SOCKS5 no acceptable auth method in {:?}",
+            methods
+        ));
+    }
+    conn.write_all(&[SOCKS_VERSION, method])?;
+
+    match auth {
+        Some(auth) => authenticate(conn, auth),
+        None => Ok(()),
+    }
+}
+
+/// The username / password sub-negotiation of RFC 1929
+fn authenticate(conn: &mut TcpStream, auth: &Socks5Auth) -> Result<(), anyhow::Error> {
+    let mut header = [0u8; 2];
+    conn.read_exact(&mut header)?;
+    if header[0] != AUTH_VERSION {
+        return Err(anyhow::anyhow!(
+            "[HOST] SOCKS5 unsupported auth version {}",
+            header[0]
+        ));
+    }
+
+    let mut username = vec![0u8; header[1] as usize];
+    conn.read_exact(&mut username)?;
+
+    let mut len = [0u8; 1];
+    conn.read_exact(&mut len)?;
+    let mut password = vec![0u8; len[0] as usize];
+    conn.read_exact(&mut password)?;
+
+    if username != auth.username.as_bytes() || password != auth.password.as_bytes() {
+        conn.write_all(&[AUTH_VERSION, 0x01])?;
+        let _ = conn.shutdown(Shutdown::Both);
+        return Err(anyhow::anyhow!("[HOST] SOCKS5 authentication failed"));
+    }
+
+    conn.write_all(&[AUTH_VERSION, 0x00])?;
+    Ok(())
+}
+
+/// Read the request of the client, returns the destination as address:port
+fn read_request(conn: &mut TcpStream) -> Result<String, anyhow::Error> {
+    let mut header = [0u8; 4];
+    conn.read_exact(&mut header)?;
+    if header[0] != SOCKS_VERSION {
+        return Err(anyhow::anyhow!(
+            "[HOST] SOCKS5 unsupported version {}",
+            header[0]
+        ));
+    }
+
+    if header[1] != CMD_CONNECT {
+        reply(conn, REPLY_COMMAND_NOT_SUPPORTED)?;
+        return Err(anyhow::anyhow!(
+            "[HOST] SOCKS5 unsupported command {}",
+            header[1]
+        ));
+    }
+
+    let host = match header[3] {
+        ATYP_IPV4 => {
+            let mut addr = [0u8; 4];
+            conn.read_exact(&mut addr)?;
+            std::net::Ipv4Addr::from(addr).to_string()
+        }
+        ATYP_IPV6 => {
+            let mut addr = [0u8; 16];
+            conn.read_exact(&mut addr)?;
+            format!("[{}]", std::net::Ipv6Addr::from(addr))
+        }
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            conn.read_exact(&mut len)?;
+            let mut domain = vec![0u8; len[0] as usize];
+            conn.read_exact(&mut domain)?;
+            String::from_utf8(domain).context("[HOST] SOCKS5 invalid domain")?
+        }
+        atyp => {
+            reply(conn, REPLY_ADDRESS_TYPE_NOT_SUPPORTED)?;
+            return Err(anyhow::anyhow!(
+                "[HOST] SOCKS5 unsupported address type {}",
+                atyp
+            ));
+        }
+    };
+
+    let mut port = [0u8; 2];
+    conn.read_exact(&mut port)?;
+
+    Ok(format!("{}:{}", host, u16::from_be_bytes(port)))
+}
+
+/// Reply to the request with `code`, the bound address is left unspecified
+fn reply(conn: &mut TcpStream, code: u8) -> Result<(), anyhow::Error> {
+    conn.write_all(&[SOCKS_VERSION, code, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0])?;
+    Ok(())
+}
@@ -0,0 +1,485 @@
+//! Per-connection traffic statistics for the WATER runtime.
+//!
+//! Every WATM instance (`H2O` core) owns a `ConnStats`, which is shared with the `Host` stored in the
+//! wasmtime `Store`, so counters can be updated from both the caller side (`WATERClient::read` / `write`)
+//! and the network side (sockets pushed into the WATM by the Host exported functions) without
+//! locking the store -- which is held by the worker thread for its whole lifetime.
+//!
+//! Counters are also aggregated per loaded WATM module (keyed by the `.wasm` path), so the
+//! overhead of one transport can be compared against another.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    io::{IoSlice, IoSliceMut},
+    mem::ManuallyDrop,
+    net::SocketAddr,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use once_cell::sync::Lazy;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags},
+    Error, SystemTimeSpec, WasiFile,
+};
+
+/// Aggregated counters of every loaded WATM module, keyed by the path of the `.wasm` binary
+static MODULE_STATS: Lazy<Mutex<HashMap<String, Arc<ModuleCounters>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Monotonic id assigned to each connection
+static NEXT_CONN_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Counters shared by all the connections created from the same WATM module
+#[derive(Default)]
+struct ModuleCounters {
+    connections: AtomicU64,
+    active: AtomicU64,
+    caller_bytes_written: AtomicU64,
+    caller_bytes_read: AtomicU64,
+    network_bytes_sent: AtomicU64,
+    network_bytes_received: AtomicU64,
+    errors: AtomicU64,
+}
+
+#[derive(Default)]
+struct ConnCounters {
+    id: u64,
+    module: String,
+
+    caller_bytes_written: AtomicU64,
+    caller_bytes_read: AtomicU64,
+    network_bytes_sent: AtomicU64,
+    network_bytes_received: AtomicU64,
+    errors: AtomicU64,
+
+    /// (started_at, ended_at)
+    timestamps: Mutex<(Option<SystemTime>, Option<SystemTime>)>,
+
+    /// (local_addr, peer_addr) of the last socket pushed into the WATM (or accepted by it)
+    addrs: Mutex<(Option<SocketAddr>, Option<SocketAddr>)>,
+
+    /// None when the stats are not attached to any module (e.g. `Host::default()`)
+    aggregate: Option<Arc<ModuleCounters>>,
+}
+
+impl Drop for ConnCounters {
+    fn drop(&mut self) {
+        // the connection is gone without being marked as ended (e.g. the client was dropped)
+        let timestamps = self.timestamps.get_mut().unwrap_or_else(|e| e.into_inner());
+        if let (Some(_), None, Some(aggregate)) = (timestamps.0, timestamps.1, &self.aggregate) {
+            aggregate.active.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Traffic statistics of one connection, cheap to clone and safe to update from any thread.
+#[derive(Clone, Default)]
+pub struct ConnStats {
+    inner: Arc<ConnCounters>,
+}
+
+impl ConnStats {
+    /// Create the statistics for a new connection of the WATM module loaded from `module`
+    pub fn new(module: &str) -> Self {
+        let aggregate = {
+            let mut modules = MODULE_STATS.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(modules.entry(module.to_string()).or_default())
+        };
+
+        ConnStats {
+            inner: Arc::new(ConnCounters {
+                id: NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed),
+                module: module.to_string(),
+                caller_bytes_written: AtomicU64::new(0),
+                caller_bytes_read: AtomicU64::new(0),
+                network_bytes_sent: AtomicU64::new(0),
+                network_bytes_received: AtomicU64::new(0),
+                errors: AtomicU64::new(0),
+                timestamps: Mutex::new((None, None)),
+                addrs: Mutex::new((None, None)),
+                aggregate: Some(aggregate),
+            }),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// Path of the WATM module this connection is created from
+    pub fn module(&self) -> &str {
+        &self.inner.module
+    }
+
+    /// Bytes written by the caller into the WATM (before the transform)
+    pub fn record_caller_write(&self, n: u64) {
+        self.add(n, |c| &c.caller_bytes_written, |m| &m.caller_bytes_written);
+    }
+
+    /// Bytes read by the caller out of the WATM (after the transform)
+    pub fn record_caller_read(&self, n: u64) {
+        self.add(n, |c| &c.caller_bytes_read, |m| &m.caller_bytes_read);
+    }
+
+    /// Bytes sent by the WATM to the network (after the transform)
+    pub fn record_network_send(&self, n: u64) {
+        self.add(n, |c| &c.network_bytes_sent, |m| &m.network_bytes_sent);
+    }
+
+    /// Bytes received by the WATM from the network (before the transform)
+    pub fn record_network_recv(&self, n: u64) {
+        self.add(
+            n,
+            |c| &c.network_bytes_received,
+            |m| &m.network_bytes_received,
+        );
+    }
+
+    pub fn record_error(&self) {
+        self.add(1, |c| &c.errors, |m| &m.errors);
+    }
+
+    /// Mark the connection as established, only the first call takes effect
+    pub fn mark_started(&self) {
+        let mut timestamps = self
+            .inner
+            .timestamps
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if timestamps.0.is_none() {
+            timestamps.0 = Some(SystemTime::now());
+            if let Some(aggregate) = &self.inner.aggregate {
+                aggregate.connections.fetch_add(1, Ordering::Relaxed);
+                aggregate.active.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Mark the connection as closed, only the first call after `mark_started` takes effect
+    pub fn mark_ended(&self) {
+        let mut timestamps = self
+            .inner
+            .timestamps
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if timestamps.0.is_some() && timestamps.1.is_none() {
+            timestamps.1 = Some(SystemTime::now());
+            if let Some(aggregate) = &self.inner.aggregate {
+                aggregate.active.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Record the addresses of `socket` on the network side, a listener only has the local one
+    pub fn record_socket_addrs(&self, socket: &impl AsFd) {
+        // only borrowing the fd to query it, it stays owned by `socket`
+        let socket = ManuallyDrop::new(unsafe {
+            std::net::TcpStream::from_raw_fd(socket.as_fd().as_raw_fd())
+        });
+
+        let mut addrs = self.inner.addrs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Ok(local_addr) = socket.local_addr() {
+            addrs.0 = Some(local_addr);
+        }
+        if let Ok(peer_addr) = socket.peer_addr() {
+            addrs.1 = Some(peer_addr);
+        }
+    }
+
+    /// The address the network side is bound to, once a socket was pushed into the WATM
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.addrs.lock().unwrap_or_else(|e| e.into_inner()).0
+    }
+
+    /// The address of the peer on the network side, once connected or accepted
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.addrs.lock().unwrap_or_else(|e| e.into_inner()).1
+    }
+
+    /// Take a consistent-enough copy of the current counters
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let (started_at, ended_at) = *self
+            .inner
+            .timestamps
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let (local_addr, peer_addr) = *self.inner.addrs.lock().unwrap_or_else(|e| e.into_inner());
+
+        StatsSnapshot {
+            id: self.inner.id,
+            module: self.inner.module.clone(),
+            caller_bytes_written: self.inner.caller_bytes_written.load(Ordering::Relaxed),
+            caller_bytes_read: self.inner.caller_bytes_read.load(Ordering::Relaxed),
+            network_bytes_sent: self.inner.network_bytes_sent.load(Ordering::Relaxed),
+            network_bytes_received: self.inner.network_bytes_received.load(Ordering::Relaxed),
+            errors: self.inner.errors.load(Ordering::Relaxed),
+            started_at,
+            ended_at,
+            local_addr,
+            peer_addr,
+        }
+    }
+
+    /// Wrap a file pushed into the WATM so the bytes going through it are counted as network traffic
+    pub fn wrap_network_file(&self, file: Box<dyn WasiFile>) -> Box<dyn WasiFile> {
+        Box::new(StatsFile {
+            inner: file,
+            stats: self.clone(),
+        })
+    }
+
+    fn add(
+        &self,
+        n: u64,
+        conn: impl Fn(&ConnCounters) -> &AtomicU64,
+        module: impl Fn(&ModuleCounters) -> &AtomicU64,
+    ) {
+        conn(&self.inner).fetch_add(n, Ordering::Relaxed);
+        if let Some(aggregate) = &self.inner.aggregate {
+            module(aggregate).fetch_add(n, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A point-in-time copy of the statistics of one connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    pub id: u64,
+    pub module: String,
+
+    pub caller_bytes_written: u64,
+    pub caller_bytes_read: u64,
+    pub network_bytes_sent: u64,
+    pub network_bytes_received: u64,
+    pub errors: u64,
+
+    pub started_at: Option<SystemTime>,
+    pub ended_at: Option<SystemTime>,
+
+    pub local_addr: Option<SocketAddr>,
+    pub peer_addr: Option<SocketAddr>,
+}
+
+impl StatsSnapshot {
+    /// How long the connection has been (or was) alive
+    pub fn duration(&self) -> Option<Duration> {
+        let started_at = self.started_at?;
+        self.ended_at
+            .unwrap_or_else(SystemTime::now)
+            .duration_since(started_at)
+            .ok()
+    }
+
+    /// Ratio of bytes sent to the network over bytes written by the caller, i.e. the expansion caused by the transform
+    pub fn expansion_ratio(&self) -> Option<f64> {
+        ratio(self.network_bytes_sent, self.caller_bytes_written)
+    }
+}
+
+/// A point-in-time copy of the counters aggregated over all connections of one WATM module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleStatsSnapshot {
+    pub module: String,
+
+    /// Number of connections established so far
+    pub connections: u64,
+    /// Number of connections established and not yet closed
+    pub active: u64,
+
+    pub caller_bytes_written: u64,
+    pub caller_bytes_read: u64,
+    pub network_bytes_sent: u64,
+    pub network_bytes_received: u64,
+    pub errors: u64,
+}
+
+impl ModuleStatsSnapshot {
+    fn from_counters(module: &str, counters: &ModuleCounters) -> Self {
+        ModuleStatsSnapshot {
+            module: module.to_string(),
+            connections: counters.connections.load(Ordering::Relaxed),
+            active: counters.active.load(Ordering::Relaxed),
+            caller_bytes_written: counters.caller_bytes_written.load(Ordering::Relaxed),
+            caller_bytes_read: counters.caller_bytes_read.load(Ordering::Relaxed),
+            network_bytes_sent: counters.network_bytes_sent.load(Ordering::Relaxed),
+            network_bytes_received: counters.network_bytes_received.load(Ordering::Relaxed),
+            errors: counters.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Overhead of the transport: bytes on the network side over bytes on the caller side, both directions
+    pub fn overhead_ratio(&self) -> Option<f64> {
+        ratio(
+            self.network_bytes_sent + self.network_bytes_received,
+            self.caller_bytes_written + self.caller_bytes_read,
+        )
+    }
+}
+
+/// Get the aggregated statistics of the WATM module loaded from `module`
+pub fn module_stats(module: &str) -> Option<ModuleStatsSnapshot> {
+    let modules = MODULE_STATS.lock().unwrap_or_else(|e| e.into_inner());
+    modules
+        .get(module)
+        .map(|counters| ModuleStatsSnapshot::from_counters(module, counters))
+}
+
+/// Get the aggregated statistics of every WATM module loaded in this process
+pub fn all_module_stats() -> Vec<ModuleStatsSnapshot> {
+    let modules = MODULE_STATS.lock().unwrap_or_else(|e| e.into_inner());
+    modules
+        .iter()
+        .map(|(module, counters)| ModuleStatsSnapshot::from_counters(module, counters))
+        .collect()
+}
+
+fn ratio(numerator: u64, denominator: u64) -> Option<f64> {
+    if denominator == 0 {
+        return None;
+    }
+    Some(numerator as f64 / denominator as f64)
+}
+
+/// A `WasiFile` wrapper counting the bytes going through a network socket pushed into the WATM
+struct StatsFile {
+    inner: Box<dyn WasiFile>,
+    stats: ConnStats,
+}
+
+#[async_trait::async_trait]
+impl WasiFile for StatsFile {
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+
+    fn pollable(&self) -> Option<BorrowedFd<'_>> {
+        self.inner.pollable()
+    }
+
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        // connections accepted from a listener pushed by the Host are network traffic as well
+        let file = self.inner.sock_accept(fdflags).await?;
+        if let Some(fd) = file.pollable() {
+            self.stats.record_socket_addrs(&fd);
+        }
+        Ok(self.stats.wrap_network_file(file))
+    }
+
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        let (n, flags) = self.inner.sock_recv(ri_data, ri_flags).await?;
+        self.stats.record_network_recv(n);
+        Ok((n, flags))
+    }
+
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        let n = self.inner.sock_send(si_data, si_flags).await?;
+        self.stats.record_network_send(n);
+        Ok(n)
+    }
+
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.inner.sock_shutdown(how).await
+    }
+
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.inner.set_filestat_size(size).await
+    }
+
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        let n = self.inner.read_vectored(bufs).await?;
+        self.stats.record_network_recv(n);
+        Ok(n)
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        let n = self.inner.write_vectored(bufs).await?;
+        self.stats.record_network_send(n);
+        Ok(n)
+    }
+
+    async fn write_vectored_at<'a>(&self, bufs: &[IoSlice<'a>], offset: u64) -> Result<u64, Error> {
+        self.inner.write_vectored_at(bufs, offset).await
+    }
+
+    async fn seek(&self, pos: std::io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
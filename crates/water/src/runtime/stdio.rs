@@ -0,0 +1,141 @@
+//! Standard I/O of the WATM instances, set up per instance as configured by [`StdioMode`]:
+//! inherited from the Host, discarded, captured into in-memory ring buffers, or redirected to a file.
+
+use std::{
+    collections::VecDeque,
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use wasi_common::{
+    pipe::{ReadPipe, WritePipe},
+    Table, WasiSched,
+};
+
+use crate::config::StdioMode;
+use crate::runtime::*;
+
+/// Number of the last lines of the captured stderr attached to the error when a WATM traps
+pub const STDERR_TAIL_LINES: usize = 20;
+
+/// An in-memory ring buffer keeping only the last `capacity` bytes written into it
+#[derive(Clone)]
+pub struct RingBuffer {
+    inner: Arc<Mutex<VecDeque<u8>>>,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            inner: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Get a copy of the bytes currently kept in the buffer
+    pub fn contents(&self) -> Vec<u8> {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.iter().copied().collect()
+    }
+
+    /// Get the last `n` lines kept in the buffer (the first one may be cut by the ring buffer)
+    pub fn tail_lines(&self, n: usize) -> String {
+        let contents = String::from_utf8_lossy(&self.contents()).into_owned();
+        let lines: Vec<&str> = contents.lines().collect();
+        lines[lines.len().saturating_sub(n)..].join("\n")
+    }
+}
+
+impl Write for RingBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+
+        // only the last `capacity` bytes of buf can be kept anyway
+        let kept = &buf[buf.len().saturating_sub(self.capacity)..];
+        let overflow = (inner.len() + kept.len()).saturating_sub(self.capacity);
+        inner.drain(..overflow);
+        inner.extend(kept);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The captured stdout & stderr of a WATM instance with [`StdioMode::Capture`]
+#[derive(Clone)]
+pub struct CapturedStdio {
+    pub stdout: RingBuffer,
+    pub stderr: RingBuffer,
+}
+
+/// Build the WasiCtx for a WATM instance with its stdio set up as `mode`, trapped by the scheduler once `interrupt` is requested,
+/// also returns the buffers when the output is captured.
+pub fn build_wasi_ctx(
+    mode: &StdioMode,
+    interrupt: &Interrupt,
+) -> Result<(WasiCtx, Option<CapturedStdio>), anyhow::Error> {
+    build_wasi_ctx_with_sched(mode, pipe::sched::sched_ctx(interrupt.clone()))
+}
+
+/// Same as [`build_wasi_ctx`], with `sched` doing the `poll_oneoff` of the WATM instead of the blocking one,
+/// e.g. the Tokio one for the instances driven in async mode.
+pub fn build_wasi_ctx_with_sched(
+    mode: &StdioMode,
+    sched: Box<dyn WasiSched>,
+) -> Result<(WasiCtx, Option<CapturedStdio>), anyhow::Error> {
+    let ctx = WasiCtx::new(
+        wasmtime_wasi::sync::random_ctx(),
+        wasmtime_wasi::sync::clocks_ctx(),
+        sched,
+        Table::new(),
+    );
+    let mut captured = None;
+
+    match mode {
+        StdioMode::Inherit => {
+            ctx.set_stdin(Box::new(wasmtime_wasi::sync::stdio::stdin()));
+            ctx.set_stdout(Box::new(wasmtime_wasi::sync::stdio::stdout()));
+            ctx.set_stderr(Box::new(wasmtime_wasi::sync::stdio::stderr()));
+        }
+        StdioMode::Null => {
+            ctx.set_stdin(Box::new(ReadPipe::from(Vec::new())));
+            ctx.set_stdout(Box::new(WritePipe::new(std::io::sink())));
+            ctx.set_stderr(Box::new(WritePipe::new(std::io::sink())));
+        }
+        StdioMode::Capture(capacity) => {
+            let stdio = CapturedStdio {
+                stdout: RingBuffer::new(*capacity),
+                stderr: RingBuffer::new(*capacity),
+            };
+
+            ctx.set_stdin(Box::new(ReadPipe::from(Vec::new())));
+            ctx.set_stdout(Box::new(WritePipe::new(stdio.stdout.clone())));
+            ctx.set_stderr(Box::new(WritePipe::new(stdio.stderr.clone())));
+
+            captured = Some(stdio);
+        }
+        StdioMode::File(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .context(format!("failed to open stdio file {}", path))?;
+            let stdout = cap_std::fs::File::from_std(file.try_clone()?);
+            let stderr = cap_std::fs::File::from_std(file);
+
+            ctx.set_stdin(Box::new(ReadPipe::from(Vec::new())));
+            ctx.set_stdout(Box::new(wasmtime_wasi::sync::file::File::from_cap_std(
+                stdout,
+            )));
+            ctx.set_stderr(Box::new(wasmtime_wasi::sync::file::File::from_cap_std(
+                stderr,
+            )));
+        }
+    }
+
+    Ok((ctx, captured))
+}
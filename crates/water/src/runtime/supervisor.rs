@@ -0,0 +1,215 @@
+//! A supervisor keeping a WATER `Runner` running: every time the entry function traps (or returns an error),
+//! a new runner is created from the same config and run again, after an exponential backoff.
+//!
+//! The backoff starts over once a runner has been running for longer than the maximum backoff.
+//! The cause of every restart is reported to the `on_trap` callback, and a [`SupervisorHandle`] stops
+//! the supervisor from another thread, together with the runner it is running.
+
+use std::{
+    sync::{Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::runtime::{client::WATERClient, runner::RunnerHandle, *};
+
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// What the `on_trap` callback of a [`RunnerSupervisor`] is told about a runner that failed
+pub struct RunnerTrap<'a> {
+    /// the error the runner exited with, carrying the trap cause
+    pub error: &'a anyhow::Error,
+
+    /// number of restarts before this failure
+    pub restarts: usize,
+
+    /// how long the runner was running
+    pub uptime: Duration,
+
+    /// how long until the runner is restarted, `None` when the supervisor gives up
+    pub backoff: Option<Duration>,
+}
+
+impl RunnerTrap<'_> {
+    /// The trap code, backtrace, etc. of the failure, when the runner failed running the WATM
+    pub fn guest_trap(&self) -> Option<&trap::GuestTrap> {
+        self.error.downcast_ref::<trap::GuestTrap>()
+    }
+}
+
+type OnTrap = Box<dyn FnMut(&RunnerTrap<'_>) + Send>;
+
+/// A supervisor restarting the `Runner` created with `conf` when it fails
+pub struct RunnerSupervisor {
+    conf: WATERConfig,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_restarts: Option<usize>,
+    on_trap: Option<OnTrap>,
+    state: Arc<SupervisorState>,
+}
+
+/// A handle to stop the [`RunnerSupervisor`] it was taken from
+#[derive(Clone)]
+pub struct SupervisorHandle {
+    state: Arc<SupervisorState>,
+}
+
+/// State shared between the supervisor and its handles
+#[derive(Default)]
+struct SupervisorState {
+    inner: Mutex<SupervisorInner>,
+
+    /// notified when the stop is requested, to cut the backoff short
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct SupervisorInner {
+    stopped: bool,
+
+    /// the handle of the runner currently running
+    runner: Option<RunnerHandle>,
+}
+
+impl RunnerSupervisor {
+    /// Supervise the `Runner` created with `conf`
+    pub fn new(conf: WATERConfig) -> Result<Self, anyhow::Error> {
+        if conf.client_type != WaterBinType::Runner {
+            return Err(anyhow::anyhow!(
+                "[HOST] RunnerSupervisor needs a Runner config, got {:?}",
+                conf.client_type
+            ));
+        }
+
+        Ok(RunnerSupervisor {
+            conf,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_restarts: None,
+            on_trap: None,
+            state: Arc::new(SupervisorState::default()),
+        })
+    }
+
+    /// Wait `initial` before the first restart, doubling it for the next ones up to `max`
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Give up after `max` restarts in a row, `run` then returns the last error
+    pub fn with_max_restarts(mut self, max: usize) -> Self {
+        self.max_restarts = Some(max);
+        self
+    }
+
+    /// Call `on_trap` every time the runner fails, before it is restarted
+    pub fn on_trap(mut self, on_trap: impl FnMut(&RunnerTrap<'_>) + Send + 'static) -> Self {
+        self.on_trap = Some(Box::new(on_trap));
+        self
+    }
+
+    /// A handle to stop the supervisor and its runner, e.g. from a signal handler or another thread
+    pub fn handle(&self) -> SupervisorHandle {
+        SupervisorHandle {
+            state: Arc::clone(&self.state),
+        }
+    }
+
+    /// Run the runner on the current thread, restarting it when it fails, until it returns, the supervisor
+    /// is stopped or gives up. Creating the runner failing (e.g. a bad config) is not retried.
+    pub fn run(&mut self) -> Result<(), anyhow::Error> {
+        let mut restarts = 0;
+        let mut backoff = self.initial_backoff;
+
+        loop {
+            let mut water_client = WATERClient::new(self.conf.clone())?;
+            if !self.state.set_runner(Some(water_client.runner_handle()?)) {
+                return Ok(());
+            }
+
+            let started = Instant::now();
+            let res = water_client.execute();
+            let uptime = started.elapsed();
+            self.state.set_runner(None);
+
+            let error = match res {
+                Ok(()) => {
+                    info!("[HOST] RunnerSupervisor runner exited");
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+
+            // a runner that has been healthy for a while starts the backoff over
+            if uptime > self.max_backoff {
+                restarts = 0;
+                backoff = self.initial_backoff;
+            }
+
+            let give_up = self.max_restarts.is_some_and(|max| restarts >= max);
+            info!(
+                "[HOST] RunnerSupervisor runner failed after {:?} ({} restarts): {}",
+                uptime, restarts, error
+            );
+            if let Some(on_trap) = &mut self.on_trap {
+                on_trap(&RunnerTrap {
+                    error: &error,
+                    restarts,
+                    uptime,
+                    backoff: (!give_up).then_some(backoff),
+                });
+            }
+
+            if give_up {
+                return Err(error.context(format!(
+                    "[HOST] RunnerSupervisor gave up after {} restarts",
+                    restarts
+                )));
+            }
+
+            if !self.state.sleep(backoff) {
+                return Ok(());
+            }
+            restarts += 1;
+            backoff = std::cmp::min(backoff * 2, self.max_backoff);
+        }
+    }
+}
+
+impl SupervisorHandle {
+    /// Stop the supervisor: the runner running is stopped and not restarted, `run` returns `Ok`
+    pub fn stop(&self) {
+        let mut inner = self.state.lock_inner();
+        inner.stopped = true;
+        if let Some(runner) = &inner.runner {
+            runner.stop();
+        }
+        self.state.changed.notify_all();
+    }
+}
+
+impl SupervisorState {
+    fn lock_inner(&self) -> MutexGuard<'_, SupervisorInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Set the runner stopped together with the supervisor, returns false once the supervisor is stopped
+    fn set_runner(&self, runner: Option<RunnerHandle>) -> bool {
+        let mut inner = self.lock_inner();
+        inner.runner = runner;
+        !inner.stopped
+    }
+
+    /// Wait `backoff` before restarting, returns false if the supervisor is stopped meanwhile
+    fn sleep(&self, backoff: Duration) -> bool {
+        let inner = self.lock_inner();
+        let (inner, _) = self
+            .changed
+            .wait_timeout_while(inner, backoff, |inner| !inner.stopped)
+            .unwrap_or_else(|e| e.into_inner());
+        !inner.stopped
+    }
+}
@@ -1,5 +1,5 @@
 //! Transport trait for WATER runtime that will be implemented by each version and all roles of WATM,
-//! It is for the WATM module to communicate with the Host via UnixStream.
+//! It is for the WATM module to communicate with the Host via in-process pipes (`PipeStream`).
 
 use std::thread::JoinHandle;
 
@@ -53,47 +53,40 @@ pub trait WATERTransportTrait: Send {
     // ======================== v0 only below for now ========================
     // Methods to provide access to the shared state, not implemented by default
 
-    /// v0 only, Get the caller_io (UnixStream) from the WATM runtime object
-    fn get_caller_io(&mut self) -> &mut Option<UnixStream> {
+    /// v0 only, Get the caller_io (PipeStream) from the WATM runtime object
+    fn get_caller_io(&mut self) -> &mut Option<PipeStream> {
         unimplemented!("get_caller_io not implemented")
     }
 
-    /// v0 only, Get the cancel_io (UnixStream) from the WATM runtime object
-    fn get_cancel_io(&mut self) -> &mut Option<UnixStream> {
+    /// v0 only, Get the cancel_io (PipeStream) from the WATM runtime object
+    fn get_cancel_io(&mut self) -> &mut Option<PipeStream> {
         unimplemented!("get_cancel_io not implemented")
     }
 
-    /// v0 only, Get the core (H2O) from the WATM runtime object
+    /// Get the core (H2O) from the WATM runtime object
     fn get_core(&mut self) -> &mut H2O<Host> {
         unimplemented!("get_core not implemented")
     }
 
-    /// v0 only, Set the caller_io (UnixStream) in the WATM runtime object
-    fn set_caller_io(&mut self, _caller_io: Option<UnixStream>) {
+    /// v0 only, Set the caller_io (PipeStream) in the WATM runtime object
+    fn set_caller_io(&mut self, _caller_io: Option<PipeStream>) {
         unimplemented!("set_caller_io not implemented")
     }
 
-    /// v0 only, Set the cancel_io (UnixStream) in the WATM runtime object
-    fn set_cancel_io(&mut self, _cancel_io: Option<UnixStream>) {
+    /// v0 only, Set the cancel_io (PipeStream) in the WATM runtime object
+    fn set_cancel_io(&mut self, _cancel_io: Option<PipeStream>) {
         unimplemented!("set_cancel_io not implemented")
     }
 
-    /// v0 only, Set the cancel_io (UnixStream) in the WATM runtime object and
+    /// v0 only, Set the cancel_io (PipeStream) in the WATM runtime object and
     /// call the corresponding setup function in WATM
     fn cancel_with(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERTransport v0 cancel_with...");
 
-        let (caller_io, water_io) = UnixStream::pair()?;
+        let (caller_io, water_io) = PipeStream::pair();
 
         self.set_cancel_io(Some(caller_io));
 
-        let water_io_file = unsafe { cap_std::fs::File::from_raw_fd(water_io.as_raw_fd()) };
-
-        // insert file here
-        let water_io_file = wasmtime_wasi::sync::file::File::from_cap_std(water_io_file);
-
-        std::mem::forget(water_io); // forget the water_io, so that it won't be closed
-
         let core = self.get_core();
 
         let mut store = core
@@ -107,7 +100,7 @@ pub trait WATERTransportTrait: Send {
             .as_mut()
             .context("Failed to retrieve preview1_ctx from Host")?;
 
-        let water_io_fd = ctx.push_file(Box::new(water_io_file), FileAccessMode::all())?;
+        let water_io_fd = ctx.push_file(Box::new(water_io), FileAccessMode::all())?;
 
         let _water_cancel_with = match core.instance.get_func(&mut *store, CANCEL_FN) {
             Some(func) => func,
@@ -123,12 +116,7 @@ pub trait WATERTransportTrait: Send {
         let mut res = vec![Val::I32(0); _water_cancel_with.ty(&*store).results().len()];
         match _water_cancel_with.call(&mut *store, &params, &mut res) {
             Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function failed: {}",
-                    CANCEL_FN, e
-                )))
-            }
+            Err(e) => return Err(store.data().guest_error(CANCEL_FN, e)),
         }
 
         if res[0].unwrap_i32() != 0 {
@@ -141,7 +129,7 @@ pub trait WATERTransportTrait: Send {
         Ok(())
     }
 
-    /// v0 only, Cancel the connection by writing to the prev set cancel_io (UnixStream)
+    /// v0 only, Cancel the connection by writing to the prev set cancel_io (PipeStream)
     fn cancel(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERTransport v0 cancel...");
 
@@ -194,6 +182,7 @@ pub trait WATERTransportTrait: Send {
         };
 
         // run the entry_fn in a thread -- Host will still have the ability to control it (e.g. with cancel)
+        let entry_fn_name = conf.entry_fn.clone();
         let handle = std::thread::spawn(move || {
             let mut store = store
                 .lock()
@@ -201,7 +190,9 @@ pub trait WATERTransportTrait: Send {
             let mut res = vec![Val::I32(0); entry_fn.ty(&mut *store).results().len()];
             match entry_fn.call(&mut *store, &[], &mut res) {
                 Ok(_) => Ok(()),
-                Err(e) => Err(anyhow::Error::msg(format!("function failed: {}", e))),
+                // the trap of an instance canceled by the Host (v1) is the way it exits
+                Err(e) if interrupt::is_interrupted(&e) => Ok(()),
+                Err(e) => Err(store.data().guest_error(&entry_fn_name, e)),
             }
         });
 
@@ -0,0 +1,178 @@
+//! Structured details of a WATM failing while the Host was calling one of its functions: the trap code,
+//! the wasm backtrace symbolized with the name section (and the DWARF info, when the WATM is built with it)
+//! and the function invoked, returned as a [`GuestTrap`] error instead of a flat string.
+//!
+//! ```ignore
+//! if let Some(trap) = err.downcast_ref::<GuestTrap>() {
+//!     println!("{} trapped with {:?} in {:?}", trap.function, trap.code, trap.backtrace.first());
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::runtime::*;
+
+/// the trap codes of wasmtime, to match [`GuestTrap::code`] without depending on it
+pub use wasmtime::Trap;
+
+/// A WATM trapped (or a Host function it called failed) while the Host was calling `function`
+#[derive(Clone, Debug)]
+pub struct GuestTrap {
+    /// the function of the WATM invoked by the Host, e.g. `_water_dial` or `_water_worker`
+    pub function: String,
+
+    /// the trap code, `None` when the WATM was trapped by an error of a Host function
+    pub code: Option<Trap>,
+
+    /// what went wrong: the trap code or the error of the Host function
+    pub cause: String,
+
+    /// the wasm frames, innermost first
+    pub backtrace: Vec<TrapFrame>,
+
+    /// the last lines written by the WATM to stderr, only with `StdioMode::Capture`
+    pub stderr_tail: Option<String>,
+}
+
+/// A wasm frame of the backtrace of a [`GuestTrap`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrapFrame {
+    /// the name of the module, from its name section
+    pub module_name: Option<String>,
+
+    pub func_index: u32,
+
+    /// the (demangled) name of the function, from the name section
+    pub func_name: Option<String>,
+
+    /// offset of the instruction in the module / in the function
+    pub module_offset: Option<usize>,
+    pub func_offset: Option<usize>,
+
+    /// the source locations from the DWARF info, more than one when functions were inlined
+    pub symbols: Vec<TrapSymbol>,
+}
+
+/// A source location of a [`TrapFrame`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrapSymbol {
+    pub name: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl GuestTrap {
+    /// Capture the details of `e` returned by calling `function` in the WATM,
+    /// `None` when it didn't come from the WATM running (e.g. the arguments didn't type check)
+    pub fn capture(function: &str, e: &anyhow::Error) -> Option<Self> {
+        let code = e.downcast_ref::<Trap>().copied();
+        let backtrace = e.downcast_ref::<WasmBacktrace>();
+        if code.is_none() && backtrace.is_none() {
+            return None;
+        }
+
+        Some(GuestTrap {
+            function: function.to_string(),
+            code,
+            cause: match code {
+                Some(code) => code.to_string(),
+                None => e.root_cause().to_string(),
+            },
+            backtrace: backtrace
+                .map(|backtrace| backtrace.frames().iter().map(TrapFrame::from).collect())
+                .unwrap_or_default(),
+            stderr_tail: None,
+        })
+    }
+}
+
+impl From<&FrameInfo> for TrapFrame {
+    fn from(frame: &FrameInfo) -> Self {
+        TrapFrame {
+            module_name: frame.module().name().map(String::from),
+            func_index: frame.func_index(),
+            func_name: frame.func_name().map(demangle),
+            module_offset: frame.module_offset(),
+            func_offset: frame.func_offset(),
+            symbols: frame
+                .symbols()
+                .iter()
+                .map(|symbol| TrapSymbol {
+                    name: symbol.name().map(demangle),
+                    file: symbol.file().map(String::from),
+                    line: symbol.line(),
+                    column: symbol.column(),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+impl fmt::Display for GuestTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} function failed: {}", self.function, self.cause)?;
+
+        if !self.backtrace.is_empty() {
+            write!(f, "\nwasm backtrace:")?;
+        }
+        for (i, frame) in self.backtrace.iter().enumerate() {
+            write!(f, "\n  {:>3}: ", i)?;
+            if let Some(offset) = frame.module_offset {
+                write!(f, "{:#6x} - ", offset)?;
+            }
+            write!(
+                f,
+                "{}!",
+                frame.module_name.as_deref().unwrap_or("<unknown>")
+            )?;
+            match &frame.func_name {
+                Some(name) => write!(f, "{}", name)?,
+                None => write!(f, "<wasm function {}>", frame.func_index)?,
+            }
+
+            for symbol in &frame.symbols {
+                if let Some(file) = &symbol.file {
+                    write!(f, "\n                    at {}", file)?;
+                    if let Some(line) = symbol.line {
+                        write!(f, ":{}", line)?;
+                        if let Some(column) = symbol.column {
+                            write!(f, ":{}", column)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(stderr_tail) = &self.stderr_tail {
+            write!(f, "\nWATM stderr:\n{}", stderr_tail)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GuestTrap {}
+
+/// The error returned by the Host for `e` of calling `function` in the WATM: a [`GuestTrap`] with the tail of
+/// the captured stderr attached when the WATM failed running, counted in the metrics
+pub(crate) fn guest_error(
+    function: &str,
+    e: anyhow::Error,
+    stdio: Option<&CapturedStdio>,
+) -> anyhow::Error {
+    metrics::registry().record_guest_error(function, &e);
+
+    let mut trap = match GuestTrap::capture(function, &e) {
+        Some(trap) => trap,
+        None => return anyhow::Error::msg(format!("{} function failed: {}", function, e)),
+    };
+
+    trap.stderr_tail = stdio
+        .map(|stdio| stdio.stderr.tail_lines(stdio::STDERR_TAIL_LINES))
+        .filter(|tail| !tail.is_empty());
+    trap.into()
+}
@@ -1,11 +1,14 @@
 //! Configurations for the v0 runtime
 
-use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd};
 
 use anyhow::Context;
 use serde::Deserialize;
 use tracing::info;
 
+use crate::config::SocketOptions;
+use crate::runtime::{core::WrappedConn, socket};
+
 // A Config currently contains the local + remote ip & port
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
@@ -73,6 +76,9 @@ pub struct V0Config {
     pub remote_port: u32,
 
     pub conn: V0CRole,
+
+    /// Options of the sockets dialed, listened and accepted
+    pub socket_options: SocketOptions,
 }
 
 impl V0Config {
@@ -90,44 +96,76 @@ impl V0Config {
             remote_addr,
             remote_port,
             conn: V0CRole::Unknown,
+            socket_options: SocketOptions::default(),
         })
     }
 
-    /// It will connect to the remote addr and set the fd in the V0Config
-    pub fn connect(&mut self) -> Result<std::net::TcpStream, anyhow::Error> {
-        let addr = format!("{}:{}", self.remote_addr, self.remote_port);
+    /// It will connect to the remote addr (or `destination` if given) and set the fd in the V0Config
+    pub fn connect(
+        &mut self,
+        destination: Option<&(String, u16)>,
+    ) -> Result<std::net::TcpStream, anyhow::Error> {
+        let addr = self.dial_addr(destination)?;
+        let conn = socket::connect(addr, &self.socket_options)?;
+        self.set_dialed(&conn);
+        Ok(conn)
+    }
+
+    /// Check that the connection can be dialed in this role, returns the addr to connect to:
+    /// `destination` if given, otherwise the remote addr
+    pub fn dial_addr(&self, destination: Option<&(String, u16)>) -> Result<String, anyhow::Error> {
+        let addr = match destination {
+            Some((host, port)) => format!("{}:{}", host, port),
+            None => format!("{}:{}", self.remote_addr, self.remote_port),
+        };
 
         info!("[HOST] WATERCore V0 connecting to {}", addr);
 
-        match &mut self.conn {
-            // if the V0CRole is Relay, then it will remain as Relay
-            V0CRole::Relay(_, _, ref mut conn_fd) => {
-                // now relay has been built, need to dial
-                if *conn_fd != -1 {
-                    return Err(anyhow::Error::msg("Relay already connected"));
-                }
-
-                let conn = std::net::TcpStream::connect(addr)?;
-                *conn_fd = conn.as_raw_fd();
-                Ok(conn)
+        match &self.conn {
+            // now relay has been built, need to dial
+            V0CRole::Relay(_, _, conn_fd) if *conn_fd != -1 => {
+                Err(anyhow::Error::msg("Relay already connected"))
             }
-            // if the V0CRole has not been set, and connect() was called, then it should be a dialer
+            V0CRole::Relay(..) | V0CRole::Unknown => Ok(addr),
+            _ => Err(anyhow::Error::msg("not a dialer")),
+        }
+    }
+
+    /// It will take `conn` as the connection of a dialer instead of connecting, and set the fd in the V0Config
+    pub fn wrap(&mut self, conn: WrappedConn) -> Result<WrappedConn, anyhow::Error> {
+        info!("[HOST] WATERCore V0 wrapping the provided connection");
+
+        match &self.conn {
             V0CRole::Unknown => {
-                let conn = std::net::TcpStream::connect(addr)?;
-                self.conn = V0CRole::Dialer(conn.as_raw_fd());
+                self.set_dialed(&conn.as_fd());
                 Ok(conn)
             }
             _ => Err(anyhow::Error::msg("not a dialer")),
         }
     }
 
+    /// Set the fd of the connection dialed to `dial_addr()` in the V0Config
+    pub fn set_dialed(&mut self, conn: &impl AsRawFd) {
+        match &mut self.conn {
+            // if the V0CRole is Relay, then it will remain as Relay
+            V0CRole::Relay(_, _, ref mut conn_fd) => *conn_fd = conn.as_raw_fd(),
+            // if the V0CRole has not been set, and connect() was called, then it should be a dialer
+            _ => self.conn = V0CRole::Dialer(conn.as_raw_fd()),
+        }
+    }
+
     /// It will create a listener and set the fd in the V0Config (for either listener or relay)
     pub fn create_listener(&mut self, is_relay: bool) -> Result<(), anyhow::Error> {
         let addr = format!("{}:{}", self.loc_addr, self.loc_port);
 
         info!("[HOST] WATERCore V0 creating listener on {}", addr);
 
-        let listener = std::net::TcpListener::bind(addr)?;
+        let listener = socket::listen(addr, &self.socket_options)?;
+        // the port is picked by the OS when the config asks for port 0
+        info!(
+            "[HOST] WATERCore V0 listening on {}",
+            listener.local_addr()?
+        );
 
         if is_relay {
             self.conn = V0CRole::Relay(listener.into_raw_fd(), -1, -1);
@@ -139,60 +177,71 @@ impl V0Config {
 
     /// It will accept a connection and set the fd in the V0Config (for either listener or relay)
     pub fn accept(&mut self) -> Result<std::net::TcpStream, anyhow::Error> {
-        info!("[HOST] WATERCore V0 accept with conn {:?} ...", self.conn);
+        let listener_fd = self.listener_fd()?;
 
-        match self.conn {
-            V0CRole::Listener(ref mut listener_fd, ref mut accepted_fd) => {
-                if *accepted_fd != -1 {
-                    return Err(anyhow::Error::msg("Listener already accepted"));
-                }
+        let listener = unsafe { std::net::TcpListener::from_raw_fd(listener_fd) };
+        let accepted = listener.accept();
+        let _ = listener.into_raw_fd(); // made sure the listener is not closed after scope
 
-                let listener = unsafe { std::net::TcpListener::from_raw_fd(*listener_fd) };
+        let (stream, _) = accepted?;
+        socket::apply_accepted(&stream, &self.socket_options)?;
+        self.set_accepted(&stream);
+        Ok(stream)
+    }
 
-                let (stream, _) = listener.accept()?;
+    /// Check that a connection can be accepted in this role, returns the fd of the listener to accept from
+    pub fn listener_fd(&self) -> Result<i32, anyhow::Error> {
+        info!("[HOST] WATERCore V0 accept with conn {:?} ...", self.conn);
 
-                *listener_fd = listener.into_raw_fd(); // made sure the listener is not closed after scope
-                *accepted_fd = stream.as_raw_fd();
+        match self.conn {
+            V0CRole::Listener(listener_fd, accepted_fd) => match accepted_fd {
+                -1 => Ok(listener_fd),
+                _ => Err(anyhow::Error::msg("Listener already accepted")),
+            },
+            V0CRole::Relay(listener_fd, accepted_fd, _) => match accepted_fd {
+                -1 => Ok(listener_fd),
+                _ => Err(anyhow::Error::msg("Relay already accepted")),
+            },
+            _ => Err(anyhow::Error::msg("not a listener")),
+        }
+    }
 
-                Ok(stream)
+    /// The listener (for either listener or relay), whether a connection was accepted from it or not
+    pub fn listener_socket(&self) -> Result<BorrowedFd<'_>, anyhow::Error> {
+        match self.conn {
+            // the listener is owned by the V0Config until it is moved into the WATM
+            V0CRole::Listener(listener_fd, _) | V0CRole::Relay(listener_fd, _, _) => {
+                Ok(unsafe { BorrowedFd::borrow_raw(listener_fd) })
             }
-            V0CRole::Relay(ref mut listener_fd, ref mut accepted_fd, _) => {
-                if *accepted_fd != -1 {
-                    return Err(anyhow::Error::msg("Relay already accepted"));
-                }
-
-                let listener = unsafe { std::net::TcpListener::from_raw_fd(*listener_fd) };
-                let (stream, _) = listener.accept()?;
-                *listener_fd = listener.into_raw_fd(); // made sure the listener is not closed after scope
+            _ => Err(anyhow::Error::msg("not a listener")),
+        }
+    }
+
+    /// Set the fd of the connection accepted from `listener_fd()` in the V0Config
+    pub fn set_accepted(&mut self, stream: &std::net::TcpStream) {
+        match self.conn {
+            V0CRole::Listener(_, ref mut accepted_fd)
+            | V0CRole::Relay(_, ref mut accepted_fd, _) => {
                 *accepted_fd = stream.as_raw_fd();
-                Ok(stream)
             }
-            _ => Err(anyhow::Error::msg("not a listener")),
+            _ => {}
         }
     }
 
-    /// It will close the connection to remote / accepted connection listened and exit gracefully
+    /// It will release the connection to remote / accepted connection listened and exit gracefully.
+    ///
+    /// The sockets were moved into the WATM's WasiCtx when pushed, so they are closed by the WATM
+    /// (or when the store is dropped) -- closing the raw fds here again would be a double close.
     pub fn defer(&mut self) {
         info!("[HOST] WATERCore V0 defer with conn {:?} ...", self.conn);
 
         match self.conn {
             V0CRole::Listener(_, ref mut accepted_fd) => {
                 // The accepted stream should be defered, not the listener
-                let accepted_conn = unsafe { std::net::TcpStream::from_raw_fd(*accepted_fd) };
-                drop(accepted_conn);
                 *accepted_fd = -1; // set it back to default
             }
-            V0CRole::Dialer(conn_fd) => {
-                let conn = unsafe { std::net::TcpStream::from_raw_fd(conn_fd) };
-                drop(conn);
-            }
             V0CRole::Relay(_, ref mut accepted_fd, ref mut conn_fd) => {
-                let accepted_conn = unsafe { std::net::TcpStream::from_raw_fd(*accepted_fd) };
-                drop(accepted_conn);
                 *accepted_fd = -1; // set it back to default
-
-                let conn = unsafe { std::net::TcpStream::from_raw_fd(*conn_fd) };
-                drop(conn);
                 *conn_fd = -1; // set it back to default
             }
             _ => {}
@@ -208,18 +257,11 @@ impl V0Config {
 
         match self.conn {
             V0CRole::Listener(_, ref mut accepted_fd) => {
-                if *accepted_fd != -1 {
-                    *accepted_fd = -1; // set it back to default
-                }
+                *accepted_fd = -1; // set it back to default
             }
             V0CRole::Relay(_, ref mut accepted_fd, ref mut conn_fd) => {
-                if *accepted_fd != -1 {
-                    *accepted_fd = -1; // set it back to default
-                }
-
-                if *conn_fd != -1 {
-                    *conn_fd = -1; // set it back to default
-                }
+                *accepted_fd = -1; // set it back to default
+                *conn_fd = -1; // set it back to default
             }
             _ => {}
         }
@@ -1,5 +1,6 @@
 //! Exported functions implementation for v0 WATM module from the Host
 
+use crate::runtime::core::WrappedConn;
 use crate::runtime::v0::config::V0Config;
 use crate::runtime::*;
 use std::sync::{Arc, Mutex};
@@ -18,15 +19,37 @@ pub fn export_tcp_connect(
                 info!("[WASM] invoking host_dial v0 ...");
 
                 let mut config = config.lock().unwrap();
+                let destination = caller.data().destination.clone();
 
-                let tcp = config
-                    .connect()
-                    .map(TcpStream::from_std)
-                    .context("failed to connect to endpoint")
-                    .unwrap();
+                // the connection provided by the application is pushed instead of dialing
+                let conn = match caller.data_mut().take_wrapped() {
+                    Ok(Some(conn)) => config.wrap(conn),
+                    Ok(None) => config
+                        .connect(destination.as_ref())
+                        .map(WrappedConn::Tcp)
+                        .inspect(|_| {
+                            metrics::registry().record_dial_success();
+                        }),
+                    Err(e) => Err(e),
+                };
 
-                // Connecting Tcp
-                let socket_file: Box<dyn WasiFile> = wasmtime_wasi::net::Socket::from(tcp).into();
+                // a failed dial is reported to the WATM as a negative fd instead of panicking the Host
+                let conn = match conn {
+                    Ok(conn) => {
+                        caller.data().stats.record_socket_addrs(&conn);
+                        conn
+                    }
+                    Err(e) => {
+                        info!("[HOST] WATERCore V0 failed to connect to endpoint: {}", e);
+                        metrics::registry().record_dial_failure(&e);
+                        return -1;
+                    }
+                };
+
+                // Connecting Tcp (or the Unix socket provided by the application)
+                let socket_file = conn.into_wasi_file();
+                // count the traffic going thru the socket as the network side of the connection
+                let socket_file = caller.data().stats.wrap_network_file(socket_file);
 
                 // Get the WasiCtx of the caller(WASM), then insert_file into it
                 let ctx: &mut WasiCtx = caller
@@ -59,14 +82,22 @@ pub fn export_accept(
 
                 let mut config = config.lock().unwrap();
 
-                let tcp = config
-                    .accept()
-                    .map(TcpStream::from_std)
-                    .context("failed to accept")
-                    .unwrap();
+                // a failed accept (e.g. the listener shut down) is reported to the WATM as a negative fd
+                let tcp = match config.accept() {
+                    Ok(tcp) => {
+                        caller.data().stats.record_socket_addrs(&tcp);
+                        TcpStream::from_std(tcp)
+                    }
+                    Err(e) => {
+                        info!("[HOST] WATERCore V0 failed to accept: {}", e);
+                        return -1;
+                    }
+                };
 
                 // Connecting Tcp
                 let socket_file: Box<dyn WasiFile> = wasmtime_wasi::net::Socket::from(tcp).into();
+                // count the traffic going thru the socket as the network side of the connection
+                let socket_file = caller.data().stats.wrap_network_file(socket_file);
 
                 // Get the WasiCtx of the caller(WASM), then insert_file into it
                 let ctx: &mut WasiCtx = caller
@@ -90,13 +121,163 @@ pub fn export_defer(
     config: Arc<Mutex<V0Config>>,
 ) -> Result<(), anyhow::Error> {
     linker
-        .func_wrap("env", "host_defer", move |_caller: Caller<'_, Host>| {
+        .func_wrap("env", "host_defer", move |caller: Caller<'_, Host>| {
             info!("[WASM] invoking host_defer v0 ...");
 
             let mut config = config.lock().unwrap();
 
             config.defer();
+
+            caller.data().stats.mark_ended();
         })
         .context("Failed to export defer function to WASM")?;
     Ok(())
 }
+
+/// Async version of `export_tcp_connect` for the instances driven in async mode,
+/// where the connection is made with Tokio instead of blocking the thread running the WATM.
+#[cfg(feature = "async")]
+pub fn export_tcp_connect_async(
+    linker: &mut Linker<Host>,
+    config: Arc<Mutex<V0Config>>,
+) -> Result<(), anyhow::Error> {
+    linker
+        .func_wrap0_async("env", "host_dial", move |mut caller: Caller<'_, Host>| {
+            let config = Arc::clone(&config);
+            Box::new(async move {
+                info!("[WASM] invoking host_dial v0 async ...");
+
+                let destination = caller.data().destination.clone();
+
+                // a failed dial is reported to the WATM as a negative fd instead of panicking the Host
+                let tcp = match connect_async(&config, destination.as_ref()).await {
+                    Ok(tcp) => {
+                        metrics::registry().record_dial_success();
+                        caller.data().stats.record_socket_addrs(&tcp);
+                        TcpStream::from_std(tcp)
+                    }
+                    Err(e) => {
+                        info!("[HOST] WATERCore V0 failed to connect to endpoint: {}", e);
+                        metrics::registry().record_dial_failure(&e);
+                        return -1;
+                    }
+                };
+
+                let socket_file: Box<dyn WasiFile> =
+                    Box::new(wasmtime_wasi::tokio::TcpStream::from_cap_std(tcp));
+                push_network_file(&mut caller, socket_file)
+            })
+        })
+        .context("Failed to export Dial function to WASM")?;
+    Ok(())
+}
+
+/// Async version of `export_accept` for the instances driven in async mode,
+/// where the connection is accepted with Tokio instead of blocking the thread running the WATM.
+#[cfg(feature = "async")]
+pub fn export_accept_async(
+    linker: &mut Linker<Host>,
+    config: Arc<Mutex<V0Config>>,
+) -> Result<(), anyhow::Error> {
+    linker
+        .func_wrap0_async("env", "host_accept", move |mut caller: Caller<'_, Host>| {
+            let config = Arc::clone(&config);
+            Box::new(async move {
+                info!("[WASM] invoking host_accept v0 async ...");
+
+                let tcp = match accept_async(&config).await {
+                    Ok(tcp) => {
+                        caller.data().stats.record_socket_addrs(&tcp);
+                        TcpStream::from_std(tcp)
+                    }
+                    Err(e) => {
+                        info!("[HOST] WATERCore V0 failed to accept: {}", e);
+                        return -1;
+                    }
+                };
+
+                let socket_file: Box<dyn WasiFile> =
+                    Box::new(wasmtime_wasi::tokio::TcpStream::from_cap_std(tcp));
+                push_network_file(&mut caller, socket_file)
+            })
+        })
+        .context("Failed to export TcpListener create function to WASM")?;
+    Ok(())
+}
+
+/// Connect to the remote addr of the V0Config (or `destination` if given) without holding its lock while connecting
+#[cfg(feature = "async")]
+async fn connect_async(
+    config: &Mutex<V0Config>,
+    destination: Option<&(String, u16)>,
+) -> Result<std::net::TcpStream, anyhow::Error> {
+    let (addr, opts) = {
+        let config = lock_config(config)?;
+        (
+            config.dial_addr(destination)?,
+            config.socket_options.clone(),
+        )
+    };
+
+    // the WATM only reads after polling, as with the connections made by the blocking host_dial
+    let conn = crate::runtime::socket::connect_async(&addr, &opts).await?;
+
+    lock_config(config)?.set_dialed(&conn);
+    Ok(conn)
+}
+
+/// Accept a connection from the listener of the V0Config without holding its lock while accepting
+#[cfg(feature = "async")]
+async fn accept_async(config: &Mutex<V0Config>) -> Result<std::net::TcpStream, anyhow::Error> {
+    use std::os::fd::BorrowedFd;
+
+    let listener_fd = lock_config(config)?.listener_fd()?;
+
+    // the listener stays owned by the V0Config, Tokio accepts with a dup of it
+    // (which also makes the listener non-blocking, only the async host_accept is using it)
+    let listener = unsafe { BorrowedFd::borrow_raw(listener_fd) }.try_clone_to_owned()?;
+    let listener = std::net::TcpListener::from(listener);
+    listener.set_nonblocking(true)?;
+
+    let (conn, _) = tokio::net::TcpListener::from_std(listener)?
+        .accept()
+        .await?;
+    let conn = conn.into_std()?;
+    conn.set_nonblocking(false)?;
+
+    let mut config = lock_config(config)?;
+    crate::runtime::socket::apply_accepted(&conn, &config.socket_options)?;
+    config.set_accepted(&conn);
+    Ok(conn)
+}
+
+#[cfg(feature = "async")]
+fn lock_config(
+    config: &Mutex<V0Config>,
+) -> Result<std::sync::MutexGuard<'_, V0Config>, anyhow::Error> {
+    config
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Failed to lock v0_conf: {}", e))
+}
+
+/// Push the connection into the WATM, counting the traffic going thru it as the network side of the connection
+#[cfg(feature = "async")]
+fn push_network_file(caller: &mut Caller<'_, Host>, socket_file: Box<dyn WasiFile>) -> i32 {
+    let socket_file = caller.data().stats.wrap_network_file(socket_file);
+
+    let ctx = match caller.data_mut().preview1_ctx.as_mut() {
+        Some(ctx) => ctx,
+        None => {
+            info!("[HOST] WATERCore V0 preview1_ctx in Store is None");
+            return -1;
+        }
+    };
+
+    match ctx.push_file(socket_file, FileAccessMode::all()) {
+        Ok(fd) => fd as i32,
+        Err(e) => {
+            info!("[HOST] WATERCore V0 failed to push file into WASM: {}", e);
+            -1
+        }
+    }
+}
@@ -1,24 +1,26 @@
 //! This file contains the v0_plus WATERListener implementation,
 //! it implements the WATERListenerTrait and WATERTransportTrait.
 
-use crate::runtime::{listener::WATERListenerTrait, transport::WATERTransportTrait, *};
+use crate::runtime::{
+    core::CompiledWatm, listener::WATERListenerTrait, transport::WATERTransportTrait, *,
+};
 
 pub struct WATERListener<Host> {
     /// the pipe for communcating between Host and WASM
-    pub caller_io: Option<UnixStream>,
-    /// the UnixStream side for communcating between Host and WASM
-    pub cancel_io: Option<UnixStream>,
+    pub caller_io: Option<PipeStream>,
+    /// the pipe for cancelling the WATM worker
+    pub cancel_io: Option<PipeStream>,
 
     /// core WASM runtime (engine, linker, instance, store, module)
     pub core: H2O<Host>,
 }
 
 impl WATERTransportTrait for WATERListener<Host> {
-    fn get_caller_io(&mut self) -> &mut Option<UnixStream> {
+    fn get_caller_io(&mut self) -> &mut Option<PipeStream> {
         &mut self.caller_io
     }
 
-    fn get_cancel_io(&mut self) -> &mut Option<UnixStream> {
+    fn get_cancel_io(&mut self) -> &mut Option<PipeStream> {
         &mut self.cancel_io
     }
 
@@ -26,11 +28,11 @@ impl WATERTransportTrait for WATERListener<Host> {
         &mut self.core
     }
 
-    fn set_caller_io(&mut self, caller_io: Option<UnixStream>) {
+    fn set_caller_io(&mut self, caller_io: Option<PipeStream>) {
         self.caller_io = caller_io;
     }
 
-    fn set_cancel_io(&mut self, cancel_io: Option<UnixStream>) {
+    fn set_cancel_io(&mut self, cancel_io: Option<PipeStream>) {
         self.cancel_io = cancel_io;
     }
 }
@@ -56,6 +58,8 @@ impl WATERListenerTrait for WATERListener<Host> {
             }
         }
 
+        self.core.record_v0_listener_addr()?;
+
         Ok(())
     }
 
@@ -63,17 +67,9 @@ impl WATERListenerTrait for WATERListener<Host> {
     fn accept(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERListener v0 accepting...");
 
-        let (caller_io, water_io) = UnixStream::pair()?;
+        let (caller_io, water_io) = PipeStream::pair();
         self.caller_io = Some(caller_io);
 
-        // push the WATM end of the Unixpipe to WATM
-        let water_io_file = unsafe { cap_std::fs::File::from_raw_fd(water_io.as_raw_fd()) };
-
-        // insert file here
-        let water_io_file = wasmtime_wasi::sync::file::File::from_cap_std(water_io_file);
-
-        std::mem::forget(water_io); // forget the water_io, so that it won't be closed
-
         let mut store = self
             .core
             .store
@@ -86,7 +82,8 @@ impl WATERListenerTrait for WATERListener<Host> {
             .as_mut()
             .context("Failed to retrieve preview1_ctx from Host")?;
 
-        let water_io_fd = ctx.push_file(Box::new(water_io_file), FileAccessMode::all())?;
+        // push the WATM end of the pipe to WATM
+        let water_io_fd = ctx.push_file(Box::new(water_io), FileAccessMode::all())?;
 
         let _water_accept = match self.core.instance.get_func(&mut *store, ACCEPT_FN) {
             Some(func) => func,
@@ -103,12 +100,7 @@ impl WATERListenerTrait for WATERListener<Host> {
         let mut res = vec![Val::I32(0); _water_accept.ty(&*store).results().len()];
         match _water_accept.call(&mut *store, &params, &mut res) {
             Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function failed: {}",
-                    ACCEPT_FN, e
-                )))
-            }
+            Err(e) => return Err(store.data().guest_error(ACCEPT_FN, e)),
         }
 
         if res[0].unwrap_i32() < 0 {
@@ -136,11 +128,15 @@ impl WATERListener<Host> {
     }
 
     /// Migrates the listener from one WATM instance to another, where every newly accept()'ed connection will be handled by a separate WATM instance.
-    pub fn migrate_listener(_conf: &WATERConfig, core: &H2O<Host>) -> Result<Self, anyhow::Error> {
+    pub fn migrate_listener(
+        _conf: &WATERConfig,
+        watm: &CompiledWatm,
+        core: &H2O<Host>,
+    ) -> Result<Self, anyhow::Error> {
         info!("[HOST] WATERListener v0 migrating listener...");
 
         let mut new_core =
-            core::H2O::v0_migrate_core(_conf, core).context("Failed to migrate core")?;
+            core::H2O::v0_migrate_core(_conf, watm, core).context("Failed to migrate core")?;
         new_core._prepare(_conf)?;
 
         WATERListener::init(_conf, new_core)
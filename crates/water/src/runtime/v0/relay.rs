@@ -1,24 +1,26 @@
 //! This file contains the v0_plus WATERRelay implementation,
 //! it implements the WATERRelayTrait and WATERTransportTrait.
 
-use crate::runtime::{relay::WATERRelayTrait, transport::WATERTransportTrait, *};
+use crate::runtime::{
+    core::CompiledWatm, relay::WATERRelayTrait, transport::WATERTransportTrait, *,
+};
 
 pub struct WATERRelay<Host> {
     /// the pipe for communcating between Host and WASM
-    pub caller_io: Option<UnixStream>,
-    /// the UnixStream side for communcating between Host and WASM
-    pub cancel_io: Option<UnixStream>,
+    pub caller_io: Option<PipeStream>,
+    /// the pipe for cancelling the WATM worker
+    pub cancel_io: Option<PipeStream>,
 
     /// core WASM runtime (engine, linker, instance, store, module)
     pub core: H2O<Host>,
 }
 
 impl WATERTransportTrait for WATERRelay<Host> {
-    fn get_caller_io(&mut self) -> &mut Option<UnixStream> {
+    fn get_caller_io(&mut self) -> &mut Option<PipeStream> {
         &mut self.caller_io
     }
 
-    fn get_cancel_io(&mut self) -> &mut Option<UnixStream> {
+    fn get_cancel_io(&mut self) -> &mut Option<PipeStream> {
         &mut self.cancel_io
     }
 
@@ -26,11 +28,11 @@ impl WATERTransportTrait for WATERRelay<Host> {
         &mut self.core
     }
 
-    fn set_caller_io(&mut self, caller_io: Option<UnixStream>) {
+    fn set_caller_io(&mut self, caller_io: Option<PipeStream>) {
         self.caller_io = caller_io;
     }
 
-    fn set_cancel_io(&mut self, cancel_io: Option<UnixStream>) {
+    fn set_cancel_io(&mut self, cancel_io: Option<PipeStream>) {
         self.cancel_io = cancel_io;
     }
 }
@@ -60,12 +62,7 @@ impl WATERRelayTrait for WATERRelay<Host> {
         let mut res = vec![Val::I32(0); _water_associate.ty(&*store).results().len()];
         match _water_associate.call(&mut *store, &[], &mut res) {
             Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function failed: {}",
-                    ASSOCIATE_FN, e
-                )))
-            }
+            Err(e) => return Err(store.data().guest_error(ASSOCIATE_FN, e)),
         }
 
         if res[0].unwrap_i32() < 0 {
@@ -99,6 +96,8 @@ impl WATERRelayTrait for WATERRelay<Host> {
             }
         }
 
+        self.core.record_v0_listener_addr()?;
+
         Ok(())
     }
 }
@@ -117,11 +116,15 @@ impl WATERRelay<Host> {
     }
 
     /// Migrates the listener in Relay from one WATM instance to another, where every newly accept()'ed connection will be handled by a separate WATM instance.
-    pub fn migrate_listener(_conf: &WATERConfig, core: &H2O<Host>) -> Result<Self, anyhow::Error> {
+    pub fn migrate_listener(
+        _conf: &WATERConfig,
+        watm: &CompiledWatm,
+        core: &H2O<Host>,
+    ) -> Result<Self, anyhow::Error> {
         info!("[HOST] WATERelay v0 migrating listener...");
 
         let mut new_core =
-            core::H2O::v0_migrate_core(_conf, core).context("Failed to migrate core")?;
+            core::H2O::v0_migrate_core(_conf, watm, core).context("Failed to migrate core")?;
         new_core._prepare(_conf)?;
 
         WATERRelay::init(_conf, new_core)
@@ -6,7 +6,7 @@ use crate::runtime::{stream::WATERStreamTrait, transport::WATERTransportTrait, *
 /// This file contains the WATERStream implementation
 /// which is a TcpStream liked definition with utilizing WASM
 /// ```ignore
-///           UnixSocket          Connection created with Host
+///           PipeStream          Connection created with Host
 ///    Write =>  u2w  +----------------+  w2n
 ///            -----> |  WATERStream   | ------>
 ///    Caller         |  WASM Runtime  |  n2w    Destination
@@ -14,23 +14,22 @@ use crate::runtime::{stream::WATERStreamTrait, transport::WATERTransportTrait, *
 ///    Read  =>  w2u  +----------------+
 ///                      WATERStream
 /// ```
-
 pub struct WATERStream<Host> {
     /// the pipe for communcating between Host and WASM
-    pub caller_io: Option<UnixStream>,
-    /// the UnixStream side for communcating between Host and WASM
-    pub cancel_io: Option<UnixStream>,
+    pub caller_io: Option<PipeStream>,
+    /// the pipe for cancelling the WATM worker
+    pub cancel_io: Option<PipeStream>,
 
     /// core WASM runtime (engine, linker, instance, store, module)
     pub core: H2O<Host>,
 }
 
 impl WATERTransportTrait for WATERStream<Host> {
-    fn get_caller_io(&mut self) -> &mut Option<UnixStream> {
+    fn get_caller_io(&mut self) -> &mut Option<PipeStream> {
         &mut self.caller_io
     }
 
-    fn get_cancel_io(&mut self) -> &mut Option<UnixStream> {
+    fn get_cancel_io(&mut self) -> &mut Option<PipeStream> {
         &mut self.cancel_io
     }
 
@@ -38,11 +37,11 @@ impl WATERTransportTrait for WATERStream<Host> {
         &mut self.core
     }
 
-    fn set_caller_io(&mut self, caller_io: Option<UnixStream>) {
+    fn set_caller_io(&mut self, caller_io: Option<PipeStream>) {
         self.caller_io = caller_io;
     }
 
-    fn set_cancel_io(&mut self, cancel_io: Option<UnixStream>) {
+    fn set_cancel_io(&mut self, cancel_io: Option<PipeStream>) {
         self.cancel_io = cancel_io;
     }
 }
@@ -52,17 +51,9 @@ impl WATERStreamTrait for WATERStream<Host> {
     fn connect(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERStream v0 connecting...");
 
-        let (caller_io, water_io) = UnixStream::pair()?;
+        let (caller_io, water_io) = PipeStream::pair();
         self.caller_io = Some(caller_io);
 
-        // push the WATM end of the Unixpipe to WATM
-        let water_io_file = unsafe { cap_std::fs::File::from_raw_fd(water_io.as_raw_fd()) };
-
-        // insert file here
-        let water_io_file = wasmtime_wasi::sync::file::File::from_cap_std(water_io_file);
-
-        std::mem::forget(water_io); // forget the water_io, so that it won't be closed
-
         let mut store = self
             .core
             .store
@@ -75,7 +66,8 @@ impl WATERStreamTrait for WATERStream<Host> {
             .as_mut()
             .context("Failed to retrieve preview1_ctx from Host")?;
 
-        let water_io_fd = ctx.push_file(Box::new(water_io_file), FileAccessMode::all())?;
+        // push the WATM end of the pipe to WATM
+        let water_io_fd = ctx.push_file(Box::new(water_io), FileAccessMode::all())?;
 
         let _water_dial = match self.core.instance.get_func(&mut *store, DIAL_FN) {
             Some(func) => func,
@@ -92,12 +84,7 @@ impl WATERStreamTrait for WATERStream<Host> {
         let mut res = vec![Val::I32(0); _water_dial.ty(&*store).results().len()];
         match _water_dial.call(&mut *store, &params, &mut res) {
             Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function failed: {}",
-                    DIAL_FN, e
-                )))
-            }
+            Err(e) => return Err(store.data().guest_error(DIAL_FN, e)),
         }
 
         if res[0].unwrap_i32() < 0 {
@@ -46,38 +46,76 @@ pub fn export_tcp_connect(linker: &mut Linker<Host>) -> Result<(), anyhow::Error
                     _ => ("Wrong".into(), 0),
                 };
 
-                let tcp = match (host.as_str(), port) {
-                    ("localhost", port) => std::net::TcpStream::connect(SocketAddr::V4(
-                        SocketAddrV4::new(Ipv4Addr::LOCALHOST, port),
-                    )),
-                    addr => std::net::TcpStream::connect(addr),
+                // the destination picked by the Host for this connection takes over the one of the WATM
+                let (host, port) = caller.data().destination.clone().unwrap_or((host, port));
+
+                // the connection provided by the application is pushed instead of dialing
+                let wrapped = match caller.data_mut().take_wrapped() {
+                    std::result::Result::Ok(wrapped) => wrapped,
+                    Err(e) => {
+                        info!("[HOST] Failed to take the wrapped connection: {:#}", e);
+                        return -1;
+                    }
+                };
+                if let Some(conn) = wrapped {
+                    info!("[HOST] wrapping the provided connection instead of dialing");
+                    caller.data().stats.record_socket_addrs(&conn);
+                    return push_socket(&mut caller, conn.into_wasi_file());
                 }
-                .map(TcpStream::from_std)
-                .context(format!(
-                    "Failed to connect to {}:{} in Host exported dial",
-                    host, port
-                ))
-                .unwrap();
-
-                // Connecting Tcp
-                let socket_file: Box<dyn WasiFile> = wasmtime_wasi::net::Socket::from(tcp).into();
 
-                // Get the WasiCtx of the caller(WASM), then insert_file into it
-                let ctx: &mut WasiCtx = caller
-                    .data_mut()
-                    .preview1_ctx
-                    .as_mut()
-                    .context("preview1_ctx in Store is None")
-                    .unwrap();
-                ctx.push_file(socket_file, FileAccessMode::all())
-                    .context("Failed to push file into WASM")
-                    .unwrap() as i32
+                let opts = &caller.data().socket_options;
+                let tcp = match (host.trim_start_matches('[').trim_end_matches(']'), port) {
+                    ("localhost", port) => socket::connect(
+                        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)),
+                        opts,
+                    ),
+                    addr => socket::connect(addr, opts),
+                };
+
+                // a failed dial is reported to the WATM as a negative fd instead of panicking the Host
+                let tcp = match tcp {
+                    std::result::Result::Ok(tcp) => {
+                        metrics::registry().record_dial_success();
+                        caller.data().stats.record_socket_addrs(&tcp);
+                        TcpStream::from_std(tcp)
+                    }
+                    Err(e) => {
+                        let e = anyhow::Error::from(e).context(format!(
+                            "Failed to connect to {}:{} in Host exported dial",
+                            host, port
+                        ));
+                        info!("[HOST] {:#}", e);
+                        metrics::registry().record_dial_failure(&e);
+                        return -1;
+                    }
+                };
+
+                push_socket(&mut caller, wasmtime_wasi::net::Socket::from(tcp).into())
             },
         )
         .context("Failed to export Dial function to WASM")?;
     Ok(())
 }
 
+/// Push the connected `socket_file` into the WASI ctx of the WATM, returns its fd
+fn push_socket(caller: &mut Caller<'_, Host>, socket_file: Box<dyn WasiFile>) -> i32 {
+    // shut down when the instance is interrupted, as long as the WATM didn't close it
+    let socket_file = watm_socket::track(socket_file, &caller.data().interrupt);
+    // count the traffic going thru the socket as the network side of the connection
+    let socket_file = caller.data().stats.wrap_network_file(socket_file);
+
+    // Get the WasiCtx of the caller(WASM), then insert_file into it
+    let ctx: &mut WasiCtx = caller
+        .data_mut()
+        .preview1_ctx
+        .as_mut()
+        .context("preview1_ctx in Store is None")
+        .unwrap();
+    ctx.push_file(socket_file, FileAccessMode::all())
+        .context("Failed to push file into WASM")
+        .unwrap() as i32
+}
+
 /// This function is exporting the `create_listen(ptr: u32, size: u32) -> i32`
 /// to the WATM where it is used to create a tcp listener and returns the fd of the listener used by Listener & Relay.
 pub fn export_tcplistener_create(linker: &mut Linker<Host>) -> Result<(), anyhow::Error> {
@@ -118,11 +156,34 @@ pub fn export_tcplistener_create(linker: &mut Linker<Host>) -> Result<(), anyhow
                     _ => ("Wrong".into(), 0),
                 };
 
-                // Creating Tcp Listener
-                let tcp = std::net::TcpListener::bind((addr.as_str(), port)).unwrap();
+                // the listener already bound by the Host is pushed instead of binding another one,
+                // otherwise port 0 gets a port picked by the OS
+                let tcp = match caller.data_mut().take_listener() {
+                    std::result::Result::Ok(Some(tcp)) => std::result::Result::Ok(tcp),
+                    std::result::Result::Ok(None) => {
+                        socket::listen((addr.as_str(), port), &caller.data().socket_options)
+                            .map_err(Into::into)
+                    }
+                    Err(e) => Err(e),
+                };
+
+                // a failed bind is reported to the WATM as a negative fd instead of panicking the Host
+                let tcp = match tcp {
+                    std::result::Result::Ok(tcp) => tcp,
+                    Err(e) => {
+                        info!("[HOST] Failed to listen on {}:{}: {:#}", addr, port, e);
+                        return -1;
+                    }
+                };
+                caller.data().stats.record_socket_addrs(&tcp);
+
                 let tcp = TcpListener::from_std(tcp);
-                // tcp.set_nonblocking(true);
                 let socket_file: Box<dyn WasiFile> = wasmtime_wasi::net::Socket::from(tcp).into();
+                // the connections accepted are shut down when the instance is interrupted
+                let socket_file =
+                    watm_socket::track_accepted(socket_file, &caller.data().interrupt);
+                // count the traffic going thru the socket as the network side of the connection
+                let socket_file = caller.data().stats.wrap_network_file(socket_file);
 
                 // Get the WasiCtx of the caller(WASM), then insert_file into it
                 let ctx: &mut WasiCtx = caller
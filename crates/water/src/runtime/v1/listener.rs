@@ -1,7 +1,12 @@
 //! This file contains the v1_preview WATERListener implementation,
 //! it implements the WATERListenerTrait and WATERTransportTrait.
 
-use crate::runtime::{listener::WATERListenerTrait, transport::WATERTransportTrait, *};
+use crate::runtime::{
+    listener::WATERListenerTrait,
+    transport::WATERTransportTrait,
+    v1::{protocol, shared_buffer::SharedBuffer},
+    *,
+};
 
 pub struct WATERListener<Host> {
     /// the reader in WASM (read from net -- n2w)
@@ -12,16 +17,33 @@ pub struct WATERListener<Host> {
     /// returns the number of bytes written
     pub writer: Func,
 
-    /// the reader in Caller (read from WASM -- w2u)
-    pub caller_reader: UnixStream,
-    /// the writer in Caller (write to WASM -- u2w)
-    pub caller_writer: UnixStream,
+    /// the reader in Caller (read from WASM -- w2u), None when using the shared buffer
+    pub caller_reader: Option<PipeStream>,
+    /// the writer in Caller (write to WASM -- u2w), None when using the shared buffer
+    pub caller_writer: Option<PipeStream>,
+
+    /// the buffer in the WASM memory for communcating between Host and WASM, instead of the pipes
+    pub shared_buffer: Option<SharedBuffer>,
 
     /// core WASM runtime (engine, linker, instance, store, module)
     pub core: H2O<Host>,
 }
 
 impl WATERTransportTrait for WATERListener<Host> {
+    fn get_core(&mut self) -> &mut H2O<Host> {
+        &mut self.core
+    }
+
+    /// Nothing to set up, v1 instances are canceled by the Host (see `v1::cancel`)
+    fn cancel_with(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn cancel(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERListener v1_preview canceling...");
+        v1::cancel(&self.core)
+    }
+
     /// Read from the target address
     fn read(&mut self, buf: &mut Vec<u8>) -> Result<i64, anyhow::Error> {
         info!("[HOST] WATERListener v1_preview reading...");
@@ -33,57 +55,21 @@ impl WATERTransportTrait for WATERListener<Host> {
             Err(e) => return Err(anyhow::Error::msg(format!("Failed to lock store: {}", e))),
         };
 
-        let mut res = vec![Val::I64(0); self.reader.ty(&*store).results().len()];
-        match self.reader.call(&mut *store, &[], &mut res) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function failed: {}",
-                    READER_FN, e
-                )))
-            }
+        if let Some(shared_buffer) = &self.shared_buffer {
+            return shared_buffer.read(&mut store, &self.reader, buf);
         }
+        let caller_reader = self
+            .caller_reader
+            .as_mut()
+            .context("Failed to retrieve caller_reader of WATERListener")?;
 
-        let nums: i64 = match res.first() {
-            Some(wasmtime::Val::I64(v)) => *v,
-            _ => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function returned unexpected type / no return",
-                    READER_FN
-                )))
-            }
-        };
-
-        // read from WASM's caller_reader
-        buf.resize(nums as usize, 0);
-        match self.caller_reader.read(&mut buf[..]) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "failed to read from caller_reader: {}",
-                    e
-                )))
-            }
-        }
-
-        Ok(nums)
+        protocol::read(&mut store, &self.reader, caller_reader, buf)
     }
 
     /// Write to the target address
     fn write(&mut self, buf: &[u8]) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERListener v1_preview writing...");
 
-        // write to WASM's caller_writer
-        match self.caller_writer.write_all(buf) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "failed to write to caller_writer: {}",
-                    e
-                )))
-            }
-        }
-
         let store_lock_result = self.core.store.lock();
 
         let mut store = match store_lock_result {
@@ -91,39 +77,40 @@ impl WATERTransportTrait for WATERListener<Host> {
             Err(e) => return Err(anyhow::Error::msg(format!("Failed to lock store: {}", e))),
         };
 
-        let params = vec![Val::I64(buf.len() as i64)];
-        let mut res = vec![Val::I64(0)];
-        match self.writer.call(&mut *store, &params, &mut res) {
-            Ok(_) => {
-                match res.first() {
-                    Some(wasmtime::Val::I64(v)) => {
-                        if *v != buf.len() as i64 {
-                            return Err(anyhow::Error::msg(format!(
-                                "WASM write function returned unexpected value: {}",
-                                *v
-                            )));
-                        }
-                    }
-                    _ => {
-                        return Err(anyhow::Error::msg(
-                            "user_write_done function returned unexpected type / no return",
-                        ))
-                    }
-                };
-            }
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function failed: {}",
-                    WRITER_FN, e
-                )))
-            }
+        if let Some(shared_buffer) = &self.shared_buffer {
+            return shared_buffer.write(&mut store, &self.writer, buf);
         }
+        let caller_writer = self
+            .caller_writer
+            .as_mut()
+            .context("Failed to retrieve caller_writer of WATERListener")?;
 
-        Ok(())
+        protocol::write(&mut store, &self.writer, caller_writer, buf)
     }
 }
 
 impl WATERListenerTrait for WATERListener<Host> {
+    /// Bind the listener on the local addr:port of the config before the WATM asks for it in `create_listen`,
+    /// so the address is known (e.g. the port picked by the OS for port 0) before accepting
+    fn listen(&mut self, conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERListener v1_preview create listener...");
+
+        let config = v0::config::Config::from(&conf.config_wasm)?;
+        let addr = format!("{}:{}", config.local_address, config.local_port);
+        let listener = socket::listen(&addr, &conf.socket_options)
+            .context(format!("Failed to listen on {}", addr))?;
+        self.core.stats.record_socket_addrs(&listener);
+
+        let mut store = self
+            .core
+            .store
+            .lock()
+            .map_err(|e| anyhow::Error::msg(format!("Failed to lock store: {}", e)))?;
+        store.data_mut().listener = Some(Arc::new(listener));
+
+        Ok(())
+    }
+
     /// Listening at the addr:port with running the WASM listen function
     fn accept(&mut self, conf: &WATERConfig) -> Result<(), anyhow::Error> {
         info!("[HOST] WATERListener v1_preview listening...");
@@ -144,12 +131,7 @@ impl WATERListenerTrait for WATERListener<Host> {
 
         match fnc.call(&mut *store, &[], &mut []) {
             Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "connect function failed: {}",
-                    e
-                )))
-            }
+            Err(e) => return Err(store.data().guest_error(&conf.entry_fn, e)),
         }
 
         Ok(())
@@ -157,24 +139,14 @@ impl WATERListenerTrait for WATERListener<Host> {
 }
 
 impl WATERListener<Host> {
-    /// The constructor of WATERListener will create 2 pairs of UnixStream for communicating between WATM and Host
-    pub fn init(_conf: &WATERConfig, core: H2O<Host>) -> Result<Self, anyhow::Error> {
+    /// The constructor of WATERListener will create 2 one-way pipes for communicating between WATM and Host,
+    /// or a shared buffer in the WATM memory instead when `v1_shared_buffer` is set and the WATM supports it
+    pub fn init(conf: &WATERConfig, core: H2O<Host>) -> Result<Self, anyhow::Error> {
         info!("[HOST] WATERListener v1_preview init...");
 
-        // constructing 2 pairs of UnixStream for communicating between WASM and Host
-        // returns (read_end, write_end) for caller
-        let (caller_reader, water_writer) = UnixStream::pair()?;
-        let (water_reader, caller_writer) = UnixStream::pair()?;
-
-        let water_write_file = unsafe { cap_std::fs::File::from_raw_fd(water_writer.as_raw_fd()) };
-        let water_read_file = unsafe { cap_std::fs::File::from_raw_fd(water_reader.as_raw_fd()) };
-
-        // insert file here
-        let wasi_water_reader = wasmtime_wasi::sync::file::File::from_cap_std(water_read_file);
-        let wasi_water_writer = wasmtime_wasi::sync::file::File::from_cap_std(water_write_file);
-
-        std::mem::forget(water_writer);
-        std::mem::forget(water_reader);
+        let mut caller_reader = None;
+        let mut caller_writer = None;
+        let shared_buffer;
 
         let reader;
         let writer;
@@ -184,57 +156,76 @@ impl WATERListener<Host> {
                 .store
                 .lock()
                 .map_err(|e| anyhow::Error::msg(format!("Failed to lock store: {}", e)))?;
-            let ctx = store
-                .data_mut()
-                .preview1_ctx
-                .as_mut()
-                .ok_or(anyhow::anyhow!("preview1_ctx in Store is None"))?;
-            let water_reader_fd =
-                ctx.push_file(Box::new(wasi_water_reader), FileAccessMode::all())?;
-            let water_writer_fd =
-                ctx.push_file(Box::new(wasi_water_writer), FileAccessMode::all())?;
-
-            let water_bridging = match core.instance.get_func(&mut *store, WATER_BRIDGING_FN) {
-                Some(func) => func,
-                None => {
-                    return Err(anyhow::Error::msg(format!(
-                        "{} function not found in WASM",
-                        WATER_BRIDGING_FN
-                    )))
-                }
+
+            shared_buffer = match conf.v1_shared_buffer {
+                true => SharedBuffer::init(&mut store, &core.instance)?,
+                false => None,
             };
 
-            let params = vec![
-                Val::I32(water_reader_fd as i32),
-                Val::I32(water_writer_fd as i32),
-            ];
-            match water_bridging.call(&mut *store, &params, &mut []) {
-                Ok(_) => {}
-                Err(e) => {
-                    return Err(anyhow::Error::msg(format!(
-                        "{} function failed: {}",
-                        WATER_BRIDGING_FN, e
-                    )))
+            let (reader_fn, writer_fn) = match shared_buffer {
+                Some(_) => {
+                    info!("[HOST] WATERListener v1_preview using the shared buffer");
+                    (READ_BUF_FN, WRITE_BUF_FN)
                 }
-            }
+                None => {
+                    // constructing 2 one-way pipes for communicating between WASM and Host
+                    // returns (read_end, write_end)
+                    let (reader_end, water_writer) = PipeStream::simplex();
+                    let (water_reader, writer_end) = PipeStream::simplex();
+                    caller_reader = Some(reader_end);
+                    caller_writer = Some(writer_end);
+
+                    let ctx = store
+                        .data_mut()
+                        .preview1_ctx
+                        .as_mut()
+                        .ok_or(anyhow::anyhow!("preview1_ctx in Store is None"))?;
+                    let water_reader_fd =
+                        ctx.push_file(Box::new(water_reader), FileAccessMode::all())?;
+                    let water_writer_fd =
+                        ctx.push_file(Box::new(water_writer), FileAccessMode::all())?;
+
+                    let water_bridging =
+                        match core.instance.get_func(&mut *store, WATER_BRIDGING_FN) {
+                            Some(func) => func,
+                            None => {
+                                return Err(anyhow::Error::msg(format!(
+                                    "{} function not found in WASM",
+                                    WATER_BRIDGING_FN
+                                )))
+                            }
+                        };
+
+                    let params = vec![
+                        Val::I32(water_reader_fd as i32),
+                        Val::I32(water_writer_fd as i32),
+                    ];
+                    match water_bridging.call(&mut *store, &params, &mut []) {
+                        Ok(_) => {}
+                        Err(e) => return Err(store.data().guest_error(WATER_BRIDGING_FN, e)),
+                    }
+
+                    (READER_FN, WRITER_FN)
+                }
+            };
 
             // getting reader & writer func from WASM
-            reader = match core.instance.get_func(&mut *store, READER_FN) {
+            reader = match core.instance.get_func(&mut *store, reader_fn) {
                 Some(func) => func,
                 None => {
                     return Err(anyhow::Error::msg(format!(
                         "{} function not found in WASM",
-                        READER_FN
+                        reader_fn
                     )))
                 }
             };
 
-            writer = match core.instance.get_func(&mut *store, WRITER_FN) {
+            writer = match core.instance.get_func(&mut *store, writer_fn) {
                 Some(func) => func,
                 None => {
                     return Err(anyhow::Error::msg(format!(
                         "{} function not found in WASM",
-                        WRITER_FN
+                        writer_fn
                     )))
                 }
             };
@@ -246,6 +237,7 @@ impl WATERListener<Host> {
 
             caller_reader,
             caller_writer,
+            shared_buffer,
 
             core,
         };
@@ -2,4 +2,16 @@
 
 pub mod funcs;
 pub mod listener;
+pub mod protocol;
+pub mod shared_buffer;
 pub mod stream;
+
+use crate::runtime::*;
+
+/// Cancel a v1 instance from the Host, v1 WATMs don't take a cancel pipe as the v0 ones do: the instance is interrupted
+/// (trapping the WATM waiting in the scheduler) and its network connections, dialed or accepted, are shut down
+/// (returning from the blocking reads / writes of the WATM on them). The instance can't be used afterwards.
+pub fn cancel(core: &H2O<Host>) -> Result<(), anyhow::Error> {
+    core.interrupt.request();
+    Ok(())
+}
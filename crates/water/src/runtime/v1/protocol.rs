@@ -0,0 +1,105 @@
+//! The v1 protocol between the Host and the WATM for moving the bytes of a connection through the pipes,
+//! shared by the v1 WATERStream and WATERListener:
+//!
+//! - `_water_write(len) -> i64`: the Host has written exactly `len` bytes (at most [`PIPE_CAPACITY`]) into the pipe,
+//!   the WATM reads all of them, sends them out and returns `len`. Larger buffers are written chunk by chunk,
+//!   and each call only returns once its chunk is sent, which is the backpressure of the Host.
+//! - `_water_read() -> i64`: the WATM reads from the network once, writes what it got (at most [`PIPE_CAPACITY`])
+//!   into the pipe and returns the number of bytes written. The Host then reads back exactly these bytes.
+//!   `0` signals the EOF of the connection.
+//!
+//! Both return a negative number when the WATM fails.
+
+use crate::runtime::{pipe::PIPE_CAPACITY, *};
+
+/// Call `reader` (`_water_read`) then read back the bytes it wrote into `caller_reader`, `buf` is resized to fit them.
+/// Returns 0 on EOF.
+pub fn read(
+    store: &mut Store<Host>,
+    reader: &Func,
+    caller_reader: &mut PipeStream,
+    buf: &mut Vec<u8>,
+) -> Result<i64, anyhow::Error> {
+    let mut res = vec![Val::I64(0); reader.ty(&*store).results().len()];
+    match reader.call(&mut *store, &[], &mut res) {
+        Ok(_) => {}
+        Err(e) => return Err(store.data().guest_error(READER_FN, e)),
+    }
+
+    let nums: i64 = match res.first() {
+        Some(wasmtime::Val::I64(v)) => *v,
+        _ => {
+            return Err(anyhow::Error::msg(format!(
+                "{} function returned unexpected type / no return",
+                READER_FN
+            )))
+        }
+    };
+
+    if nums < 0 || nums as usize > PIPE_CAPACITY {
+        return Err(anyhow::Error::msg(format!(
+            "{} function returned unexpected value: {}",
+            READER_FN, nums
+        )));
+    }
+
+    // read exactly what the WATM wrote, not to leave any of it for the next read
+    buf.resize(nums as usize, 0);
+    match caller_reader.read_exact(&mut buf[..]) {
+        Ok(_) => {}
+        Err(e) => {
+            return Err(anyhow::Error::msg(format!(
+                "failed to read from caller_reader: {}",
+                e
+            )))
+        }
+    }
+
+    Ok(nums)
+}
+
+/// Write `buf` into `caller_writer` and call `writer` (`_water_write`) for the WATM to send it, chunk by chunk
+pub fn write(
+    store: &mut Store<Host>,
+    writer: &Func,
+    caller_writer: &mut PipeStream,
+    buf: &[u8],
+) -> Result<(), anyhow::Error> {
+    for chunk in buf.chunks(PIPE_CAPACITY) {
+        // never blocks, the WATM has read everything in the pipe before returning from the last call
+        match caller_writer.write_all(chunk) {
+            Ok(_) => {}
+            Err(e) => {
+                return Err(anyhow::Error::msg(format!(
+                    "failed to write to caller_writer: {}",
+                    e
+                )))
+            }
+        }
+
+        let params = vec![Val::I64(chunk.len() as i64)];
+        let mut res = vec![Val::I64(0)];
+        match writer.call(&mut *store, &params, &mut res) {
+            Ok(_) => {}
+            Err(e) => return Err(store.data().guest_error(WRITER_FN, e)),
+        }
+
+        match res.first() {
+            Some(wasmtime::Val::I64(v)) if *v == chunk.len() as i64 => {}
+            Some(wasmtime::Val::I64(v)) => {
+                return Err(anyhow::Error::msg(format!(
+                    "{} function returned unexpected value: {}",
+                    WRITER_FN, *v
+                )));
+            }
+            _ => {
+                return Err(anyhow::Error::msg(format!(
+                    "{} function returned unexpected type / no return",
+                    WRITER_FN
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
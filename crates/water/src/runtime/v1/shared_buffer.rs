@@ -0,0 +1,131 @@
+//! The shared buffer data path of v1, an alternative to the pipes for high-throughput relays:
+//! the Host allocates a region in the linear memory of the WATM with `_water_alloc`, then copies the bytes
+//! directly into / out of it when calling `_water_write_buf(ptr, len)` / `_water_read_buf(ptr, cap)`.
+//!
+//! It is used when `v1_shared_buffer` is set in the [`WATERConfig`] and the WATM exports all 3 functions,
+//! otherwise the v1 WATM is bridged with pipes as usual.
+
+use crate::runtime::*;
+
+/// Size of the region allocated in the WATM, the most bytes moved by one call of the WATM
+pub const SHARED_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A region in the linear memory of a WATM, shared between the Host and the WATM
+pub struct SharedBuffer {
+    memory: Memory,
+    ptr: usize,
+    len: usize,
+}
+
+impl SharedBuffer {
+    /// Allocate the region in the WATM if it supports the shared buffer data path, returns None if it doesn't
+    pub fn init(
+        store: &mut Store<Host>,
+        instance: &Instance,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let exported = [ALLOC_FN, READ_BUF_FN, WRITE_BUF_FN]
+            .iter()
+            .all(|name| instance.get_func(&mut *store, name).is_some());
+        if !exported {
+            return Ok(None);
+        }
+
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .context("memory not exported by WASM")?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&mut *store, ALLOC_FN)
+            .context(format!("{} function has an unexpected type", ALLOC_FN))?;
+
+        let ptr = match alloc.call(&mut *store, SHARED_BUFFER_SIZE as i32) {
+            Ok(ptr) => ptr,
+            Err(e) => return Err(store.data().guest_error(ALLOC_FN, e)),
+        };
+        if ptr <= 0 || ptr as usize + SHARED_BUFFER_SIZE > memory.data_size(&*store) {
+            return Err(anyhow::anyhow!(
+                "{} returned an invalid region at {}",
+                ALLOC_FN,
+                ptr
+            ));
+        }
+
+        Ok(Some(SharedBuffer {
+            memory,
+            ptr: ptr as usize,
+            len: SHARED_BUFFER_SIZE,
+        }))
+    }
+
+    /// Call `reader` (`_water_read_buf`) for the WATM to fill the region, then copy what it returned into `buf`
+    pub fn read(
+        &self,
+        store: &mut Store<Host>,
+        reader: &Func,
+        buf: &mut Vec<u8>,
+    ) -> Result<i64, anyhow::Error> {
+        let params = [Val::I32(self.ptr as i32), Val::I32(self.len as i32)];
+        let mut res = [Val::I64(0)];
+        if let Err(e) = reader.call(&mut *store, &params, &mut res) {
+            return Err(store.data().guest_error(READ_BUF_FN, e));
+        }
+
+        let nums = match res[0] {
+            Val::I64(n) if n >= 0 && n as usize <= self.len => n,
+            Val::I64(n) => {
+                return Err(anyhow::anyhow!(
+                    "{} function returned unexpected value: {}",
+                    READ_BUF_FN,
+                    n
+                ))
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{} function returned unexpected type / no return",
+                    READ_BUF_FN
+                ))
+            }
+        };
+
+        buf.resize(nums as usize, 0);
+        self.memory.read(&*store, self.ptr, &mut buf[..])?;
+
+        Ok(nums)
+    }
+
+    /// Copy `buf` into the region and call `writer` (`_water_write_buf`) for the WATM to send it, chunk by chunk
+    pub fn write(
+        &self,
+        store: &mut Store<Host>,
+        writer: &Func,
+        buf: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        for chunk in buf.chunks(self.len) {
+            self.memory.write(&mut *store, self.ptr, chunk)?;
+
+            let params = [Val::I32(self.ptr as i32), Val::I32(chunk.len() as i32)];
+            let mut res = [Val::I64(0)];
+            if let Err(e) = writer.call(&mut *store, &params, &mut res) {
+                return Err(store.data().guest_error(WRITE_BUF_FN, e));
+            }
+
+            match res[0] {
+                Val::I64(n) if n == chunk.len() as i64 => {}
+                Val::I64(n) => {
+                    return Err(anyhow::anyhow!(
+                        "{} function returned unexpected value: {}",
+                        WRITE_BUF_FN,
+                        n
+                    ))
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "{} function returned unexpected type / no return",
+                        WRITE_BUF_FN
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
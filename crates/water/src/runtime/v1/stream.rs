@@ -1,12 +1,17 @@
 //! This file contains the v1_preview WATERStream implementation,
 //! it implements the WATERStreamTrait and WATERTransportTrait.
 
-use crate::runtime::{stream::WATERStreamTrait, transport::WATERTransportTrait, *};
+use crate::runtime::{
+    stream::WATERStreamTrait,
+    transport::WATERTransportTrait,
+    v1::{protocol, shared_buffer::SharedBuffer},
+    *,
+};
 
 /// This file contains the WATERStream implementation
 /// which is a TcpStream liked definition with utilizing WASM
 /// ```ignore
-///           UnixSocket          Connection created with Host
+///           PipeStream          Connection created with Host
 ///    Write =>  u2w  +----------------+  w2n
 ///            -----> |  WATERStream   | ------>
 ///    Caller         |  WASM Runtime  |  n2w    Destination
@@ -14,7 +19,6 @@ use crate::runtime::{stream::WATERStreamTrait, transport::WATERTransportTrait, *
 ///    Read  =>  w2u  +----------------+
 ///                      WATERStream
 /// ```
-
 pub struct WATERStream<Host> {
     /// the reader in WASM (read from net -- n2w), returns the number of bytes read
     pub reader: Func,
@@ -22,14 +26,31 @@ pub struct WATERStream<Host> {
     /// the writer in WASM (write to net -- w2n), returns the number of bytes written
     pub writer: Func,
 
-    /// the pipe for communcating between Host and WASM
-    pub caller_io: UnixStream,
+    /// the pipe for communcating between Host and WASM, None when using the shared buffer
+    pub caller_io: Option<PipeStream>,
+
+    /// the buffer in the WASM memory for communcating between Host and WASM, instead of the pipe
+    pub shared_buffer: Option<SharedBuffer>,
 
     /// core WASM runtime (engine, linker, instance, store, module)
     pub core: H2O<Host>,
 }
 
 impl WATERTransportTrait for WATERStream<Host> {
+    fn get_core(&mut self) -> &mut H2O<Host> {
+        &mut self.core
+    }
+
+    /// Nothing to set up, v1 instances are canceled by the Host (see `v1::cancel`)
+    fn cancel_with(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn cancel(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERStream v1_preview canceling...");
+        v1::cancel(&self.core)
+    }
+
     /// Read from the target address thru the WATM module
     fn read(&mut self, buf: &mut Vec<u8>) -> Result<i64, anyhow::Error> {
         debug!("[HOST] WATERStream v1_preview reading...");
@@ -41,40 +62,15 @@ impl WATERTransportTrait for WATERStream<Host> {
             Err(e) => return Err(anyhow::Error::msg(format!("Failed to lock store: {}", e))),
         };
 
-        let mut res = vec![Val::I64(0); self.reader.ty(&*store).results().len()];
-        match self.reader.call(&mut *store, &[], &mut res) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function failed: {}",
-                    READER_FN, e
-                )))
-            }
-        }
-
-        let nums: i64 = match res.first() {
-            Some(wasmtime::Val::I64(v)) => *v,
-            _ => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function returned unexpected type / no return",
-                    READER_FN
-                )))
-            }
-        };
-
-        // read from WASM's caller_reader
-        buf.resize(nums as usize, 0);
-        match self.caller_io.read(&mut buf[..]) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "failed to read from caller_reader: {}",
-                    e
-                )))
-            }
+        if let Some(shared_buffer) = &self.shared_buffer {
+            return shared_buffer.read(&mut store, &self.reader, buf);
         }
+        let caller_io = self
+            .caller_io
+            .as_mut()
+            .context("Failed to retrieve caller_io of WATERStream")?;
 
-        Ok(nums)
+        protocol::read(&mut store, &self.reader, caller_io, buf)
     }
 
     /// Write to the target address thru the WATM module
@@ -88,46 +84,15 @@ impl WATERTransportTrait for WATERStream<Host> {
             Err(e) => return Err(anyhow::Error::msg(format!("Failed to lock store: {}", e))),
         };
 
-        // write to WASM's caller_writer
-        match self.caller_io.write_all(buf) {
-            Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "failed to write to caller_writer: {}",
-                    e
-                )))
-            }
-        }
-
-        let params = vec![Val::I64(buf.len() as i64)];
-        let mut res = vec![Val::I64(0)];
-        match self.writer.call(&mut *store, &params, &mut res) {
-            Ok(_) => {
-                match res.first() {
-                    Some(wasmtime::Val::I64(v)) => {
-                        if *v != buf.len() as i64 {
-                            return Err(anyhow::Error::msg(format!(
-                                "WASM write function returned unexpected value: {}",
-                                *v
-                            )));
-                        }
-                    }
-                    _ => {
-                        return Err(anyhow::Error::msg(
-                            "user_write_done function returned unexpected type / no return",
-                        ))
-                    }
-                };
-            }
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "{} function failed: {}",
-                    WRITER_FN, e
-                )))
-            }
+        if let Some(shared_buffer) = &self.shared_buffer {
+            return shared_buffer.write(&mut store, &self.writer, buf);
         }
+        let caller_io = self
+            .caller_io
+            .as_mut()
+            .context("Failed to retrieve caller_io of WATERStream")?;
 
-        Ok(())
+        protocol::write(&mut store, &self.writer, caller_io, buf)
     }
 }
 
@@ -155,12 +120,7 @@ impl WATERStreamTrait for WATERStream<Host> {
 
         match fnc.call(&mut *store, &[], &mut []) {
             Ok(_) => {}
-            Err(e) => {
-                return Err(anyhow::Error::msg(format!(
-                    "connect function failed: {}",
-                    e
-                )))
-            }
+            Err(e) => return Err(store.data().guest_error(DIAL_FN, e)),
         }
 
         Ok(())
@@ -168,18 +128,11 @@ impl WATERStreamTrait for WATERStream<Host> {
 }
 
 impl WATERStream<Host> {
-    pub fn init(_conf: &WATERConfig, core: H2O<Host>) -> Result<Self, anyhow::Error> {
+    pub fn init(conf: &WATERConfig, core: H2O<Host>) -> Result<Self, anyhow::Error> {
         info!("[HOST] WATERStream v1_preview...");
 
-        // constructing a pair of UnixStream for communicating between WASM and Host
-        let (caller_io, water_io) = UnixStream::pair()?;
-
-        let water_io_file = unsafe { cap_std::fs::File::from_raw_fd(water_io.as_raw_fd()) };
-
-        // insert file here
-        let water_io_file = wasmtime_wasi::sync::file::File::from_cap_std(water_io_file);
-
-        std::mem::forget(water_io); // forget the water_io, so that it won't be closed
+        let mut caller_io = None;
+        let shared_buffer;
 
         let reader;
         let writer;
@@ -190,51 +143,66 @@ impl WATERStream<Host> {
                 .lock()
                 .map_err(|e| anyhow::Error::msg(format!("Failed to lock store: {}", e)))?;
 
-            let ctx = store
-                .data_mut()
-                .preview1_ctx
-                .as_mut()
-                .context("Failed to retrieve preview1_ctx from Host")?;
-            let water_io_fd = ctx.push_file(Box::new(water_io_file), FileAccessMode::all())?;
+            shared_buffer = match conf.v1_shared_buffer {
+                true => SharedBuffer::init(&mut store, &core.instance)?,
+                false => None,
+            };
 
-            let water_bridging = match core.instance.get_func(&mut *store, WATER_BRIDGING_FN) {
-                Some(func) => func,
-                None => {
-                    return Err(anyhow::Error::msg(format!(
-                        "{} function not found in WASM",
-                        WATER_BRIDGING_FN
-                    )))
+            let (reader_fn, writer_fn) = match shared_buffer {
+                Some(_) => {
+                    info!("[HOST] WATERStream v1_preview using the shared buffer");
+                    (READ_BUF_FN, WRITE_BUF_FN)
                 }
-            };
+                None => {
+                    // constructing a pipe for communicating between WASM and Host
+                    let (caller, water_io) = PipeStream::pair();
+                    caller_io = Some(caller);
+
+                    let ctx = store
+                        .data_mut()
+                        .preview1_ctx
+                        .as_mut()
+                        .context("Failed to retrieve preview1_ctx from Host")?;
+                    let water_io_fd = ctx.push_file(Box::new(water_io), FileAccessMode::all())?;
+
+                    let water_bridging =
+                        match core.instance.get_func(&mut *store, WATER_BRIDGING_FN) {
+                            Some(func) => func,
+                            None => {
+                                return Err(anyhow::Error::msg(format!(
+                                    "{} function not found in WASM",
+                                    WATER_BRIDGING_FN
+                                )))
+                            }
+                        };
+
+                    let params: Vec<Val> = vec![Val::I32(water_io_fd as i32)];
+                    match water_bridging.call(&mut *store, &params, &mut []) {
+                        Ok(_) => {}
+                        Err(e) => return Err(store.data().guest_error(WATER_BRIDGING_FN, e)),
+                    }
 
-            let params: Vec<Val> = vec![Val::I32(water_io_fd as i32)];
-            match water_bridging.call(&mut *store, &params, &mut []) {
-                Ok(_) => {}
-                Err(e) => {
-                    return Err(anyhow::Error::msg(format!(
-                        "{} function failed: {}",
-                        WATER_BRIDGING_FN, e
-                    )))
+                    (READER_FN, WRITER_FN)
                 }
-            }
+            };
 
             // getting reader & writer func from WASM
-            reader = match core.instance.get_func(&mut *store, READER_FN) {
+            reader = match core.instance.get_func(&mut *store, reader_fn) {
                 Some(func) => func,
                 None => {
                     return Err(anyhow::Error::msg(format!(
                         "{} function not found in WASM",
-                        READER_FN
+                        reader_fn
                     )))
                 }
             };
 
-            writer = match core.instance.get_func(&mut *store, WRITER_FN) {
+            writer = match core.instance.get_func(&mut *store, writer_fn) {
                 Some(func) => func,
                 None => {
                     return Err(anyhow::Error::msg(format!(
                         "{} function not found in WASM",
-                        WRITER_FN
+                        writer_fn
                     )))
                 }
             };
@@ -245,6 +213,7 @@ impl WATERStream<Host> {
             writer,
 
             caller_io,
+            shared_buffer,
 
             core,
         };
@@ -0,0 +1,66 @@
+//! This file contains the V2 WATERListener implementation,
+//! it implements the WATERListenerTrait and WATERTransportTrait.
+
+use crate::runtime::{listener::WATERListenerTrait, transport::WATERTransportTrait, v2::V2Core, *};
+
+/// The V2 WATERListener, where the WATM listens and accepts 1 connection with wasi-sockets
+pub struct WATERListener {
+    /// core of the V2 WATM instance (engine, store, bindings of the transport)
+    pub core: V2Core,
+}
+
+impl WATERTransportTrait for WATERListener {
+    fn read(&mut self, buf: &mut Vec<u8>) -> Result<i64, anyhow::Error> {
+        debug!("[HOST] WATERListener V2 reading...");
+        self.core.read(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), anyhow::Error> {
+        debug!("[HOST] WATERListener V2 writing...");
+        self.core.write(buf)
+    }
+
+    /// Nothing to set up, the connection is closed by calling the WATM directly in `cancel`
+    fn cancel_with(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn cancel(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERListener V2 closing...");
+        self.core.close()
+    }
+
+    fn run_entry_fn(
+        &mut self,
+        _conf: &WATERConfig,
+    ) -> Result<std::thread::JoinHandle<Result<(), anyhow::Error>>, anyhow::Error> {
+        Err(anyhow::anyhow!(
+            "V2 WATMs don't have a worker, read / write are calling the WATM directly"
+        ))
+    }
+}
+
+impl WATERListenerTrait for WATERListener {
+    /// Listen on the local address in the config and accept 1 connection with the WATM accept function
+    fn accept(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERListener V2 accepting...");
+
+        let local_address = self.core.local_address();
+        self.core.call("accept", |transport, store| {
+            transport.call_accept(store, &local_address)
+        })
+    }
+
+    /// The WATM binds the listener itself when accepting
+    fn listen(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERListener V2 listening...");
+        Ok(())
+    }
+}
+
+impl WATERListener {
+    pub fn init(_conf: &WATERConfig, core: V2Core) -> Result<Self, anyhow::Error> {
+        info!("[HOST] WATERListener V2...");
+        Ok(WATERListener { core })
+    }
+}
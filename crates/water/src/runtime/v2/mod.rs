@@ -0,0 +1,264 @@
+//! The V2 runtime, where the WATM is a component implementing the `water:watm/transport` interface
+//! of the WIT world in `crates/water/wit/water.wit`.
+//!
+//! Different from v0 / v1, the WATM does its own networking with wasi-sockets (WASI preview2) instead of
+//! using the fds pushed by the Host, and exchanges the application data with the Host through the typed
+//! `read` / `write` functions instead of pipes and blobs at raw pointers.
+
+pub mod listener;
+pub mod relay;
+pub mod stream;
+
+use std::sync::Mutex;
+use std::time::Instant;
+
+use wasmtime::component::{Linker as ComponentLinker, ResourceTable};
+use wasmtime_wasi::preview2::{self, WasiView};
+
+use crate::{
+    config::StdioMode,
+    runtime::{
+        core::{Compiled, CompiledWatm},
+        v0::config::Config,
+        *,
+    },
+};
+
+wasmtime::component::bindgen!({
+    path: "wit",
+    world: "watm",
+    with: {
+        "wasi:io/error": preview2::bindings::io::error,
+        "wasi:io/poll": preview2::bindings::io::poll,
+        "wasi:io/streams": preview2::bindings::io::streams,
+        "wasi:clocks/monotonic-clock": preview2::bindings::clocks::monotonic_clock,
+        "wasi:sockets/network": preview2::bindings::sockets::network,
+        "wasi:sockets/instance-network": preview2::bindings::sockets::instance_network,
+        "wasi:sockets/tcp": preview2::bindings::sockets::tcp,
+        "wasi:sockets/tcp-create-socket": preview2::bindings::sockets::tcp_create_socket,
+    },
+});
+
+/// Magic and layer of the component binary format, core modules have `01 00 00 00` after the magic instead
+const COMPONENT_HEADER: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+
+/// Whether the WATM binary at `path` is a component, which is the V2 WATM
+pub fn is_component<P: AsRef<Path>>(path: P) -> Result<bool, anyhow::Error> {
+    let mut header = [0; COMPONENT_HEADER.len()];
+    let mut file = std::fs::File::open(path.as_ref()).context(format!(
+        "failed to read WATM module {}",
+        path.as_ref().display()
+    ))?;
+
+    match file.read_exact(&mut header) {
+        Ok(_) => Ok(header == COMPONENT_HEADER),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The store data of a V2 WATM instance
+pub struct V2Host {
+    pub table: ResourceTable,
+    pub ctx: preview2::WasiCtx,
+
+    /// traffic statistics of the connection handled by this WATM instance
+    pub stats: ConnStats,
+}
+
+impl WasiView for V2Host {
+    fn table(&self) -> &ResourceTable {
+        &self.table
+    }
+
+    fn table_mut(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    fn ctx(&self) -> &preview2::WasiCtx {
+        &self.ctx
+    }
+
+    fn ctx_mut(&mut self) -> &mut preview2::WasiCtx {
+        &mut self.ctx
+    }
+}
+
+/// The core of a V2 WATM instance, the counterpart of `H2O` for components
+#[derive(Clone)]
+pub struct V2Core {
+    pub version: Version,
+
+    pub engine: Engine,
+    pub store: Arc<Mutex<Store<V2Host>>>,
+    pub watm: Arc<Watm>,
+
+    /// addresses from the WATM config file
+    pub config: Config,
+
+    /// keeps this instance counted in the active instances metric until the last clone is dropped
+    pub instance_guard: Arc<metrics::ActiveInstance>,
+
+    /// traffic statistics of this instance, accessible without locking the store
+    pub stats: ConnStats,
+}
+
+impl V2Core {
+    /// Instantiate the V2 WATM component at `conf.filepath` and initialize it with the config file
+    pub fn init(conf: &WATERConfig) -> Result<Self, anyhow::Error> {
+        Self::instantiate(conf, &CompiledWatm::compile(conf)?)
+    }
+
+    /// Instantiate the V2 WATM component compiled in `watm` and initialize it with the config file
+    pub fn instantiate(conf: &WATERConfig, watm: &CompiledWatm) -> Result<Self, anyhow::Error> {
+        info!("[HOST] WATERCore V2 initing...");
+
+        let started = Instant::now();
+
+        let component = match &watm.compiled {
+            Compiled::Component(component) => component,
+            Compiled::Module(_) => {
+                return Err(anyhow::anyhow!(
+                    "V2 WATM has to be a component implementing the water:watm world"
+                ))
+            }
+        };
+        let engine = watm.engine.clone();
+
+        let mut linker = ComponentLinker::new(&engine);
+        preview2::command::sync::add_to_linker(&mut linker)?;
+
+        let mut builder = preview2::WasiCtxBuilder::new();
+        builder.inherit_network();
+        match &conf.stdio {
+            StdioMode::Null => {}
+            StdioMode::Inherit => {
+                builder.inherit_stdio();
+            }
+            mode => {
+                return Err(anyhow::anyhow!(
+                    "[HOST] WATERCore V2 doesn't support stdio mode {:?}",
+                    mode
+                ))
+            }
+        }
+
+        let stats = ConnStats::new(&conf.filepath);
+        let host = V2Host {
+            table: ResourceTable::new(),
+            ctx: builder.build(),
+            stats: stats.clone(),
+        };
+        let mut store = Store::new(&engine, host);
+
+        let (watm, _instance) = Watm::instantiate(&mut store, component, &linker)?;
+
+        // the config file is optional for V2, the WATM gets an empty one when it doesn't exist
+        let (config_bytes, config) = match std::fs::read(&conf.config_wasm) {
+            Ok(bytes) => {
+                let config = serde_json::from_slice(&bytes)
+                    .context(format!("failed to parse config file {}", conf.config_wasm))?;
+                (bytes, config)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (Vec::new(), Config::default()),
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("failed to read config file {}", conf.config_wasm)))
+            }
+        };
+
+        match watm
+            .water_watm_transport()
+            .call_init(&mut store, &config_bytes)
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(watm_error("init", e)),
+            Err(e) => return Err(guest_error("init", e)),
+        }
+
+        metrics::registry().observe_instantiation(started.elapsed());
+
+        Ok(V2Core {
+            version: Version::V2,
+            engine,
+            store: Arc::new(Mutex::new(store)),
+            watm: Arc::new(watm),
+            config,
+            instance_guard: Arc::new(metrics::ActiveInstance::new()),
+            stats,
+        })
+    }
+
+    /// Call a function of the transport interface of the WATM with the locked store, turning both the traps
+    /// and the errors returned by the WATM into the errors of `function`.
+    pub fn call<R>(
+        &self,
+        function: &str,
+        f: impl FnOnce(
+            &exports::water::watm::transport::Transport,
+            &mut Store<V2Host>,
+        ) -> Result<Result<R, String>>,
+    ) -> Result<R, anyhow::Error> {
+        let mut store = self
+            .store
+            .lock()
+            .map_err(|e| anyhow::Error::msg(format!("Failed to lock store: {}", e)))?;
+
+        match f(self.watm.water_watm_transport(), &mut store) {
+            Ok(Ok(r)) => Ok(r),
+            Ok(Err(e)) => Err(watm_error(function, e)),
+            Err(e) => Err(guest_error(function, e)),
+        }
+    }
+
+    /// Read the data decoded by the WATM into `buf`, as the `read` of the v0 / v1 WATMs
+    pub fn read(&self, buf: &mut [u8]) -> Result<i64, anyhow::Error> {
+        let data = self.call("read", |transport, store| {
+            transport.call_read(store, buf.len() as u32)
+        })?;
+
+        if data.is_empty() {
+            return Err(anyhow::Error::msg("Stream closed or read 0 bytes"));
+        }
+        if data.len() > buf.len() {
+            return Err(anyhow::Error::msg(format!(
+                "read function returned {} bytes, more than the {} requested",
+                data.len(),
+                buf.len()
+            )));
+        }
+
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len() as i64)
+    }
+
+    /// Hand all of `buf` to the WATM to be encoded and sent
+    pub fn write(&self, buf: &[u8]) -> Result<(), anyhow::Error> {
+        self.call("write", |transport, store| transport.call_write(store, buf))
+    }
+
+    /// Close the connection of the WATM
+    pub fn close(&self) -> Result<(), anyhow::Error> {
+        self.call("close", |transport, store| {
+            transport.call_close(store).map(Ok)
+        })
+    }
+
+    pub fn local_address(&self) -> String {
+        format!("{}:{}", self.config.local_address, self.config.local_port)
+    }
+
+    pub fn remote_address(&self) -> String {
+        format!("{}:{}", self.config.remote_address, self.config.remote_port)
+    }
+}
+
+/// Errors returned by the WATM through `result<_, string>`
+fn watm_error(function: &str, e: String) -> anyhow::Error {
+    anyhow::Error::msg(format!("{} function failed: {}", function, e))
+}
+
+/// Traps and other errors of calling into the WATM, counted in the metrics like the v0 / v1 ones
+fn guest_error(function: &str, e: anyhow::Error) -> anyhow::Error {
+    trap::guest_error(function, e, None)
+}
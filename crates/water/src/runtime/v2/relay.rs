@@ -0,0 +1,62 @@
+//! This file contains the V2 WATERRelay implementation,
+//! it implements the WATERRelayTrait and WATERTransportTrait.
+
+use crate::runtime::{relay::WATERRelayTrait, transport::WATERTransportTrait, v2::V2Core, *};
+
+/// The V2 WATERRelay, where the WATM accepts 1 connection, dials the remote address and relays
+/// between them all by itself with wasi-sockets
+pub struct WATERRelay {
+    /// core of the V2 WATM instance (engine, store, bindings of the transport)
+    pub core: V2Core,
+}
+
+impl WATERTransportTrait for WATERRelay {
+    /// Nothing to set up, the relay ends when either side is closed
+    fn cancel_with(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn cancel(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERRelay V2 closing...");
+        self.core.close()
+    }
+
+    /// Run the WATM associate function in a separate thread, which returns when the relay ends
+    fn run_entry_fn(
+        &mut self,
+        _conf: &WATERConfig,
+    ) -> Result<std::thread::JoinHandle<Result<(), anyhow::Error>>, anyhow::Error> {
+        info!("[HOST] WATERRelay V2 running associate...");
+
+        let core = self.core.clone();
+        let handle = std::thread::spawn(move || {
+            let (local_address, remote_address) = (core.local_address(), core.remote_address());
+            core.call("associate", |transport, store| {
+                transport.call_associate(store, &local_address, &remote_address)
+            })
+        });
+
+        Ok(handle)
+    }
+}
+
+impl WATERRelayTrait for WATERRelay {
+    /// The WATM accepts and dials by itself once the worker is running
+    fn associate(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERRelay V2 associating...");
+        Ok(())
+    }
+
+    /// The WATM binds the listener itself when associating
+    fn listen(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERRelay V2 listening...");
+        Ok(())
+    }
+}
+
+impl WATERRelay {
+    pub fn init(_conf: &WATERConfig, core: V2Core) -> Result<Self, anyhow::Error> {
+        info!("[HOST] WATERRelay V2...");
+        Ok(WATERRelay { core })
+    }
+}
@@ -0,0 +1,75 @@
+//! This file contains the V2 WATERStream implementation,
+//! it implements the WATERStreamTrait and WATERTransportTrait.
+
+use crate::runtime::{stream::WATERStreamTrait, transport::WATERTransportTrait, v2::V2Core, *};
+
+/// The V2 WATERStream, where the WATM dials the remote address with wasi-sockets
+/// ```ignore
+///           transport.write     wasi-sockets
+///    Write =>       +----------------+
+///            -----> |  WATERStream   | ------>
+///    Caller         |  WASM Runtime  |         Destination
+///            <----- | Decode/Encode  | <------
+///    Read  =>       +----------------+
+///           transport.read
+/// ```
+pub struct WATERStream {
+    /// core of the V2 WATM instance (engine, store, bindings of the transport)
+    pub core: V2Core,
+}
+
+impl WATERTransportTrait for WATERStream {
+    fn read(&mut self, buf: &mut Vec<u8>) -> Result<i64, anyhow::Error> {
+        debug!("[HOST] WATERStream V2 reading...");
+        self.core.read(buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<(), anyhow::Error> {
+        debug!("[HOST] WATERStream V2 writing...");
+        self.core.write(buf)
+    }
+
+    /// Nothing to set up, the connection is closed by calling the WATM directly in `cancel`
+    fn cancel_with(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    fn cancel(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERStream V2 closing...");
+        self.core.close()
+    }
+
+    fn run_entry_fn(
+        &mut self,
+        _conf: &WATERConfig,
+    ) -> Result<std::thread::JoinHandle<Result<(), anyhow::Error>>, anyhow::Error> {
+        Err(anyhow::anyhow!(
+            "V2 WATMs don't have a worker, read / write are calling the WATM directly"
+        ))
+    }
+}
+
+impl WATERStreamTrait for WATERStream {
+    /// Connect to the remote address in the config with the WATM dial function
+    fn connect(&mut self, _conf: &WATERConfig) -> Result<(), anyhow::Error> {
+        info!("[HOST] WATERStream V2 connecting...");
+
+        let remote_address = self.core.remote_address();
+        let res = self.core.call("dial", |transport, store| {
+            transport.call_dial(store, &remote_address)
+        });
+
+        match &res {
+            Ok(_) => metrics::registry().record_dial_success(),
+            Err(e) => metrics::registry().record_dial_failure(e),
+        }
+        res
+    }
+}
+
+impl WATERStream {
+    pub fn init(_conf: &WATERConfig, core: V2Core) -> Result<Self, anyhow::Error> {
+        info!("[HOST] WATERStream V2...");
+        Ok(WATERStream { core })
+    }
+}
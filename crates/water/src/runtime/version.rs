@@ -17,10 +17,7 @@ pub enum Version {
 
 impl Version {
     pub fn parse(s: &str) -> Option<Version> {
-        match Version::from_str(s) {
-            Ok(v) => Some(v),
-            Err(_) => None,
-        }
+        Version::from_str(s).ok()
     }
 
     /// Current API v0 needs some configurations at the beginning
@@ -29,45 +26,29 @@ impl Version {
 
         let wasm_config = Config::from(&conf.config_wasm)?;
 
-        let v = match conf.client_type {
-            WaterBinType::Dial => {
-                let v0_conf = V0Config::init(
-                    "CONNECT".into(),
-                    wasm_config.local_address.clone(),
-                    wasm_config.local_port,
-                    wasm_config.remote_address.clone(),
-                    wasm_config.remote_port,
-                )?;
-                Version::V0(Some(Arc::new(Mutex::new(v0_conf))))
-            }
-            WaterBinType::Listen => {
-                let v0_conf = V0Config::init(
-                    "LISTEN".into(),
-                    wasm_config.local_address.clone(),
-                    wasm_config.local_port,
-                    wasm_config.remote_address.clone(),
-                    wasm_config.remote_port,
-                )?;
-                Version::V0(Some(Arc::new(Mutex::new(v0_conf))))
-            }
-            WaterBinType::Relay => {
-                let v0_conf = V0Config::init(
-                    "RELAY".into(),
-                    wasm_config.local_address.clone(),
-                    wasm_config.local_port,
-                    wasm_config.remote_address.clone(),
-                    wasm_config.remote_port,
-                )?;
-                Version::V0(Some(Arc::new(Mutex::new(v0_conf))))
-            }
+        let name = match conf.client_type {
+            // a wrapped connection is driven by the WATM as a dialed one
+            WaterBinType::Dial | WaterBinType::Wrap => "CONNECT",
+            WaterBinType::Listen => "LISTEN",
+            WaterBinType::Relay => "RELAY",
             WaterBinType::Unknown => {
-                Version::Unknown // WATER is setting up?
+                return Ok(Version::Unknown); // WATER is setting up?
             }
             _ => {
                 unimplemented!("This client type is not supported yet")
             }
         };
 
+        let mut v0_conf = V0Config::init(
+            name.into(),
+            wasm_config.local_address.clone(),
+            wasm_config.local_port,
+            wasm_config.remote_address.clone(),
+            wasm_config.remote_port,
+        )?;
+        v0_conf.socket_options = conf.socket_options.clone();
+        let v = Version::V0(Some(Arc::new(Mutex::new(v0_conf))));
+
         Ok(v)
     }
 
@@ -28,3 +28,51 @@ pub fn export_config(linker: &mut Linker<Host>, config_file: String) -> Result<(
         .context("Failed to export config function to WASM")?;
     Ok(())
 }
+
+/// exporting a function `host_log(level: i32, ptr: u32, len: u32)` that will be used
+/// for WATM to forward its log messages (UTF-8 string at ptr with len) into the Host's tracing pipeline,
+/// where level is 1 = ERROR, 2 = WARN, 3 = INFO, 4 = DEBUG, 5 = TRACE.
+///
+/// The events are emitted under the `watm` target within a span carrying the module and connection id,
+/// DEBUG and TRACE events are dropped unless `debug` is enabled in the WATERConfig.
+pub fn export_log(linker: &mut Linker<Host>, debug: bool) -> Result<(), anyhow::Error> {
+    linker
+        .func_wrap(
+            "env",
+            "host_log",
+            move |mut caller: Caller<'_, Host>, level: i32, ptr: u32, len: u32| {
+                if !debug && level > 3 {
+                    return;
+                }
+
+                let memory = match caller.get_export("memory") {
+                    Some(Extern::Memory(memory)) => memory,
+                    _ => return,
+                };
+
+                let data = memory.data(&caller);
+                let msg = match data.get(ptr as usize..(ptr as usize).saturating_add(len as usize))
+                {
+                    Some(bytes) => String::from_utf8_lossy(bytes),
+                    None => {
+                        debug!("[HOST] host_log called with out of bounds message");
+                        return;
+                    }
+                };
+
+                let stats = &caller.data().stats;
+                let span = tracing::info_span!("watm", module = stats.module(), conn = stats.id());
+                let _enter = span.enter();
+
+                match level {
+                    1 => tracing::error!(target: "watm", "{}", msg),
+                    2 => tracing::warn!(target: "watm", "{}", msg),
+                    3 => tracing::info!(target: "watm", "{}", msg),
+                    4 => tracing::debug!(target: "watm", "{}", msg),
+                    _ => tracing::trace!(target: "watm", "{}", msg),
+                }
+            },
+        )
+        .context("Failed to export log function to WASM")?;
+    Ok(())
+}
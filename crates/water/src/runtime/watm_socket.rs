@@ -0,0 +1,206 @@
+//! The network sockets pushed into the WATM as seen by the Host, which doesn't own them: each one is known by its fd
+//! until the WATM closes it, so the Host can shut it down (e.g. to cancel the instance) without keeping a dup of it
+//! open past the close -- the peer gets its EOF as soon as the WATM is done with the socket.
+
+use std::{
+    any::Any,
+    io::{IoSlice, IoSliceMut},
+    net::Shutdown,
+    os::fd::{AsRawFd, BorrowedFd, RawFd},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use socket2::SockRef;
+use wasi_common::{
+    file::{Advice, FdFlags, FileType, Filestat, RiFlags, RoFlags, SdFlags, SiFlags},
+    Error, SystemTimeSpec, WasiFile,
+};
+
+use crate::runtime::Interrupt;
+
+/// A socket owned by the WATM, `None` once the WATM closed it
+#[derive(Clone, Default)]
+pub struct WatmSocket {
+    fd: Arc<Mutex<Option<RawFd>>>,
+}
+
+impl WatmSocket {
+    /// Shut down the socket if the WATM didn't close it yet
+    pub fn shutdown(&self, how: Shutdown) {
+        // the lock is held for the fd not to be closed (and reused) meanwhile
+        if let Some(fd) = *lock(&self.fd) {
+            let socket = unsafe { BorrowedFd::borrow_raw(fd) };
+            let _ = SockRef::from(&socket).shutdown(how);
+        }
+    }
+
+    /// The fd of the socket, only valid until the WATM runs again as it may close it
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        *lock(&self.fd)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        lock(&self.fd).is_none()
+    }
+}
+
+/// Wrap the connection `file` pushed into (or accepted by) the WATM, it is shut down when `interrupt` is requested
+/// as long as the WATM didn't close it
+pub(crate) fn track(file: Box<dyn WasiFile>, interrupt: &Interrupt) -> Box<dyn WasiFile> {
+    let socket = WatmSocket {
+        fd: Arc::new(Mutex::new(file.pollable().map(|fd| fd.as_raw_fd()))),
+    };
+    interrupt.shutdown_on_request(socket.clone());
+    Box::new(TrackedFile {
+        socket,
+        interrupt: interrupt.clone(),
+        inner: file,
+    })
+}
+
+/// Wrap the listener `file` pushed into the WATM, the connections it accepts are tracked as the ones of [`track`]
+pub(crate) fn track_accepted(file: Box<dyn WasiFile>, interrupt: &Interrupt) -> Box<dyn WasiFile> {
+    Box::new(TrackedFile {
+        socket: WatmSocket::default(),
+        interrupt: interrupt.clone(),
+        inner: file,
+    })
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A `WasiFile` wrapper forgetting the fd of its `WatmSocket` when the WATM closes it
+struct TrackedFile {
+    socket: WatmSocket,
+    interrupt: Interrupt,
+    inner: Box<dyn WasiFile>,
+}
+
+impl Drop for TrackedFile {
+    fn drop(&mut self) {
+        // before `inner` is dropped, closing the fd
+        *lock(&self.socket.fd) = None;
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiFile for TrackedFile {
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        self.inner.get_filetype().await
+    }
+
+    fn pollable(&self) -> Option<BorrowedFd<'_>> {
+        self.inner.pollable()
+    }
+
+    fn isatty(&self) -> bool {
+        self.inner.isatty()
+    }
+
+    async fn sock_accept(&self, fdflags: FdFlags) -> Result<Box<dyn WasiFile>, Error> {
+        let file = self.inner.sock_accept(fdflags).await?;
+        Ok(track(file, &self.interrupt))
+    }
+
+    async fn sock_recv<'a>(
+        &self,
+        ri_data: &mut [IoSliceMut<'a>],
+        ri_flags: RiFlags,
+    ) -> Result<(u64, RoFlags), Error> {
+        self.inner.sock_recv(ri_data, ri_flags).await
+    }
+
+    async fn sock_send<'a>(
+        &self,
+        si_data: &[IoSlice<'a>],
+        si_flags: SiFlags,
+    ) -> Result<u64, Error> {
+        self.inner.sock_send(si_data, si_flags).await
+    }
+
+    async fn sock_shutdown(&self, how: SdFlags) -> Result<(), Error> {
+        self.inner.sock_shutdown(how).await
+    }
+
+    async fn datasync(&self) -> Result<(), Error> {
+        self.inner.datasync().await
+    }
+
+    async fn sync(&self) -> Result<(), Error> {
+        self.inner.sync().await
+    }
+
+    async fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        self.inner.get_fdflags().await
+    }
+
+    async fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.inner.set_fdflags(flags).await
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.inner.get_filestat().await
+    }
+
+    async fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        self.inner.set_filestat_size(size).await
+    }
+
+    async fn advise(&self, offset: u64, len: u64, advice: Advice) -> Result<(), Error> {
+        self.inner.advise(offset, len, advice).await
+    }
+
+    async fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        self.inner.set_times(atime, mtime).await
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        self.inner.read_vectored(bufs).await
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        self.inner.read_vectored_at(bufs, offset).await
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        self.inner.write_vectored(bufs).await
+    }
+
+    async fn write_vectored_at<'a>(&self, bufs: &[IoSlice<'a>], offset: u64) -> Result<u64, Error> {
+        self.inner.write_vectored_at(bufs, offset).await
+    }
+
+    async fn seek(&self, pos: std::io::SeekFrom) -> Result<u64, Error> {
+        self.inner.seek(pos).await
+    }
+
+    async fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        self.inner.peek(buf).await
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        self.inner.num_ready_bytes()
+    }
+
+    async fn readable(&self) -> Result<(), Error> {
+        self.inner.readable().await
+    }
+
+    async fn writable(&self) -> Result<(), Error> {
+        self.inner.writable().await
+    }
+}
@@ -11,6 +11,10 @@
 
 use super::*;
 
+/// Size of the buffers used for one read from the network / one chunk sent to it,
+/// what is written to the Host in one `_water_read` call is at most this size after decoding
+pub const BUFFER_SIZE: usize = 4096;
+
 // ConnStream can store either a network stream Or a file stream
 pub enum ConnStream {
     TcpStream(std::net::TcpStream),
@@ -62,6 +66,17 @@ impl ConnFile {
         }
     }
 
+    /// Read exactly `buf.len()` bytes, fails on EOF before that
+    pub fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), anyhow::Error> {
+        match &mut self.file {
+            Some(stream) => stream
+                .as_read()
+                .read_exact(buf)
+                .map_err(anyhow::Error::from),
+            None => Err(anyhow::anyhow!("ConnFile's file is None")),
+        }
+    }
+
     pub fn write(&mut self, buf: &[u8]) -> Result<(), anyhow::Error> {
         match &mut self.file {
             Some(stream) => match stream {
@@ -133,39 +148,16 @@ impl<T> Connection<T> {
         self.outbound_conn.file = Some(stream);
     }
 
-    /// this _read function is triggered by the Host to read from the remote connection
+    /// this _read function is triggered by the Host to read from the remote connection,
+    /// the decoded bytes are written to the inbound connection and their number is returned, 0 means EOF
     pub fn _read_from_outbound<D: Decoder>(
         &mut self,
         decoder: &mut D,
     ) -> Result<i64, anyhow::Error> {
         debug!("[WASM] running in _read_from_net");
 
-        let mut buf = vec![0u8; 4096];
-        let bytes_read: i64 = match self.outbound_conn.read(&mut buf) {
-            Ok(n) => n,
-            Err(e) => {
-                // eprintln!("[WASM] > ERROR in _read when reading from outbound: {:?}", e);
-                // return -1; // Or another sentinel value to indicate error}
-                return Err(anyhow::anyhow!(
-                    "[WASM] > ERROR in _read when reading from outbound: {:?}",
-                    e
-                ));
-            }
-        };
-
-        // NOTE: decode logic here
-        let mut decoded = vec![0u8; 4096];
-        let len_after_decoding = match decoder.decode(&buf[..bytes_read as usize], &mut decoded) {
-            Ok(n) => n,
-            Err(e) => {
-                // eprintln!("[WASM] > ERROR in _write when encoding: {:?}", e);
-                // return -1; // Or another sentinel value to indicate error
-                return Err(anyhow::anyhow!(
-                    "[WASM] > ERROR in _write when encoding: {:?}",
-                    e
-                ));
-            }
-        };
+        let mut decoded = vec![0u8; BUFFER_SIZE];
+        let len_after_decoding = self._read_from_outbound_to_buf(decoder, &mut decoded)?;
 
         match self
             .inbound_conn
@@ -173,8 +165,6 @@ impl<T> Connection<T> {
         {
             Ok(_) => {}
             Err(e) => {
-                // eprintln!("[WASM] > ERROR in _read when writing to inbound: {:?}", e);
-                // return -1; // Or another sentinel value to indicate error
                 return Err(anyhow::anyhow!(
                     "[WASM] > ERROR in _read when writing to inbound: {:?}",
                     e
@@ -182,10 +172,12 @@ impl<T> Connection<T> {
             }
         }
 
-        Ok(len_after_decoding as i64)
+        Ok(len_after_decoding)
     }
 
-    /// this _write function is triggered by the Host to write to the remote connection
+    /// this _write function is triggered by the Host to write to the remote connection,
+    /// it reads exactly `bytes_write` bytes from the inbound connection and sends them encoded chunk by chunk,
+    /// returns `bytes_write` once everything is sent
     pub fn _write_2_outbound<E: Encoder>(
         &mut self,
         encoder: &mut E,
@@ -193,41 +185,98 @@ impl<T> Connection<T> {
     ) -> Result<i64, anyhow::Error> {
         debug!("[WASM] running in _write_2_net");
 
+        let mut buf = vec![0u8; BUFFER_SIZE];
         let mut bytes_read: i64 = 0;
-        let mut buf = vec![0u8; 4096];
+        while bytes_read < bytes_write {
+            let len = std::cmp::min(BUFFER_SIZE as i64, bytes_write - bytes_read) as usize;
+            match self.inbound_conn.read_exact(&mut buf[..len]) {
+                Ok(_) => {}
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "[WASM] > ERROR in _write when reading from inbound: {:?}",
+                        e
+                    ));
+                }
+            }
+
+            self._write_2_outbound_from_buf(encoder, &buf[..len])?;
+            bytes_read += len as i64;
+        }
+
+        Ok(bytes_read)
+    }
+
+    /// the shared buffer version of `_read_from_outbound`, which decodes what is read from the remote connection
+    /// into `buf` (the region shared with the Host) instead of writing it to the inbound connection
+    pub fn _read_from_outbound_to_buf<D: Decoder>(
+        &mut self,
+        decoder: &mut D,
+        buf: &mut [u8],
+    ) -> Result<i64, anyhow::Error> {
+        debug!("[WASM] running in _read_from_net_to_buf");
+
+        let mut read_buf = vec![0u8; buf.len()];
         loop {
-            let read = match self.inbound_conn.read(&mut buf) {
+            let bytes_read = match self.outbound_conn.read(&mut read_buf) {
                 Ok(n) => n,
                 Err(e) => {
-                    // eprintln!("[WASM] > ERROR in _read when reading from inbound: {:?}", e);
-                    // return -1; // Or another sentinel value to indicate error
                     return Err(anyhow::anyhow!(
-                        "[WASM] > ERROR in _read when reading from inbound: {:?}",
+                        "[WASM] > ERROR in _read when reading from outbound: {:?}",
                         e
                     ));
                 }
             };
 
-            bytes_read += read;
+            // EOF of the remote connection
+            if bytes_read == 0 {
+                return Ok(0);
+            }
+
+            // NOTE: decode logic here
+            let len_after_decoding = match decoder.decode(&read_buf[..bytes_read as usize], buf) {
+                Ok(n) => n,
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "[WASM] > ERROR in _read when decoding: {:?}",
+                        e
+                    ));
+                }
+            };
 
-            if read == 0 || bytes_read == bytes_write {
-                break;
+            // 0 is kept for EOF, read more when the decoder needs more bytes to output anything
+            if len_after_decoding > 0 {
+                return Ok(len_after_decoding as i64);
             }
         }
+    }
+
+    /// the shared buffer version of `_write_2_outbound`, which encodes the bytes in `buf` (the region shared with the Host)
+    /// instead of reading them from the inbound connection
+    pub fn _write_2_outbound_from_buf<E: Encoder>(
+        &mut self,
+        encoder: &mut E,
+        buf: &[u8],
+    ) -> Result<i64, anyhow::Error> {
+        debug!("[WASM] running in _write_2_net_from_buf");
 
         // NOTE: encode logic here
-        let mut encoded = vec![0u8; 4096];
-        let len_after_encoding = match encoder.encode(&buf[..bytes_read as usize], &mut encoded) {
+        let mut encoded = vec![0u8; encoder.encoded_len(buf.len())];
+        let len_after_encoding = match encoder.encode(buf, &mut encoded) {
             Ok(n) => n,
             Err(e) => {
-                // eprintln!("[WASM] > ERROR in _write when encoding: {:?}", e);
-                // return -1; // Or another sentinel value to indicate error
                 return Err(anyhow::anyhow!(
                     "[WASM] > ERROR in _write when encoding: {:?}",
                     e
                 ));
             }
         };
+        if len_after_encoding as usize > encoded.len() {
+            return Err(anyhow::anyhow!(
+                "[WASM] > ERROR in _write: encoded {} bytes, more than the {} of the output buffer",
+                len_after_encoding,
+                encoded.len()
+            ));
+        }
 
         match self
             .outbound_conn
@@ -235,16 +284,14 @@ impl<T> Connection<T> {
         {
             Ok(_) => {}
             Err(e) => {
-                // eprintln!("[WASM] > ERROR in _read when writing to outbound: {:?}", e);
-                // return -1; // Or another sentinel value to indicate error
                 return Err(anyhow::anyhow!(
-                    "[WASM] > ERROR in _read when writing to outbound: {:?}",
+                    "[WASM] > ERROR in _write when writing to outbound: {:?}",
                     e
                 ));
             }
         }
 
-        Ok(len_after_encoding as i64)
+        Ok(buf.len() as i64)
     }
 
     pub fn close_inbound(&mut self) {
@@ -283,3 +330,50 @@ impl<T> Connection<T> {
         self.outbound_conn.fd = -1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{TcpListener, TcpStream};
+    use std::os::fd::AsRawFd;
+
+    /// An encoder doubling every byte, the output is twice as long as the input
+    struct DoublingEncoder;
+
+    impl Encoder for DoublingEncoder {
+        fn encode(&self, input: &[u8], output: &mut [u8]) -> Result<u32, anyhow::Error> {
+            for (i, byte) in input.iter().enumerate() {
+                output[2 * i] = *byte;
+                output[2 * i + 1] = *byte;
+            }
+            Ok(2 * input.len() as u32)
+        }
+
+        fn encoded_len(&self, input_len: usize) -> usize {
+            2 * input_len
+        }
+    }
+
+    #[test]
+    fn test_write_2_outbound_from_buf_expanding_encoder() -> Result<(), anyhow::Error> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let mut conn: Connection<()> = Connection::new(());
+        let stream = TcpStream::connect(listener.local_addr()?)?;
+        conn.set_outbound(stream.as_raw_fd(), ConnStream::TcpStream(stream));
+        let (mut remote, _) = listener.accept()?;
+
+        let input: Vec<u8> = (0..BUFFER_SIZE).map(|i| i as u8).collect();
+        assert_eq!(
+            conn._write_2_outbound_from_buf(&mut DoublingEncoder, &input)?,
+            input.len() as i64
+        );
+
+        let mut received = vec![0u8; 2 * input.len()];
+        remote.read_exact(&mut received)?;
+        let expected: Vec<u8> = input.iter().flat_map(|byte| [*byte, *byte]).collect();
+        assert_eq!(received, expected);
+
+        Ok(())
+    }
+}
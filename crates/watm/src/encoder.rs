@@ -7,6 +7,12 @@ use tokio::io::AsyncWrite;
 /// A trait for a encoder, developers should implement this trait and pass it to _write_to_outbound
 pub trait Encoder {
     fn encode(&self, input: &[u8], output: &mut [u8]) -> Result<u32, anyhow::Error>;
+
+    /// The size of the output buffer `encode` needs for `input_len` bytes of input, encoders growing
+    /// the data (e.g. with headers / tags / padding) have to override it
+    fn encoded_len(&self, input_len: usize) -> usize {
+        input_len
+    }
 }
 
 /// A default encoder that does just copy + paste
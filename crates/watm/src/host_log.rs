@@ -0,0 +1,82 @@
+//! This module forwards the `tracing` events of the WATM to the Host with the Host exported function
+//! `host_log`, so that they show up in the Host's tracing pipeline (with the module and connection span)
+//! instead of being mixed into the inherited stdio.
+//!
+//! ```ignore
+//! #[export_name = "_water_init"]
+//! pub fn _init() {
+//!     water_watm::init_host_logging(Level::INFO).unwrap();
+//! }
+//! ```
+
+use std::fmt::{Debug, Write};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    layer::{Context, SubscriberExt},
+    util::SubscriberInitExt,
+    Layer,
+};
+
+use crate::host_log;
+
+/// A `tracing_subscriber` layer sending every event to the Host thru `host_log`
+pub struct HostLogLayer;
+
+impl<S: Subscriber> Layer<S> for HostLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        log(*event.metadata().level(), &visitor.0);
+    }
+}
+
+/// Collects the message of an event followed by its other fields as `key=value`
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.push_str(value);
+        } else {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+}
+
+/// Send a single message to the Host's tracing pipeline at the given level
+pub fn log(level: Level, msg: &str) {
+    let level = match level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        Level::TRACE => 5,
+    };
+
+    unsafe {
+        host_log(level, msg.as_ptr() as u32, msg.len() as u32);
+    }
+}
+
+/// Set the global subscriber of the WATM to forward the events up to `max_level` to the Host
+pub fn init_host_logging(max_level: Level) -> Result<(), anyhow::Error> {
+    tracing_subscriber::registry()
+        .with(HostLogLayer)
+        .with(LevelFilter::from_level(max_level))
+        .try_init()
+        .map_err(|e| anyhow::anyhow!("failed to set the global subscriber: {}", e))
+}
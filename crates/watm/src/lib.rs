@@ -5,6 +5,8 @@ pub mod connections;
 pub mod decoder;
 pub mod dialer;
 pub mod encoder;
+pub mod host_log;
+pub mod shared_buffer;
 pub mod version;
 // pub mod net;
 // pub mod listener_in_wasm;
@@ -14,6 +16,8 @@ pub use connections::*;
 pub use decoder::*;
 pub use dialer::*;
 pub use encoder::*;
+pub use host_log::*;
+pub use shared_buffer::*;
 // pub use net::*;
 // pub use listener_in_wasm::*;
 
@@ -45,4 +49,8 @@ extern "C" {
 
     /// create a TcpStream connection (specified by returned fd) -- pass ptr + size for the ip:port struct sharing to Host
     pub fn connect_tcp(ptr: u32, size: u32) -> i32;
+
+    /// forward a log message (UTF-8 string at ptr with len) into the Host's tracing pipeline,
+    /// level is 1 = ERROR, 2 = WARN, 3 = INFO, 4 = DEBUG, 5 = TRACE
+    pub fn host_log(level: i32, ptr: u32, len: u32);
 }
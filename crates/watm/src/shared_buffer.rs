@@ -0,0 +1,35 @@
+//! Helpers for the shared buffer data path of v1, where the Host copies the bytes directly into / out of
+//! a region in the linear memory of the WATM instead of going through the pipes.
+//!
+//! A WATM supporting it exports these 3 functions along with the usual v1 ones:
+//! ```ignore
+//! #[export_name = "_water_alloc"]
+//! pub fn _alloc(size: i32) -> i32 {
+//!     alloc_shared_buffer(size)
+//! }
+//!
+//! #[export_name = "_water_read_buf"]
+//! pub fn _read_buf(ptr: i32, cap: i32) -> i64 // fill the region with what is read, returns the length
+//!
+//! #[export_name = "_water_write_buf"]
+//! pub fn _write_buf(ptr: i32, len: i32) -> i64 // send the bytes in the region, returns the length sent
+//! ```
+
+/// Allocate a region of `size` bytes for the Host to share, it is never freed as the Host keeps using it
+pub fn alloc_shared_buffer(size: i32) -> i32 {
+    if size <= 0 {
+        return -1;
+    }
+
+    let buf = vec![0u8; size as usize].into_boxed_slice();
+    Box::leak(buf).as_mut_ptr() as i32
+}
+
+/// Get the region at `ptr` with `len` bytes as a slice
+///
+/// # Safety
+///
+/// `ptr` and `len` must be the ones passed by the Host, within the region returned by [`alloc_shared_buffer`]
+pub unsafe fn shared_buffer<'a>(ptr: i32, len: i32) -> &'a mut [u8] {
+    std::slice::from_raw_parts_mut(ptr as *mut u8, len as usize)
+}
@@ -1,130 +1,535 @@
-use water::config::{WATERConfig, WaterBinType};
-use water::globals::{CONFIG_WASM_PATH, MAIN, WASM_PATH};
-use water::runtime::client::WATERClient;
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream},
+    os::fd::{FromRawFd, RawFd},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
 
-use clap::Parser;
+use water::config::{registry::Registry, StdioMode, WATERConfig, WaterBinType};
+use water::globals::{CONFIG_WASM_PATH, MAIN, REGISTRY_PATH, WASM_PATH};
+use water::runtime::{
+    client::WATERClient,
+    core::CompiledWatm,
+    http_proxy::{HttpProxyServer, DEFAULT_DIAL_TIMEOUT},
+    metrics,
+    relay_server::RelayServer,
+    socks5::Socks5Server,
+    supervisor::RunnerSupervisor,
+};
+
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use tempfile::NamedTempFile;
+use tracing::{error, info, Level};
+
+/// Name of the worker function of the v0 WATMs, the default entry_fn of dial / listen / relay
+const WORKER_FN: &str = "_water_worker";
+
+/// Default limit of the connections served at once by dial / listen / relay
+const DEFAULT_MAX_CONNECTIONS: usize = 256;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+struct Cli {
+    /// Log level of the Host (and the WATM logs forwarded to it): error, warn, info, debug or trace
+    #[arg(long, global = true, default_value_t = Level::INFO)]
+    log_level: Level,
+
+    /// Optional argument specifying the local address (e.g. 127.0.0.1:9100) to serve the Prometheus metrics on
+    #[arg(long, global = true)]
+    metrics_addr: Option<String>,
+
+    /// Optional argument specifying the local registry directory of WATM packages
+    #[arg(long, global = true, default_value_t = String::from(REGISTRY_PATH))]
+    registry: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Listen on the local address, and tunnel each connection accepted there through a v0 / v1 WATM dialing the remote address
+    Dial(ServeArgs),
+
+    /// Accept connections with a v0 WATM listening on the local address, and forward each of them to the remote address
+    Listen(ServeArgs),
+
+    /// Relay with a v0 WATM listening on the local address and dialing the remote address for each connection
+    Relay(ServeArgs),
+
+    /// Serve SOCKS5 on the local address, tunneling each connection through a v0 / v1 WATM dialing the destination requested
+    Socks5(Socks5Args),
+
+    /// Serve HTTP proxy (CONNECT and absolute-form GET) on the local address, tunneling each request through a v0 / v1 WATM dialing the destination requested
+    HttpProxy(HttpProxyArgs),
+
+    /// Run a v0 / v1 WATM over the connected socket inherited as fd (e.g. from systemd or inetd), and forward the transformed stream to the remote address
+    Wrap(WrapArgs),
+
+    /// Run the entry_fn of a WATM handling everything itself (e.g. the v1 shadowsocks client) until it exits
+    Run(RunArgs),
+
+    /// List the WATM packages in the registry
+    List,
+}
+
+/// Picking the WATM and overriding its config, shared by the subcommands running one
+#[derive(Args, Debug)]
+struct WatmArgs {
     /// Optional argument specifying the .wasm file to load
     #[arg(short, long, default_value_t = String::from(WASM_PATH))]
     wasm_path: String,
 
-    /// Optional argument specifying name of the function in the .wasm file to use
-    #[arg(short, long, default_value_t = String::from(MAIN))]
-    entry_fn: String,
+    /// Optional argument specifying name of the function in the .wasm file to use,
+    /// default to be `_water_worker` for dial / listen / relay / wrap and `main` for run
+    #[arg(short, long)]
+    entry_fn: Option<String>,
 
     /// Optional argument specifying the config file
     #[arg(short, long, default_value_t = String::from(CONFIG_WASM_PATH))]
     config_wasm: String,
 
-    /// Optional argument specifying the client_type, default to be Runner
-    #[arg(short, long, default_value_t = 3)]
-    type_client: u32,
+    /// Optional argument picking the transport from the registry as name[@version_req] (e.g. plain@^0.1),
+    /// instead of the wasm_path, entry_fn and config_wasm arguments
+    #[arg(long)]
+    transport: Option<String>,
+
+    /// Optional argument overriding the local address:port in the config file
+    #[arg(short, long)]
+    local: Option<String>,
 
-    /// Optional argument enabling debug logging
-    #[arg(short, long, default_value_t = true)]
-    debug: bool,
+    /// Optional argument overriding the remote address:port in the config file
+    #[arg(short, long)]
+    remote: Option<String>,
 }
 
-impl From<Args> for WATERConfig {
-    fn from(args: Args) -> Self {
-        Self {
-            filepath: args.wasm_path,
-            entry_fn: args.entry_fn,
-            config_wasm: args.config_wasm,
-            client_type: WaterBinType::from(args.type_client),
-            debug: args.debug,
-        }
-    }
+#[derive(Args, Debug)]
+struct ServeArgs {
+    #[command(flatten)]
+    watm: WatmArgs,
+
+    /// Optional argument limiting the connections served at once, the next ones wait to be accepted until one ends
+    #[arg(long, default_value_t = DEFAULT_MAX_CONNECTIONS)]
+    max_connections: usize,
 }
 
-pub fn parse() -> Result<WATERConfig, anyhow::Error> {
-    // Parse command-line arguments and execute the appropriate commands
-    let conf: WATERConfig = Args::parse().into();
-    Ok(conf)
+#[derive(Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    watm: WatmArgs,
+
+    /// Restart the WATM when it traps, with an exponential backoff
+    #[arg(long)]
+    restart: bool,
+
+    /// Optional argument limiting the restarts in a row with `--restart`, giving up afterwards
+    #[arg(long, requires = "restart")]
+    max_restarts: Option<usize>,
 }
 
-pub async fn parse_and_execute() -> Result<(), anyhow::Error> {
-    execute(parse()?).await
+#[derive(Args, Debug)]
+struct Socks5Args {
+    #[command(flatten)]
+    watm: WatmArgs,
+
+    /// Optional argument requiring the SOCKS5 clients to authenticate with this username (and the password)
+    #[arg(long, requires = "password")]
+    username: Option<String>,
+
+    /// Optional argument requiring the SOCKS5 clients to authenticate with this password (and the username)
+    #[arg(long, requires = "username")]
+    password: Option<String>,
 }
 
-pub async fn execute(_conf: WATERConfig) -> Result<(), anyhow::Error> {
-    let mut water_client = WATERClient::new(_conf).unwrap();
+#[derive(Args, Debug)]
+struct HttpProxyArgs {
+    #[command(flatten)]
+    watm: WatmArgs,
 
-    match water_client.config.client_type {
-        WaterBinType::Dial => {
-            water_client.connect().unwrap();
-        }
-        WaterBinType::Runner => {
-            // generally for v1_preview shadowsocks client
-            water_client.execute().unwrap();
-        }
-        WaterBinType::Listen => {
-            water_client.listen().unwrap();
-
-            loop {
-                water_client.accept().unwrap();
-                let next_water_client = water_client.keep_listen().unwrap();
-                handle_incoming(water_client).await.unwrap();
-                water_client = next_water_client;
+    /// Optional argument specifying the seconds for the WATM to dial before answering 504 Gateway Timeout
+    #[arg(long, default_value_t = DEFAULT_DIAL_TIMEOUT.as_secs())]
+    dial_timeout: u64,
+}
+
+#[derive(Args, Debug)]
+struct WrapArgs {
+    #[command(flatten)]
+    watm: WatmArgs,
+
+    /// Optional argument specifying the inherited fd of the connected socket, default to be the first one passed by systemd
+    #[arg(long, default_value_t = 3)]
+    fd: RawFd,
+}
+
+pub fn parse_and_execute() -> Result<(), anyhow::Error> {
+    // Parse command-line arguments and execute the appropriate commands
+    let cli = Cli::parse();
+
+    tracing_subscriber::fmt()
+        .with_max_level(cli.log_level)
+        .init();
+
+    if let Some(addr) = &cli.metrics_addr {
+        metrics::serve(addr)?;
+    }
+
+    let debug = cli.log_level >= Level::DEBUG;
+    // the config file written with the addresses overridden, removed when the command returns
+    let mut overridden = None;
+    let mut config = |args: WatmArgs, client_type| {
+        args.into_config(&cli.registry, client_type, debug, &mut overridden)
+    };
+
+    match cli.command {
+        Command::Dial(args) => dial(config(args.watm, WaterBinType::Dial)?, args.max_connections),
+        Command::Listen(args) => listen(
+            config(args.watm, WaterBinType::Listen)?,
+            args.max_connections,
+        ),
+        Command::Relay(args) => relay(
+            config(args.watm, WaterBinType::Relay)?,
+            args.max_connections,
+        ),
+        Command::Socks5(args) => socks5(
+            config(args.watm, WaterBinType::Dial)?,
+            args.username.zip(args.password),
+        ),
+        Command::HttpProxy(args) => http_proxy(
+            config(args.watm, WaterBinType::Dial)?,
+            Duration::from_secs(args.dial_timeout),
+        ),
+        Command::Wrap(args) => wrap(config(args.watm, WaterBinType::Wrap)?, args.fd),
+        Command::Run(args) => run(
+            config(args.watm, WaterBinType::Runner)?,
+            args.restart,
+            args.max_restarts,
+        ),
+        Command::List => list_registry(&cli.registry),
+    }
+}
+
+impl WatmArgs {
+    /// Build the WATERConfig of `client_type` from the registry or the paths given,
+    /// with the addresses given overriding the ones in the config file (written to `overridden`)
+    fn into_config(
+        self,
+        registry: &str,
+        client_type: WaterBinType,
+        debug: bool,
+        overridden: &mut Option<NamedTempFile>,
+    ) -> Result<WATERConfig, anyhow::Error> {
+        let mut conf = match &self.transport {
+            Some(transport) => {
+                let (name, version_req) = transport.split_once('@').unwrap_or((transport, "*"));
+                Registry::open(registry)?.resolve(name, version_req, client_type)?
             }
-        }
-        WaterBinType::Relay => {
-            water_client.listen().unwrap();
-            water_client.associate().unwrap();
-            water_client.cancel_with().unwrap();
-
-            let handle_water = water_client.run_worker().unwrap();
-
-            match handle_water.join().unwrap() {
-                Ok(_) => {}
-                Err(e) => {
-                    eprintln!("Running _water_worker ERROR: {}", e);
-                    return Err(anyhow::anyhow!("Failed to join _water_worker thread"));
-                }
+            None => WATERConfig {
+                filepath: self.wasm_path,
+                entry_fn: String::new(),
+                config_wasm: self.config_wasm,
+                client_type,
+                debug,
+                stdio: StdioMode::Inherit,
+                trusted_keys: Vec::new(),
+                v1_shared_buffer: false,
+                socket_options: Default::default(),
+                sha256: None,
+            },
+        };
+
+        conf.debug = debug;
+        if let Some(entry_fn) = self.entry_fn {
+            conf.entry_fn = entry_fn;
+        } else if conf.entry_fn.is_empty() {
+            conf.entry_fn = match client_type {
+                WaterBinType::Runner => MAIN.to_string(),
+                _ => WORKER_FN.to_string(),
             };
         }
-        WaterBinType::Wrap => {}
-        WaterBinType::Unknown => {}
+
+        if self.local.is_some() || self.remote.is_some() {
+            let file = override_addresses(
+                &conf.config_wasm,
+                self.local.as_deref(),
+                self.remote.as_deref(),
+            )?;
+            conf.config_wasm = file.path().to_string_lossy().into_owned();
+
+            // the servers are stopped with Ctrl-C / SIGTERM, which doesn't drop the file, so the handler removes it
+            let path = file.path().to_path_buf();
+            ctrlc::set_handler(move || {
+                let _ = std::fs::remove_file(&path);
+                std::process::exit(130);
+            })?;
+            *overridden = Some(file);
+        }
+
+        Ok(conf)
+    }
+}
+
+/// Write a copy of the config file at `path` with the local / remote address:port replaced (when given) to a temp file,
+/// returns the copy, which is removed when dropped
+fn override_addresses(
+    path: &str,
+    local: Option<&str>,
+    remote: Option<&str>,
+) -> Result<NamedTempFile, anyhow::Error> {
+    let config = std::fs::read_to_string(path).context(format!("failed to read {}", path))?;
+    let mut config: serde_json::Value =
+        serde_json::from_str(&config).context(format!("failed to parse {}", path))?;
+    let fields = config
+        .as_object_mut()
+        .context(format!("{} is not a JSON object", path))?;
+
+    for (prefix, addr) in [("local", local), ("remote", remote)] {
+        if let Some(addr) = addr {
+            let (host, port) = addr
+                .rsplit_once(':')
+                .context(format!("{} is not an address:port", addr))?;
+            let port: u16 = port.parse().context(format!("invalid port in {}", addr))?;
+
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            fields.insert(format!("{}_address", prefix), host.into());
+            fields.insert(format!("{}_port", prefix), port.into());
+        }
+    }
+
+    let mut overridden = tempfile::Builder::new()
+        .prefix("water_cli_")
+        .suffix(".json")
+        .tempfile()?;
+    overridden.write_all(&serde_json::to_vec_pretty(&config)?)?;
+    Ok(overridden)
+}
+
+/// The local / remote address:port in the config file of `conf`
+fn config_address(conf: &WATERConfig, prefix: &str) -> Result<String, anyhow::Error> {
+    let config = std::fs::read_to_string(&conf.config_wasm)
+        .context(format!("failed to read {}", conf.config_wasm))?;
+    let config: serde_json::Value = serde_json::from_str(&config)?;
+
+    let host = config[format!("{}_address", prefix)]
+        .as_str()
+        .context(format!(
+            "{}_address missing in {}",
+            prefix, conf.config_wasm
+        ))?;
+    let port = config[format!("{}_port", prefix)]
+        .as_u64()
+        .context(format!("{}_port missing in {}", prefix, conf.config_wasm))?;
+    Ok(format!("{}:{}", host, port))
+}
+
+pub fn list_registry(path: &str) -> Result<(), anyhow::Error> {
+    let registry = Registry::open(path)?;
+
+    for package in registry.packages() {
+        let manifest = &package.manifest;
+        println!(
+            "{}@{}\troles: {:?}\tentry_fn: {}\t{}",
+            manifest.name,
+            manifest.version,
+            manifest.roles,
+            manifest.entry_fn,
+            package.dir.display()
+        );
     }
 
     Ok(())
 }
 
-// set as async for later dev of async reading from the pipe
-pub async fn handle_incoming(mut water_client: WATERClient) -> Result<(), anyhow::Error> {
-    water_client.cancel_with().unwrap();
+/// Accept the connections of the local applications, and tunnel each of them thru a new dialer,
+/// at most `max_connections` at once
+pub fn dial(conf: WATERConfig, max_connections: usize) -> Result<(), anyhow::Error> {
+    let local = config_address(&conf, "local")?;
+    let watm = CompiledWatm::compile(&conf)?;
+    let listener = TcpListener::bind(&local).context(format!("failed to listen on {}", local))?;
+    info!(
+        "dialing thru {} for connections to {}",
+        conf.filepath, local
+    );
 
-    let handle_water = water_client.run_worker().unwrap();
+    let slots = ConnectionSlots::new(max_connections);
+    loop {
+        // the next connections wait in the backlog until a slot is released
+        let slot = slots.acquire();
+        let conn = match listener.accept() {
+            Ok((conn, _)) => conn,
+            Err(e) => {
+                error!("failed to accept: {}", e);
+                continue;
+            }
+        };
 
-    // taking input from terminal
+        let conf = conf.clone();
+        let watm = watm.clone();
+        std::thread::spawn(move || {
+            let peer = conn.peer_addr().ok();
+            match tunnel(conf, &watm, conn) {
+                Ok(_) => info!("connection from {:?} closed", peer),
+                Err(e) => error!("connection from {:?} failed: {:#}", peer, e),
+            }
+            drop(slot);
+        });
+    }
+}
+
+/// Tunnel `conn` thru a new dialer, an instance of `watm`, until either side closes
+fn tunnel(conf: WATERConfig, watm: &CompiledWatm, conn: TcpStream) -> Result<(), anyhow::Error> {
+    let mut water_client = WATERClient::from_compiled(conf, watm)?;
+    water_client.connect()?;
+    serve(water_client, conn)
+}
+
+/// Accept the connections with the WATM listener, and forward each of them to the remote address,
+/// at most `max_connections` at once
+pub fn listen(conf: WATERConfig, max_connections: usize) -> Result<(), anyhow::Error> {
+    let remote = config_address(&conf, "remote")?;
+
+    let mut listener = WATERClient::new(conf)?;
+    listener.listen()?;
+
+    let slots = ConnectionSlots::new(max_connections);
+    let mut incoming = listener.incoming();
     loop {
-        let mut buf = vec![0; 1024];
-        let res = water_client.read(&mut buf);
-
-        if res.is_ok() {
-            let str_buf = String::from_utf8(buf).unwrap();
-            if str_buf.trim() == "exit" {
-                water_client.cancel().unwrap();
-                break;
+        // the next connections wait in the backlog until a slot is released
+        let slot = slots.acquire();
+        let water_client = match incoming.next() {
+            Some(water_client) => water_client?,
+            None => return Ok(()),
+        };
+
+        let remote = remote.clone();
+        std::thread::spawn(move || {
+            let res = TcpStream::connect(&remote)
+                .context(format!("failed to connect to {}", remote))
+                .and_then(|conn| serve(water_client, conn));
+            match res {
+                Ok(_) => info!("connection forwarded to {} closed", remote),
+                Err(e) => error!("connection forwarded to {} failed: {:#}", remote, e),
             }
+            drop(slot);
+        });
+    }
+}
 
-            println!("Received: {}", str_buf);
-        } else {
-            println!("Error: {}", res.unwrap_err());
-            break;
-        }
+/// Limits the connections served at once
+struct ConnectionSlots {
+    max: usize,
+    active: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A slot taken from [`ConnectionSlots`], released when dropped
+struct ConnectionSlot {
+    slots: Arc<ConnectionSlots>,
+}
+
+impl ConnectionSlots {
+    fn new(max: usize) -> Arc<Self> {
+        Arc::new(ConnectionSlots {
+            max: max.max(1),
+            active: Mutex::new(0),
+            released: Condvar::new(),
+        })
     }
 
-    match handle_water.join().unwrap() {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Running _water_worker ERROR: {}", e);
-            return Err(anyhow::anyhow!("Failed to join _water_worker thread"));
+    /// Block until fewer than `max` connections are served, then take a slot
+    fn acquire(self: &Arc<Self>) -> ConnectionSlot {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        while *active >= self.max {
+            active = self
+                .released
+                .wait(active)
+                .unwrap_or_else(|e| e.into_inner());
         }
-    };
+        *active += 1;
 
-    Ok(())
+        ConnectionSlot {
+            slots: Arc::clone(self),
+        }
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        *self.slots.active.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.slots.released.notify_one();
+    }
+}
+
+/// Serve `conn` with a connected / accepted `water_client`
+fn serve(mut water_client: WATERClient, conn: TcpStream) -> Result<(), anyhow::Error> {
+    water_client.tunnel(conn)
+}
+
+/// Relay the connections accepted on the local address to the remote address, each with its own WATM instance,
+/// at most `max_connections` at once
+pub fn relay(conf: WATERConfig, max_connections: usize) -> Result<(), anyhow::Error> {
+    RelayServer::listen(conf)?
+        .with_max_connections(max_connections)
+        .serve()
+}
+
+/// Serve SOCKS5 on the local address, with each client tunneled thru a new dialer
+pub fn socks5(conf: WATERConfig, auth: Option<(String, String)>) -> Result<(), anyhow::Error> {
+    let local = config_address(&conf, "local")?;
+    let mut server = Socks5Server::bind(&local, conf)?;
+    if let Some((username, password)) = auth {
+        server = server.with_auth(username, password);
+    }
+
+    server.serve()
+}
+
+/// Serve HTTP proxy on the local address, with each request tunneled thru a new dialer
+pub fn http_proxy(conf: WATERConfig, dial_timeout: Duration) -> Result<(), anyhow::Error> {
+    let local = config_address(&conf, "local")?;
+    HttpProxyServer::bind(&local, conf)?
+        .with_dial_timeout(dial_timeout)
+        .serve()
+}
+
+/// Run the WATM over the connected socket inherited as `fd`, and forward the transformed stream to the remote address
+pub fn wrap(conf: WATERConfig, fd: RawFd) -> Result<(), anyhow::Error> {
+    let remote = config_address(&conf, "remote")?;
+
+    // the socket is passed to this process to be owned by it, as with systemd or inetd
+    let conn = unsafe { TcpStream::from_raw_fd(fd) };
+    info!(
+        "wrapping fd {} ({:?}) with {}",
+        fd,
+        conn.peer_addr(),
+        conf.filepath
+    );
+
+    let mut water_client = WATERClient::new(conf)?;
+    water_client.wrap(conn)?;
+
+    let conn = TcpStream::connect(&remote).context(format!("failed to connect to {}", remote))?;
+    serve(water_client, conn)
+}
+
+/// Run the entry_fn of the WATM on the current thread until it exits, restarting it when it traps with `restart`
+pub fn run(
+    conf: WATERConfig,
+    restart: bool,
+    max_restarts: Option<usize>,
+) -> Result<(), anyhow::Error> {
+    if !restart {
+        let mut water_client = WATERClient::new(conf)?;
+        return water_client.execute();
+    }
+
+    let mut supervisor = RunnerSupervisor::new(conf)?.on_trap(|trap| match trap.backoff {
+        Some(backoff) => error!("WATM failed, restarting in {:?}: {}", backoff, trap.error),
+        None => error!("WATM failed, giving up: {}", trap.error),
+    });
+    if let Some(max) = max_restarts {
+        supervisor = supervisor.with_max_restarts(max);
+    }
+    supervisor.run()
 }
@@ -13,7 +13,7 @@ fn listen() {
     wrapper().unwrap();
 }
 
-fn _listener_creation() -> Result<i32, std::io::Error> {
+pub fn _listener_creation() -> Result<i32, std::io::Error> {
     let global_conn = match DIALER.lock() {
         Ok(conf) => conf,
         Err(e) => {
@@ -1,5 +1,10 @@
 // =================== Imports & Modules =====================
-use std::{io::Read, os::fd::FromRawFd, sync::Mutex, vec};
+use std::{
+    io::Read,
+    os::fd::{AsRawFd, FromRawFd},
+    sync::Mutex,
+    vec,
+};
 
 use anyhow::Result;
 use bincode::{self};
@@ -23,7 +28,7 @@ lazy_static! {
 #[export_name = "_water_init"]
 pub fn _init() {
     // default to have logging enabled
-    tracing_subscriber::fmt().with_max_level(Level::INFO).init();
+    init_host_logging(Level::INFO).unwrap();
 
     info!("[WASM] running in _init");
 }
@@ -140,6 +145,57 @@ pub fn _read() -> i64 {
     }
 }
 
+#[export_name = "_water_alloc"]
+pub fn _alloc(size: i32) -> i32 {
+    alloc_shared_buffer(size)
+}
+
+#[export_name = "_water_write_buf"]
+pub fn _write_buf(ptr: i32, len: i32) -> i64 {
+    let mut global_dialer = match DIALER.lock() {
+        Ok(dialer) => dialer,
+        Err(e) => {
+            eprintln!("[WASM] > ERROR: {}", e);
+            return -1;
+        }
+    };
+
+    let buf = unsafe { shared_buffer(ptr, len) };
+    match global_dialer
+        .file_conn
+        ._write_2_outbound_from_buf(&mut DefaultEncoder, buf)
+    {
+        Ok(n) => n,
+        Err(e) => {
+            eprintln!("[WASM] > ERROR in _write_buf: {}", e);
+            -1
+        }
+    }
+}
+
+#[export_name = "_water_read_buf"]
+pub fn _read_buf(ptr: i32, cap: i32) -> i64 {
+    match DIALER.lock() {
+        Ok(mut global_dialer) => {
+            let buf = unsafe { shared_buffer(ptr, cap) };
+            match global_dialer
+                .file_conn
+                ._read_from_outbound_to_buf(&mut DefaultDecoder, buf)
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    eprintln!("[WASM] > ERROR in _read_buf: {}", e);
+                    -1
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("[WASM] > ERROR: {}", e);
+            -1
+        }
+    }
+}
+
 #[export_name = "_water_dial"]
 pub fn _dial() {
     match DIALER.lock() {
@@ -153,3 +209,37 @@ pub fn _dial() {
         }
     }
 }
+
+// Accepts one connection on the listener created by the Host as the outbound connection,
+// so the Host can run one instance per connection sharing the listener
+#[export_name = "v1_accept"]
+pub fn _accept() {
+    let fd = match async_socks5_listener::_listener_creation() {
+        Ok(fd) => fd,
+        Err(e) => {
+            eprintln!("[WASM] > ERROR in _accept: {}", e);
+            return;
+        }
+    };
+
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+    let conn = match listener.accept() {
+        Ok((conn, _)) => conn,
+        Err(e) => {
+            eprintln!("[WASM] > ERROR in _accept: {}", e);
+            return;
+        }
+    };
+
+    match DIALER.lock() {
+        Ok(mut global_dialer) => {
+            let fd = conn.as_raw_fd();
+            global_dialer
+                .file_conn
+                .set_outbound(fd, ConnStream::TcpStream(conn));
+        }
+        Err(e) => {
+            eprintln!("[WASM] > ERROR: {}", e);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! A plain V2 WATM: the data is relayed as is, with the networking done by the WATM itself with wasi-sockets.
+
+use std::cell::RefCell;
+use std::net::SocketAddr;
+
+use exports::water::watm::transport::Guest;
+use wasi::io::poll;
+use wasi::io::streams::{InputStream, OutputStream, StreamError};
+use wasi::sockets::network::{
+    ErrorCode, IpAddressFamily, IpSocketAddress, Ipv4SocketAddress, Ipv6SocketAddress,
+};
+use wasi::sockets::tcp::{ShutdownType, TcpSocket};
+use wasi::sockets::{instance_network, tcp_create_socket};
+
+wit_bindgen::generate!({
+    path: "../../../crates/water/wit",
+    world: "watm",
+    exports: {
+        "water:watm/transport": Plain,
+    },
+});
+
+/// The max size of a single blocking write in wasi-io
+const WRITE_CHUNK_SIZE: usize = 4096;
+
+const READ_BUFFER_SIZE: u64 = 4096;
+
+/// The streams are children of the socket, so they have to be dropped before it
+struct Conn {
+    input: InputStream,
+    output: OutputStream,
+    socket: TcpSocket,
+}
+
+#[derive(Default)]
+struct State {
+    listener: Option<TcpSocket>,
+    conn: Option<Conn>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+struct Plain;
+
+impl Guest for Plain {
+    fn init(_config: Vec<u8>) -> Result<(), String> {
+        // nothing to configure for plain
+        Ok(())
+    }
+
+    fn dial(remote_address: String) -> Result<(), String> {
+        let conn = connect(&remote_address)?;
+        STATE.with(|state| state.borrow_mut().conn = Some(conn));
+        Ok(())
+    }
+
+    fn accept(local_address: String) -> Result<(), String> {
+        let listener = listen(&local_address)?;
+        let conn = accept(&listener)?;
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.conn = Some(conn);
+            state.listener = Some(listener);
+        });
+        Ok(())
+    }
+
+    fn associate(local_address: String, remote_address: String) -> Result<(), String> {
+        let listener = listen(&local_address)?;
+        let source = accept(&listener)?;
+        let remote = connect(&remote_address)?;
+
+        relay(&source, &remote)
+    }
+
+    fn write(data: Vec<u8>) -> Result<(), String> {
+        STATE.with(|state| match &state.borrow().conn {
+            Some(conn) => write_all(&conn.output, &data),
+            None => Err("not connected".into()),
+        })
+    }
+
+    fn read(len: u32) -> Result<Vec<u8>, String> {
+        STATE.with(|state| match &state.borrow().conn {
+            Some(conn) => match conn.input.blocking_read(len as u64) {
+                Ok(data) => Ok(data),
+                Err(StreamError::Closed) => Ok(vec![]),
+                Err(StreamError::LastOperationFailed(e)) => Err(e.to_debug_string()),
+            },
+            None => Err("not connected".into()),
+        })
+    }
+
+    fn close() {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            if let Some(conn) = state.conn.take() {
+                let _ = conn.socket.shutdown(ShutdownType::Both);
+            }
+            state.listener = None;
+        });
+    }
+}
+
+fn parse_address(address: &str) -> Result<(IpAddressFamily, IpSocketAddress), String> {
+    let address: SocketAddr = address
+        .parse()
+        .map_err(|e| format!("invalid address {}: {}", address, e))?;
+
+    Ok(match address {
+        SocketAddr::V4(addr) => {
+            let [a, b, c, d] = addr.ip().octets();
+            (
+                IpAddressFamily::Ipv4,
+                IpSocketAddress::Ipv4(Ipv4SocketAddress {
+                    port: addr.port(),
+                    address: (a, b, c, d),
+                }),
+            )
+        }
+        SocketAddr::V6(addr) => {
+            let [a, b, c, d, e, f, g, h] = addr.ip().segments();
+            (
+                IpAddressFamily::Ipv6,
+                IpSocketAddress::Ipv6(Ipv6SocketAddress {
+                    port: addr.port(),
+                    flow_info: addr.flowinfo(),
+                    address: (a, b, c, d, e, f, g, h),
+                    scope_id: addr.scope_id(),
+                }),
+            )
+        }
+    })
+}
+
+/// Wait for the async socket operation finished by `finish`
+fn wait<T>(
+    socket: &TcpSocket,
+    mut finish: impl FnMut() -> Result<T, ErrorCode>,
+) -> Result<T, String> {
+    let pollable = socket.subscribe();
+    loop {
+        match finish() {
+            Ok(t) => return Ok(t),
+            Err(ErrorCode::WouldBlock) => pollable.block(),
+            Err(e) => return Err(format!("{:?}", e)),
+        }
+    }
+}
+
+fn connect(address: &str) -> Result<Conn, String> {
+    let (family, address) = parse_address(address)?;
+    let network = instance_network::instance_network();
+
+    let socket = tcp_create_socket::create_tcp_socket(family).map_err(|e| format!("{:?}", e))?;
+    socket
+        .start_connect(&network, address)
+        .map_err(|e| format!("{:?}", e))?;
+    let (input, output) = wait(&socket, || socket.finish_connect())?;
+
+    Ok(Conn {
+        input,
+        output,
+        socket,
+    })
+}
+
+fn listen(address: &str) -> Result<TcpSocket, String> {
+    let (family, address) = parse_address(address)?;
+    let network = instance_network::instance_network();
+
+    let socket = tcp_create_socket::create_tcp_socket(family).map_err(|e| format!("{:?}", e))?;
+    socket
+        .start_bind(&network, address)
+        .map_err(|e| format!("{:?}", e))?;
+    wait(&socket, || socket.finish_bind())?;
+    socket.start_listen().map_err(|e| format!("{:?}", e))?;
+    wait(&socket, || socket.finish_listen())?;
+
+    Ok(socket)
+}
+
+fn accept(listener: &TcpSocket) -> Result<Conn, String> {
+    let (socket, input, output) = wait(listener, || listener.accept())?;
+    Ok(Conn {
+        input,
+        output,
+        socket,
+    })
+}
+
+fn write_all(output: &OutputStream, data: &[u8]) -> Result<(), String> {
+    for chunk in data.chunks(WRITE_CHUNK_SIZE) {
+        match output.blocking_write_and_flush(chunk) {
+            Ok(_) => {}
+            Err(StreamError::Closed) => return Err("connection closed".into()),
+            Err(StreamError::LastOperationFailed(e)) => return Err(e.to_debug_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Copy between the 2 connections until either of them is closed
+fn relay(a: &Conn, b: &Conn) -> Result<(), String> {
+    let pollables = [a.input.subscribe(), b.input.subscribe()];
+
+    loop {
+        for ready in poll::poll(&[&pollables[0], &pollables[1]]) {
+            let (src, dst) = match ready {
+                0 => (a, b),
+                _ => (b, a),
+            };
+
+            match src.input.read(READ_BUFFER_SIZE) {
+                Ok(data) => write_all(&dst.output, &data)?,
+                Err(StreamError::Closed) => return Ok(()),
+                Err(StreamError::LastOperationFailed(e)) => return Err(e.to_debug_string()),
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+//! A v0 WATM using wasi-threads, where the two directions of the connection are transformed in parallel:
+//! the encoder thread flips the bits of what the caller sends before writing it to the network,
+//! the decoder thread does the same for what is received from the network before writing it to the caller.
+//!
+//! Reading is done by the worker itself, polling the caller, the network and the cancel connections at once.
+
+use lazy_static::lazy_static;
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    mem::ManuallyDrop,
+    net::TcpStream,
+    os::fd::{AsRawFd, FromRawFd},
+    sync::{
+        mpsc::{self, Sender},
+        Mutex,
+    },
+    thread::JoinHandle,
+};
+
+const READ_BUFFER_SIZE: usize = 1024;
+
+// WASI Imports
+extern "C" {
+    /// obtain a connection (specified by returned fd) accepted by the host
+    fn host_accept() -> i32;
+    /// obtain a connection (specified by returned fd) dialed by the host
+    fn host_dial() -> i32;
+    /// call when exiting
+    fn host_defer();
+}
+
+// wasi-libc
+extern "C" {
+    /// set up the pthread of the main thread, done by `_initialize` of a reactor, which cdylib doesn't export
+    fn __wasi_init_tp();
+}
+
+lazy_static! {
+    /// (caller connection, network connection)
+    static ref CONNS: Mutex<Option<(TcpStream, TcpStream)>> = Mutex::new(None);
+    static ref CANCEL: Mutex<Option<TcpStream>> = Mutex::new(None);
+}
+
+#[export_name = "_water_v0"]
+pub static VERSION: i32 = 0;
+
+// version-independent API
+#[export_name = "_water_init"]
+pub fn _init() -> i32 {
+    // has to be done before spawning any thread
+    unsafe { __wasi_init_tp() };
+    0
+}
+
+// V0 API
+#[export_name = "_water_dial"]
+pub fn _dial(caller_conn_fd: i32) -> i32 {
+    println!("[WATM threaded] dialing...");
+    setup(caller_conn_fd, unsafe { host_dial() })
+}
+
+// V0 API
+#[export_name = "_water_accept"]
+pub fn _accept(caller_conn_fd: i32) -> i32 {
+    println!("[WATM threaded] accepting...");
+    setup(caller_conn_fd, unsafe { host_accept() })
+}
+
+// V0+ API
+#[export_name = "_water_cancel_with"]
+pub fn _cancel_with(fd: i32) -> i32 {
+    if fd < 0 {
+        return -1;
+    }
+
+    *CANCEL.lock().unwrap() = Some(unsafe { TcpStream::from_raw_fd(fd) });
+    0
+}
+
+/// WASM Entry point here
+#[export_name = "_water_worker"]
+pub fn _worker() -> i32 {
+    println!("[WATM threaded] worker: start");
+
+    let (caller, network) = match CONNS.lock().unwrap().take() {
+        Some(conns) => conns,
+        None => {
+            println!("[WATM threaded] worker: not connected");
+            return -1;
+        }
+    };
+    let cancel = CANCEL.lock().unwrap().take();
+
+    let result = match relay(caller, network, cancel) {
+        Ok(_) => 0,
+        Err(e) => {
+            println!("[WATM threaded] worker: {}", e);
+            -1
+        }
+    };
+
+    unsafe { host_defer() };
+    result
+}
+
+fn setup(caller_conn_fd: i32, network_fd: i32) -> i32 {
+    if caller_conn_fd < 0 || network_fd < 0 {
+        return -1;
+    }
+
+    let caller = unsafe { TcpStream::from_raw_fd(caller_conn_fd) };
+    let network = unsafe { TcpStream::from_raw_fd(network_fd) };
+    *CONNS.lock().unwrap() = Some((caller, network));
+
+    network_fd
+}
+
+/// Read from the caller / network and hand the data over to the encoder / decoder thread,
+/// until either side is closed or the host cancels.
+fn relay(caller: TcpStream, network: TcpStream, cancel: Option<TcpStream>) -> io::Result<()> {
+    // the readiness reported by the host is only a hint: once any fd is ready all of them are reported,
+    // so every read is non-blocking and WouldBlock means there was nothing to read.
+    caller.set_nonblocking(true)?;
+    network.set_nonblocking(true)?;
+    if let Some(cancel) = &cancel {
+        cancel.set_nonblocking(true)?;
+    }
+
+    let (encoder_tx, encoder) = spawn_transformer(&network);
+    let (decoder_tx, decoder) = spawn_transformer(&caller);
+
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+    let result = loop {
+        let mut fds = vec![pollfd(&caller), pollfd(&network)];
+        if let Some(cancel) = &cancel {
+            fds.push(pollfd(cancel));
+        }
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) } < 0 {
+            break Err(io::Error::last_os_error());
+        }
+
+        match try_read(&caller, &mut buf) {
+            Ok(Some(0)) => break Ok(()),
+            Ok(Some(n)) => {
+                if encoder_tx.send(buf[..n].to_vec()).is_err() {
+                    break Err(io::Error::new(ErrorKind::BrokenPipe, "encoder stopped"));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+
+        match try_read(&network, &mut buf) {
+            Ok(Some(0)) => break Ok(()),
+            Ok(Some(n)) => {
+                if decoder_tx.send(buf[..n].to_vec()).is_err() {
+                    break Err(io::Error::new(ErrorKind::BrokenPipe, "decoder stopped"));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+
+        // anything written to cancel (or cancel being closed) stops the worker
+        if let Some(cancel) = &cancel {
+            if !matches!(try_read(cancel, &mut buf), Ok(None)) {
+                println!("[WATM threaded] worker: cancelled");
+                break Ok(());
+            }
+        }
+    };
+
+    // let the transformers drain what is left before the connections are closed
+    drop(encoder_tx);
+    drop(decoder_tx);
+    for (name, handle) in [("encoder", encoder), ("decoder", decoder)] {
+        match handle.join() {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => println!("[WATM threaded] {} failed: {}", name, e),
+            Err(_) => println!("[WATM threaded] {} panicked", name),
+        }
+    }
+
+    result
+}
+
+/// Spawn a thread flipping all the bits of the data it receives and writing it to `dst`
+fn spawn_transformer(dst: &TcpStream) -> (Sender<Vec<u8>>, JoinHandle<io::Result<()>>) {
+    // WASI can't dup the fd, so the thread borrows it, the owner outlives the thread by joining it
+    let dst = ManuallyDrop::new(unsafe { TcpStream::from_raw_fd(dst.as_raw_fd()) });
+
+    let (tx, rx) = mpsc::channel::<Vec<u8>>();
+    let handle = std::thread::spawn(move || {
+        for mut data in rx {
+            data.iter_mut().for_each(|b| *b = !*b);
+            write_all(&dst, &data)?;
+        }
+        Ok(())
+    });
+    (tx, handle)
+}
+
+fn pollfd(stream: &TcpStream) -> libc::pollfd {
+    libc::pollfd {
+        fd: stream.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    }
+}
+
+/// Ok(None) when there is nothing to read for now
+fn try_read(mut src: &TcpStream, buf: &mut [u8]) -> io::Result<Option<usize>> {
+    match src.read(buf) {
+        Ok(n) => Ok(Some(n)),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_all(mut dst: &TcpStream, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        match dst.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                let mut fds = [libc::pollfd {
+                    fd: dst.as_raw_fd(),
+                    events: libc::POLLOUT,
+                    revents: 0,
+                }];
+                if unsafe { libc::poll(fds.as_mut_ptr(), 1, -1) } < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+//! Benchmarking the in-process pipes (`PipeStream`) between the Host and the WATMs against the UnixStream pairs they replaced,
+//! with an echoing thread standing in for the WATM.
+//!
+//! cargo test --release --bench benchmarking_pipe -- --nocapture
+
+use water::runtime::pipe::PipeStream;
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TOTAL_BYTES: usize = 256 * 1024 * 1024;
+const CHUNK_SIZE: usize = 16 * 1024;
+const ROUND_TRIPS: usize = 20_000;
+
+/// The (caller_reader, caller_writer) of the Host and the (water_reader, water_writer) of the echoing thread
+type Ends<R, W> = ((R, W), (R, W));
+
+fn unix_stream_ends() -> Result<Ends<UnixStream, UnixStream>, anyhow::Error> {
+    let (caller_io, water_io) = UnixStream::pair()?;
+    Ok((
+        (caller_io.try_clone()?, caller_io),
+        (water_io.try_clone()?, water_io),
+    ))
+}
+
+fn pipe_ends() -> Ends<PipeStream, PipeStream> {
+    let (caller_reader, water_writer) = PipeStream::simplex();
+    let (water_reader, caller_writer) = PipeStream::simplex();
+    ((caller_reader, caller_writer), (water_reader, water_writer))
+}
+
+/// Echo everything read back until the reader is closed
+fn spawn_echo<R, W>(mut reader: R, mut writer: W) -> thread::JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    if writer.write_all(&buf[..n]).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Push TOTAL_BYTES through the echo while reading them back, returning the elapsed time
+fn throughput<R, W>(ends: Ends<R, W>) -> Duration
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let ((mut caller_reader, mut caller_writer), (water_reader, water_writer)) = ends;
+    let echo = spawn_echo(water_reader, water_writer);
+
+    let start = Instant::now();
+    let writer = thread::spawn(move || {
+        let chunk = vec![0xAAu8; CHUNK_SIZE];
+        for _ in 0..TOTAL_BYTES / CHUNK_SIZE {
+            caller_writer.write_all(&chunk).unwrap();
+        }
+        caller_writer
+    });
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut read = 0;
+    while read < TOTAL_BYTES {
+        read += caller_reader.read(&mut buf).unwrap();
+    }
+    let elapsed = start.elapsed();
+
+    // closing the caller ends stops the echo
+    drop(writer.join().unwrap());
+    drop(caller_reader);
+    echo.join().unwrap();
+    elapsed
+}
+
+/// Send 1 byte through the echo and wait for it to come back ROUND_TRIPS times, returning the elapsed time
+fn latency<R, W>(ends: Ends<R, W>) -> Duration
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let ((mut caller_reader, mut caller_writer), (water_reader, water_writer)) = ends;
+    let echo = spawn_echo(water_reader, water_writer);
+
+    let mut buf = [0u8; 1];
+    let start = Instant::now();
+    for _ in 0..ROUND_TRIPS {
+        caller_writer.write_all(&buf).unwrap();
+        caller_reader.read_exact(&mut buf).unwrap();
+    }
+    let elapsed = start.elapsed();
+
+    drop(caller_writer);
+    drop(caller_reader);
+    echo.join().unwrap();
+    elapsed
+}
+
+fn report(name: &str, throughput: Duration, latency: Duration) {
+    println!(
+        "{:>10}: {:>8.1} MiB/s, {:>6.2} us per round trip",
+        name,
+        (TOTAL_BYTES / (1024 * 1024)) as f64 / throughput.as_secs_f64(),
+        latency.as_secs_f64() * 1e6 / ROUND_TRIPS as f64
+    );
+}
+
+#[test]
+fn benchmarking_pipe_vs_unix_stream() -> Result<(), anyhow::Error> {
+    let unix_throughput = throughput(unix_stream_ends()?);
+    let unix_latency = latency(unix_stream_ends()?);
+
+    let pipe_throughput = throughput(pipe_ends());
+    let pipe_latency = latency(pipe_ends());
+
+    report("UnixStream", unix_throughput, unix_latency);
+    report("PipeStream", pipe_throughput, pipe_latency);
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+//! This is the test file for the async mode, driving the plain.wasm (v0_plus) WATM instances with Tokio,
+//! where the connections are handled by a runtime with fewer worker threads than connections.
+
+mod common;
+
+use water::{runtime::asynchronous::client::AsyncWATERClient, *};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const CONNECTIONS: usize = 4;
+
+/// Testing Dialers running concurrently on 2 worker threads
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_dialers() -> Result<(), Box<dyn std::error::Error>> {
+    // echo server
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+
+    let (config_path, _dir) = common::config_file(listener.local_addr()?.port(), 0)?;
+
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 1024];
+                loop {
+                    match socket.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => socket.write_all(&buf[..n]).await.unwrap(),
+                    }
+                }
+            });
+        }
+    });
+
+    let mut clients = Vec::new();
+    for i in 0..CONNECTIONS {
+        let conf = config::WATERConfig::init(
+            String::from("./test_wasm/plain.wasm"),
+            String::from("_water_worker"),
+            config_path.clone(),
+            config::WaterBinType::Dial,
+            false,
+        )?;
+
+        clients.push(tokio::spawn(async move {
+            let mut water_client = AsyncWATERClient::new(conf).await?;
+            water_client.connect().await?;
+            water_client.cancel_with().await?;
+
+            let handle_water = water_client.run_worker()?;
+
+            let test_message = format!("hello from {}", i);
+            water_client.write(test_message.as_bytes()).await?;
+
+            let mut buf = vec![0; 32];
+            let read_bytes = water_client.read(&mut buf).await?;
+            assert_eq!(&buf[..read_bytes as usize], test_message.as_bytes());
+
+            water_client.cancel().await?;
+            handle_water.await??;
+
+            assert_eq!(
+                water_client.stats().caller_bytes_written,
+                test_message.len() as u64
+            );
+            Ok::<(), anyhow::Error>(())
+        }));
+    }
+
+    for client in clients {
+        client.await??;
+    }
+
+    Ok(())
+}
+
+/// Testing the Listener mode
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_async_listener() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, _dir) = common::config_file(10490, 0)?;
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Listen,
+        false,
+    )?;
+
+    let mut water_client = AsyncWATERClient::new(conf).await?;
+    water_client.listen().await?;
+
+    // the port picked by the OS
+    let addr = water_client.local_addr()?;
+
+    let test_message = b"hello";
+    let handle = tokio::spawn(async move {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(test_message).await.unwrap();
+
+        let mut buf = [0; 32];
+        let read_bytes = stream.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..read_bytes], b"world");
+    });
+
+    water_client.accept().await?;
+    water_client.cancel_with().await?;
+
+    let handle_water = water_client.run_worker()?;
+
+    let mut buf = vec![0; 32];
+    let read_bytes = water_client.read(&mut buf).await?;
+    assert_eq!(&buf[..read_bytes as usize], test_message);
+
+    water_client.write(b"world").await?;
+    handle.await?;
+
+    water_client.cancel().await?;
+    handle_water.await??;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_connect_to_invalid() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, _dir) = common::config_file(common::closed_port()?, 0)?;
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        false,
+    )?;
+
+    let mut water_client = AsyncWATERClient::new(conf).await?;
+    let err = water_client.connect_to("127.0.0.1").await.unwrap_err();
+    assert!(err.to_string().contains("Invalid destination"), "{}", err);
+
+    Ok(())
+}
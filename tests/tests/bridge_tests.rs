@@ -0,0 +1,126 @@
+//! This is the test file for bridging a TCP connection of the Host with a v0 (plain.wasm) or v1 (echo_client.wasm) WATM,
+//! which is how the cli tool and the proxy front-ends serve the connections of local applications.
+
+mod common;
+
+use water::*;
+
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+};
+
+#[test]
+fn test_bridge_dialer() -> Result<(), Box<dyn std::error::Error>> {
+    // more than the pipe holds, the bridge has to keep relaying in both directions
+    let test_message: Vec<u8> = (0..512 * 1024).map(|i| (i % 251) as u8).collect();
+
+    // the remote echoing everything until EOF
+    let remote = TcpListener::bind(("127.0.0.1", 0))?;
+    let port = remote.local_addr()?.port();
+    let handle_remote = common::echo_until_eof(remote);
+
+    let (config_path, _dir) = common::config_file(port, 8088)?;
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect().unwrap();
+    water_client.cancel_with().unwrap();
+    let handle_water = water_client.run_worker().unwrap();
+
+    // the local application
+    let local = TcpListener::bind(("127.0.0.1", 0))?;
+    let mut app = TcpStream::connect(local.local_addr()?)?;
+    let (conn, _) = local.accept()?;
+
+    let handle_bridge = std::thread::spawn(move || {
+        water_client.bridge(conn).unwrap();
+        water_client
+    });
+
+    let expected = test_message.clone();
+    let mut app_reader = app.try_clone()?;
+    let handle_app = std::thread::spawn(move || {
+        let mut received = vec![0; expected.len()];
+        app_reader.read_exact(&mut received).unwrap();
+        assert_eq!(received, expected);
+    });
+    app.write_all(&test_message)?;
+    handle_app.join().unwrap();
+
+    // closing the application ends the worker, and the bridge with it
+    drop(app);
+    let water_client = handle_bridge.join().unwrap();
+    handle_water.join().unwrap()?;
+    handle_remote.join().unwrap();
+
+    let stats = water_client.stats();
+    assert_eq!(stats.caller_bytes_written, test_message.len() as u64);
+    assert_eq!(stats.caller_bytes_read, test_message.len() as u64);
+
+    Ok(())
+}
+
+#[test]
+fn test_bridge_v1_dialer() -> Result<(), Box<dyn std::error::Error>> {
+    let test_message: Vec<u8> = (0..512 * 1024).map(|i| (i % 251) as u8).collect();
+
+    // the remote echoing everything until EOF
+    let remote = TcpListener::bind(("127.0.0.1", 0))?;
+    let port = remote.local_addr()?.port();
+    let handle_remote = common::echo_until_eof(remote);
+
+    let (config_path, _dir) = common::config_file(port, 8088)?;
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/echo_client.wasm"),
+        String::from("_water_init"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect().unwrap();
+
+    // the local application
+    let local = TcpListener::bind(("127.0.0.1", 0))?;
+    let mut app = TcpStream::connect(local.local_addr()?)?;
+    let (conn, _) = local.accept()?;
+
+    // no worker for v1, the bridge calls into the WATM whenever either side is readable
+    let handle_bridge = std::thread::spawn(move || {
+        water_client.bridge(conn).unwrap();
+        water_client
+    });
+
+    let mut app_reader = app.try_clone()?;
+    let handle_app = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        app_reader.read_to_end(&mut received).unwrap();
+        received
+    });
+    app.write_all(&test_message)?;
+
+    // the half-close of the application is passed to the remote, which closes once it echoed everything:
+    // the application still receives all of it before its EOF
+    app.shutdown(Shutdown::Write)?;
+    assert_eq!(handle_app.join().unwrap(), test_message);
+    let water_client = handle_bridge.join().unwrap();
+    handle_remote.join().unwrap();
+
+    let stats = water_client.stats();
+    assert_eq!(stats.caller_bytes_written, test_message.len() as u64);
+    assert_eq!(stats.caller_bytes_read, test_message.len() as u64);
+
+    Ok(())
+}
@@ -0,0 +1,312 @@
+//! This is the test file for canceling the v1 instances (echo_client.wasm and a v1 WATM written in WAT) and the Runners
+//! with `cancel_with` / `cancel`, as for the v0 ones.
+
+mod common;
+
+use water::*;
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::Duration,
+};
+
+use tempfile::TempDir;
+
+/// A v1 WATM with the functions of a Dialer doing nothing, and entry functions sleeping in poll_oneoff or trapping
+const SLEEPER_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (global (export "_water_v1") i32 (i32.const 1))
+  (func (export "_water_init"))
+  (func (export "_water_set_inbound") (param i32))
+  (func (export "_water_read") (result i64) (i64.const 0))
+  (func (export "_water_write") (param i64) (result i64) (local.get 0))
+
+  ;; poll_oneoff with a single clock subscription of an hour
+  (func (export "sleep")
+    (i32.store (i32.const 16) (i32.const 1))
+    (i64.store (i32.const 24) (i64.const 3600000000000))
+    (drop (call $poll_oneoff (i32.const 0) (i32.const 64) (i32.const 1) (i32.const 128))))
+
+  ;; trapping without calling into the scheduler
+  (func (export "fail")
+    unreachable)
+)
+"#;
+
+/// The StreamConfig of 127.0.0.1:`port` given to `connect_tcp` / `create_listen`, serialized with bincode
+fn stream_config(port: u16) -> String {
+    let port = port
+        .to_le_bytes()
+        .iter()
+        .chain(&[0, 0])
+        .map(|b| format!("\\{:02x}", b))
+        .collect::<String>();
+    format!(
+        "\\09\\00\\00\\00\\00\\00\\00\\00127.0.0.1{}\\04\\00\\00\\00\\00\\00\\00\\00conn",
+        port
+    )
+}
+
+/// A v1 Runner getting 3 connections with `connect`, closing each of them, then sleeping as `SLEEPER_WAT` does
+fn closer_wat(imports: &str, connect: &str, port: u16) -> String {
+    format!(
+        r#"
+(module
+  {}
+  (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "{}")
+  (global (export "_water_v1") i32 (i32.const 1))
+  (func (export "_water_init"))
+
+  (func (export "close") (local $i i32) (local $listener i32)
+    (local.set $listener (i32.const -1))
+    (loop $next
+      (drop (call $fd_close {}))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br_if $next (i32.lt_u (local.get $i) (i32.const 3))))
+    (i32.store (i32.const 16) (i32.const 1))
+    (i64.store (i32.const 24) (i64.const 3600000000000))
+    (drop (call $poll_oneoff (i32.const 0) (i32.const 64) (i32.const 1) (i32.const 128))))
+)
+"#,
+        imports,
+        stream_config(port),
+        connect
+    )
+}
+
+/// Run the Runner of `closer_wat`, every connection it closed must be closed for the peer while it keeps running
+fn assert_closed_for_peer(
+    wat: &str,
+    mut peer: impl FnMut() -> std::io::Result<TcpStream>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (conf, _dir) = wat_config(wat, "close", config::WaterBinType::Runner)?;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    let runner = water_client.runner_handle().unwrap();
+    let handle_water = std::thread::spawn(move || water_client.execute());
+
+    let mut buf = vec![0; 32];
+    for _ in 0..3 {
+        let mut socket = peer()?;
+        socket.set_read_timeout(Some(Duration::from_secs(10)))?;
+        assert_eq!(socket.read(&mut buf)?, 0);
+    }
+    assert!(!handle_water.is_finished());
+
+    runner.stop();
+    handle_water.join().unwrap()?;
+
+    Ok(())
+}
+
+fn wat_config(
+    wat: &str,
+    entry_fn: &str,
+    client_type: config::WaterBinType,
+) -> Result<(config::WATERConfig, TempDir), Box<dyn std::error::Error>> {
+    let (config_path, dir) = common::config_file(8088, 0)?;
+    let file_path = dir.path().join("watm.wat");
+    let mut file = File::create(&file_path)?;
+    file.write_all(wat.as_bytes())?;
+
+    let conf = config::WATERConfig::init(
+        String::from(file_path.to_string_lossy()),
+        String::from(entry_fn),
+        config_path,
+        client_type,
+        true,
+    )
+    .unwrap();
+
+    Ok((conf, dir))
+}
+
+fn sleeper_config(
+    client_type: config::WaterBinType,
+) -> Result<(config::WATERConfig, TempDir), Box<dyn std::error::Error>> {
+    wat_config(SLEEPER_WAT, "sleep", client_type)
+}
+
+/// Echo the connection accepted, the receiver gets a message once it is closed
+fn echo_server() -> Result<(u16, mpsc::Receiver<()>), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let port = listener.local_addr()?.port();
+    let (closed, receiver) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let (socket, _) = listener.accept().unwrap();
+        common::echo(socket);
+        closed.send(()).unwrap();
+    });
+
+    Ok((port, receiver))
+}
+
+#[test]
+fn test_cancel_v1_dialer() -> Result<(), Box<dyn std::error::Error>> {
+    let (port, closed) = echo_server()?;
+    let (config_path, _dir) = common::config_file(port, 0)?;
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/echo_client.wasm"),
+        String::from("_water_init"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect().unwrap();
+    water_client.cancel_with().unwrap();
+
+    water_client.write(b"hello").unwrap();
+    let mut buf = vec![0; 32];
+    let n = water_client.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n as usize], b"hello");
+
+    // the connection to the remote is closed, nothing is read anymore
+    water_client.cancel().unwrap();
+    closed.recv_timeout(Duration::from_secs(10))?;
+    assert!(!matches!(water_client.read(&mut buf), Ok(n) if n > 0));
+    assert!(water_client.stats().ended_at.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_cancel_v1_worker() -> Result<(), Box<dyn std::error::Error>> {
+    let (conf, _dir) = sleeper_config(config::WaterBinType::Dial)?;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.cancel_with().unwrap();
+    let handle_water = water_client.run_worker().unwrap();
+
+    std::thread::sleep(Duration::from_millis(500));
+    assert!(!handle_water.is_finished());
+
+    // the worker waiting in poll_oneoff exits without an error, as a v0 one canceled
+    water_client.cancel().unwrap();
+    handle_water.join().unwrap()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cancel_v1_worker_trap() -> Result<(), Box<dyn std::error::Error>> {
+    let (conf, _dir) = wat_config(SLEEPER_WAT, "fail", config::WaterBinType::Dial)?;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.cancel_with().unwrap();
+    water_client.cancel().unwrap();
+
+    // the WATM trapping on its own is not the way a canceled one exits, even once canceled
+    let err = water_client
+        .run_worker()
+        .unwrap()
+        .join()
+        .unwrap()
+        .unwrap_err();
+    let trap = err
+        .downcast_ref::<runtime::trap::GuestTrap>()
+        .expect("a GuestTrap");
+    assert_eq!(trap.code, Some(runtime::trap::Trap::UnreachableCodeReached));
+
+    Ok(())
+}
+
+#[test]
+fn test_cancel_v1_accepted() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, _dir) = common::config_file(8088, 0)?;
+    let mut conf = config::WATERConfig::init(
+        String::from("./test_wasm/echo_client.wasm"),
+        String::from("v1_accept"),
+        config_path,
+        config::WaterBinType::Listen,
+        true,
+    )
+    .unwrap();
+    conf.v1_shared_buffer = true;
+
+    let mut listener = runtime::client::WATERClient::new(conf).unwrap();
+    listener.listen().unwrap();
+
+    let mut conn = TcpStream::connect(listener.local_addr().unwrap())?;
+    let mut accepted = listener.accept_next().unwrap();
+
+    conn.write_all(b"hello")?;
+    let mut buf = vec![0; 32];
+    let n = accepted.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n as usize], b"hello");
+
+    // the connection accepted is closed, the listener keeps listening
+    accepted.cancel_with().unwrap();
+    accepted.cancel().unwrap();
+    conn.set_read_timeout(Some(Duration::from_secs(10)))?;
+    assert_eq!(conn.read(&mut buf)?, 0);
+
+    assert!(listener.local_addr().is_ok());
+    TcpStream::connect(listener.local_addr().unwrap())?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cancel_runner() -> Result<(), Box<dyn std::error::Error>> {
+    let (conf, _dir) = sleeper_config(config::WaterBinType::Runner)?;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.cancel_with().unwrap();
+    water_client.cancel().unwrap();
+
+    // the entry function sleeping for an hour returns right away
+    water_client.execute()?;
+    assert!(water_client.runner_handle().unwrap().is_stopped());
+
+    Ok(())
+}
+
+#[test]
+fn test_runner_closed_connections() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let wat = closer_wat(
+        r#"(import "env" "connect_tcp" (func $connect (param i32 i32) (result i32)))"#,
+        "(call $connect (i32.const 256) (i32.const 33))",
+        listener.local_addr()?.port(),
+    );
+
+    assert_closed_for_peer(&wat, || Ok(listener.accept()?.0))
+}
+
+#[test]
+fn test_runner_closed_accepted() -> Result<(), Box<dyn std::error::Error>> {
+    let port = common::closed_port()?;
+    let wat = closer_wat(
+        r#"(import "env" "create_listen" (func $listen (param i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "sock_accept" (func $sock_accept (param i32 i32 i32) (result i32)))"#,
+        r#"(if (i32.lt_s (local.get $listener) (i32.const 0))
+        (then (local.set $listener (call $listen (i32.const 256) (i32.const 33)))))
+      (drop (call $sock_accept (local.get $listener) (i32.const 0) (i32.const 512)))
+      (i32.load (i32.const 512))"#,
+        port,
+    );
+
+    assert_closed_for_peer(&wat, || {
+        // the WATM may not be listening yet
+        for _ in 0..50 {
+            if let Ok(conn) = TcpStream::connect(("127.0.0.1", port)) {
+                return Ok(conn);
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        TcpStream::connect(("127.0.0.1", port))
+    })
+}
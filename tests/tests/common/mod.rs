@@ -0,0 +1,99 @@
+//! Fixtures shared by the test files: the config file given to the WATMs and the echo servers they dial.
+//!
+//! Included with `mod common;` by the test files, each of them only uses some of the helpers.
+#![allow(dead_code)]
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread::JoinHandle,
+};
+
+use tempfile::{tempdir, TempDir};
+
+/// Write the config of a WATM dialing 127.0.0.1:`remote_port` and listening on 127.0.0.1:`local_port`,
+/// returns its path and the directory holding it, removed when dropped
+pub fn config_file(
+    remote_port: u16,
+    local_port: u16,
+) -> Result<(String, TempDir), Box<dyn std::error::Error>> {
+    let cfg_str = format!(
+        r#"
+	{{
+		"remote_address": "127.0.0.1",
+		"remote_port": {},
+		"local_address": "127.0.0.1",
+		"local_port": {}
+	}}
+	"#,
+        remote_port, local_port
+    );
+    let dir = tempdir()?;
+    let file_path = dir.path().join("temp-config.txt");
+    let mut file = File::create(&file_path)?;
+    writeln!(file, "{}", cfg_str)?;
+
+    Ok((String::from(file_path.to_string_lossy()), dir))
+}
+
+/// A port nothing is listening on, for the remotes that must not be dialed (or be refused)
+pub fn closed_port() -> Result<u16, Box<dyn std::error::Error>> {
+    Ok(TcpListener::bind(("127.0.0.1", 0))?.local_addr()?.port())
+}
+
+/// Echo everything read from `socket` until its EOF
+pub fn echo(mut socket: TcpStream) {
+    let mut buf = vec![0; 4096];
+    loop {
+        match socket.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => socket.write_all(&buf[..n]).unwrap(),
+        }
+    }
+}
+
+/// Start an echo server serving every connection on its own thread, returns its port
+pub fn echo_server() -> Result<u16, Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let port = listener.local_addr()?.port();
+
+    std::thread::spawn(move || {
+        for socket in listener.incoming() {
+            let socket = socket.unwrap();
+            std::thread::spawn(move || echo(socket));
+        }
+    });
+
+    Ok(port)
+}
+
+/// Accept a single connection on `listener` and echo it until its EOF
+pub fn echo_until_eof(listener: TcpListener) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let (socket, _) = listener.accept().unwrap();
+        echo(socket);
+    })
+}
+
+/// Accept a single connection on `listener` and echo a single read back
+pub fn echo_once(listener: TcpListener) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let n = socket.read(&mut buf).unwrap();
+        socket.write_all(&buf[..n]).unwrap();
+    })
+}
+
+/// Write `message` to `conn` and check it is echoed back
+pub fn assert_echoed(
+    conn: &mut TcpStream,
+    message: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    conn.write_all(message)?;
+    let mut received = vec![0; message.len()];
+    conn.read_exact(&mut received)?;
+    assert_eq!(received, message);
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! This is the test file for picking the destination of each dialer with `connect_to`,
+//! instead of the remote in the config, for both v0 (plain.wasm) and v1 (echo_client.wasm).
+
+mod common;
+
+use water::*;
+
+use std::net::TcpListener;
+
+/// Send a message with `wasm` to an echo server picked by `connect_to`, and check it is echoed back
+fn echo_through(wasm: &str, entry_fn: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let test_message = b"hello";
+
+    // the destination, echoing one message
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let destination = listener.local_addr()?.to_string();
+    let handle = common::echo_once(listener);
+
+    // the remote in the config is not listening
+    let (config_path, _dir) = common::config_file(common::closed_port()?, 8088)?;
+
+    let conf = config::WATERConfig::init(
+        String::from(wasm),
+        String::from(entry_fn),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect_to(&destination).unwrap();
+
+    let handle_water = match entry_fn {
+        "_water_worker" => {
+            water_client.cancel_with().unwrap();
+            Some(water_client.run_worker().unwrap())
+        }
+        _ => None,
+    };
+
+    water_client.write(test_message).unwrap();
+    let mut buf = vec![0; 32];
+    let n = water_client.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n as usize], test_message);
+
+    if let Some(handle_water) = handle_water {
+        water_client.cancel().unwrap();
+        handle_water.join().unwrap()?;
+    }
+
+    handle.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_connect_to_v0() -> Result<(), Box<dyn std::error::Error>> {
+    echo_through("./test_wasm/plain.wasm", "_water_worker")
+}
+
+#[test]
+fn test_connect_to_v1() -> Result<(), Box<dyn std::error::Error>> {
+    echo_through("./test_wasm/echo_client.wasm", "_water_init")
+}
+
+#[test]
+fn test_connect_to_invalid() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, _dir) = common::config_file(common::closed_port()?, 8088)?;
+
+    for destination in [
+        "127.0.0.1",
+        "127.0.0.1:http",
+        "127.0.0.1:65536",
+        ":80",
+        "::1:80",
+    ] {
+        let conf = config::WATERConfig::init(
+            String::from("./test_wasm/echo_client.wasm"),
+            String::from("_water_init"),
+            config_path.clone(),
+            config::WaterBinType::Dial,
+            true,
+        )
+        .unwrap();
+
+        let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+        let err = water_client.connect_to(destination).unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid destination"),
+            "{}: {}",
+            destination,
+            err
+        );
+    }
+
+    Ok(())
+}
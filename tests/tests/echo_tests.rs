@@ -1,35 +1,22 @@
 //! This is the test file for testing the echo_client.wasm which is a plain v1_preview WATM module,
 //! program procedures can also be treat as examples of using the WATER client.
 
+mod common;
+
 use water::*;
 
 use std::{
-    fs::File,
     io::{Read, Write},
     net::TcpListener,
 };
 
-use tempfile::tempdir;
-
 #[test]
 fn test_echo() -> Result<(), Box<dyn std::error::Error>> {
-    let cfg_str = r#"
-	{
-		"remote_address": "127.0.0.1",
-		"remote_port": 8080,
-		"local_address": "127.0.0.1",
-		"local_port": 8088
-	}
-	"#;
-    // Create a directory inside of `std::env::temp_dir()`.
-    let dir = tempdir()?;
-    let file_path = dir.path().join("temp-config.txt");
-    let mut file = File::create(&file_path)?;
-    writeln!(file, "{}", cfg_str)?;
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let (config_path, _dir) = common::config_file(listener.local_addr()?.port(), 0)?;
 
     let test_message = b"hello";
-    let handle = std::thread::spawn(|| {
-        let listener = TcpListener::bind(("127.0.0.1", 8080)).unwrap();
+    let handle = std::thread::spawn(move || {
         let (mut socket, _) = listener.accept().unwrap();
         let mut buf = [0; 1024];
         let res = socket.read(&mut buf);
@@ -49,7 +36,7 @@ fn test_echo() -> Result<(), Box<dyn std::error::Error>> {
         //
         String::from("./test_wasm/echo_client.wasm"),
         String::from("_water_init"),
-        String::from(file_path.to_string_lossy()),
+        config_path,
         config::WaterBinType::Dial,
         true,
     )
@@ -64,8 +51,64 @@ fn test_echo() -> Result<(), Box<dyn std::error::Error>> {
     assert!(res.is_ok());
     assert_eq!(res.unwrap() as usize, test_message.len());
 
-    drop(file);
-    dir.close()?;
     handle.join().unwrap();
     Ok(())
 }
+
+/// Push `total` bytes through echo_client.wasm to an echo server and read them back until the EOF of the server
+fn echo_payload(total: usize, shared_buffer: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let payload: Vec<u8> = (0..total).map(|i| (i % 251) as u8).collect();
+
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let port = listener.local_addr()?.port();
+
+    let (config_path, _dir) = common::config_file(port, 8088)?;
+
+    // reads everything before echoing, then closes the connection for the EOF
+    let handle = std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = vec![0; total];
+        socket.read_exact(&mut buf).unwrap();
+        socket.write_all(&buf).unwrap();
+    });
+
+    let mut conf = config::WATERConfig::init(
+        String::from("./test_wasm/echo_client.wasm"),
+        String::from("_water_init"),
+        config_path.clone(),
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+    conf.v1_shared_buffer = shared_buffer;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect().unwrap();
+    water_client.write(&payload).unwrap();
+
+    let mut received = Vec::with_capacity(total);
+    loop {
+        let mut buf = Vec::new();
+        let n = water_client.read(&mut buf).unwrap();
+        assert_eq!(n as usize, buf.len());
+        if n == 0 {
+            break;
+        }
+        received.extend_from_slice(&buf);
+    }
+    assert_eq!(received.len(), payload.len());
+    assert!(received == payload, "echoed payload differs");
+
+    handle.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_echo_multi_megabyte() -> Result<(), Box<dyn std::error::Error>> {
+    echo_payload(4 * 1024 * 1024 + 123, false)
+}
+
+#[test]
+fn test_echo_multi_megabyte_shared_buffer() -> Result<(), Box<dyn std::error::Error>> {
+    echo_payload(4 * 1024 * 1024 + 123, true)
+}
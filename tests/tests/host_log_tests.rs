@@ -0,0 +1,74 @@
+//! This is the test file for forwarding the WATM's logs into the Host's tracing pipeline,
+//! using the echo_client.wasm (v1_preview) WATM module which logs thru `host_log` in its `_water_init`.
+
+mod common;
+
+use water::*;
+
+use std::sync::{Arc, Mutex};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+/// (span name, message) of every event with the `watm` target
+#[derive(Clone, Default)]
+struct WatmEvents(Arc<Mutex<Vec<(String, String)>>>);
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for WatmEvents {
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if event.metadata().target() != "watm" {
+            return;
+        }
+
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+
+        let span = ctx
+            .event_span(event)
+            .map(|span| span.name().to_string())
+            .unwrap_or_default();
+        self.0.lock().unwrap().push((span, visitor.0));
+    }
+}
+
+#[test]
+fn test_guest_logs_forwarded() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, _dir) = common::config_file(8080, 8088)?;
+
+    let events = WatmEvents::default();
+    let subscriber = tracing_subscriber::registry().with(events.clone());
+
+    tracing::subscriber::with_default(subscriber, || {
+        let conf = config::WATERConfig::init(
+            String::from("./test_wasm/echo_client.wasm"),
+            String::from("_water_init"),
+            config_path,
+            config::WaterBinType::Dial,
+            true,
+        )
+        .unwrap();
+
+        // _water_init is called when creating the client
+        runtime::client::WATERClient::new(conf).unwrap();
+    });
+
+    let events = events.0.lock().unwrap();
+    assert!(events
+        .iter()
+        .any(|(span, msg)| span == "watm" && msg.contains("running in _init")));
+
+    Ok(())
+}
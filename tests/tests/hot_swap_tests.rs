@@ -0,0 +1,86 @@
+//! This is the test file for hot-swapping the WATM module of a listening v0_plus WATER client,
+//! swapping from plain.wasm to reverse.wasm which reverses the data it relays.
+
+mod common;
+
+use water::*;
+
+use std::{io::Write, net::TcpStream};
+
+#[test]
+fn test_swap_listener_module() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, dir) = common::config_file(8088, 0)?;
+
+    let test_message = b"hello";
+
+    let conf_with = |filepath: &str| {
+        config::WATERConfig::init(
+            String::from(filepath),
+            String::from("_water_worker"),
+            config_path.clone(),
+            config::WaterBinType::Listen,
+            true,
+        )
+        .unwrap()
+    };
+
+    let mut water_client =
+        runtime::client::WATERClient::new(conf_with("./test_wasm/plain.wasm")).unwrap();
+    water_client.listen().unwrap();
+
+    // nothing accepted yet, so the swap applies to the next connection right away
+    water_client
+        .swap_module(conf_with("./test_wasm/reverse.wasm"))
+        .unwrap();
+
+    // a v1 module can't take over the v0 listener, the swap is rolled back
+    assert!(water_client
+        .swap_module(conf_with("./test_wasm/echo_client.wasm"))
+        .is_err());
+    assert_eq!(water_client.config.filepath, "./test_wasm/reverse.wasm");
+
+    // neither can a V2 one, which owns its listener
+    let e = water_client
+        .swap_module(conf_with("./test_wasm/plain_v2.wasm"))
+        .unwrap_err();
+    assert!(format!("{:#}", e).contains("V2 WATM"));
+    assert_eq!(water_client.config.filepath, "./test_wasm/reverse.wasm");
+
+    // the listener migrated to the swapped module is still on the port picked by the OS
+    let addr = water_client.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(test_message).unwrap();
+    });
+
+    water_client.accept().unwrap();
+    water_client.cancel_with().unwrap();
+    let handle_water = water_client.run_worker().unwrap();
+
+    let mut buf = vec![0; 32];
+    let read_bytes = water_client.read(&mut buf).unwrap();
+    assert_eq!(&buf[..read_bytes as usize], b"olleh");
+
+    // swapping to a module whose instances then can't be created (the directory of its stdio file disappears),
+    // the next instance falls back to the previous module
+    let gone_dir = dir.path().join("gone");
+    std::fs::create_dir(&gone_dir)?;
+    let mut gone_conf = conf_with("./test_wasm/plain.wasm");
+    gone_conf.stdio =
+        config::StdioMode::File(String::from(gone_dir.join("stdio.log").to_string_lossy()));
+    water_client.swap_module(gone_conf).unwrap();
+    std::fs::remove_dir_all(&gone_dir)?;
+
+    let next_water_client = water_client.keep_listen().unwrap();
+    assert_eq!(
+        next_water_client.config.filepath,
+        "./test_wasm/reverse.wasm"
+    );
+
+    water_client.cancel().unwrap();
+    handle.join().unwrap();
+    handle_water.join().unwrap()?;
+
+    drop(next_water_client);
+    Ok(())
+}
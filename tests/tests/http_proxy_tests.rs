@@ -0,0 +1,150 @@
+//! This is the test file for the HTTP proxy front-end, tunneling the CONNECT and GET requests through
+//! a v0 (plain.wasm) dialer to the destination they requested.
+
+mod common;
+
+use water::*;
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use socket2::{Domain, Socket, Type};
+use tempfile::TempDir;
+
+/// Start an HTTP proxy dialing with plain.wasm, returns its address
+fn http_proxy(
+    dial_timeout: Duration,
+) -> Result<(std::net::SocketAddr, TempDir), Box<dyn std::error::Error>> {
+    // the remote in the config is not listening, the destination requested by the client is dialed instead
+    let (config_path, dir) = common::config_file(common::closed_port()?, 8088)?;
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let server = runtime::http_proxy::HttpProxyServer::bind(("127.0.0.1", 0), conf)?
+        .with_dial_timeout(dial_timeout);
+    let addr = server.local_addr()?;
+    std::thread::spawn(move || server.serve().unwrap());
+
+    Ok((addr, dir))
+}
+
+/// Read the response head, returns it as a string
+fn read_head(conn: &mut TcpStream) -> Result<String, Box<dyn std::error::Error>> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        conn.read_exact(&mut byte)?;
+        head.push(byte[0]);
+    }
+    Ok(String::from_utf8(head)?)
+}
+
+#[test]
+fn test_http_proxy_connect() -> Result<(), Box<dyn std::error::Error>> {
+    let port = common::echo_server()?;
+
+    let (addr, _dir) = http_proxy(Duration::from_secs(30))?;
+
+    let mut conn = TcpStream::connect(addr)?;
+    let request = format!(
+        "CONNECT 127.0.0.1:{} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n\r\n",
+        port, port
+    );
+    conn.write_all(request.as_bytes())?;
+    assert!(read_head(&mut conn)?.starts_with("HTTP/1.1 200"));
+
+    let test_message: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+    common::assert_echoed(&mut conn, &test_message)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_http_proxy_get() -> Result<(), Box<dyn std::error::Error>> {
+    // the origin server, answering with the request it got
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let request = read_head(&mut socket).unwrap();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+            request.len(),
+            request
+        );
+        socket.write_all(response.as_bytes()).unwrap();
+    });
+
+    let (addr, _dir) = http_proxy(Duration::from_secs(30))?;
+
+    let mut conn = TcpStream::connect(addr)?;
+    let request = format!(
+        "GET http://127.0.0.1:{}/index.html?q=1 HTTP/1.1\r\nHost: example.com\r\nProxy-Connection: keep-alive\r\n\r\n",
+        port
+    );
+    conn.write_all(request.as_bytes())?;
+    assert!(read_head(&mut conn)?.starts_with("HTTP/1.1 200 OK"));
+
+    let request = read_head(&mut conn)?;
+    assert_eq!(
+        request,
+        "GET /index.html?q=1 HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_http_proxy_bad_gateway() -> Result<(), Box<dyn std::error::Error>> {
+    // nothing is listening on the destination anymore
+    let port = common::closed_port()?;
+    let (addr, _dir) = http_proxy(Duration::from_secs(30))?;
+
+    let mut conn = TcpStream::connect(addr)?;
+    let request = format!("CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\n", port);
+    conn.write_all(request.as_bytes())?;
+    assert!(read_head(&mut conn)?.starts_with("HTTP/1.1 502"));
+
+    Ok(())
+}
+
+#[test]
+fn test_http_proxy_gateway_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    // a listener never accepting, whose accept queue is full: the SYNs of the dials are dropped
+    let listener = Socket::new(Domain::IPV4, Type::STREAM, None)?;
+    listener.bind(&SocketAddr::from(([127, 0, 0, 1], 0)).into())?;
+    listener.listen(0)?;
+    let destination = listener.local_addr()?.as_socket().unwrap();
+    let port = destination.port();
+    let _queued = TcpStream::connect(destination)?;
+
+    let (addr, _dir) = http_proxy(Duration::from_millis(500))?;
+
+    let mut conn = TcpStream::connect(addr)?;
+    let request = format!("CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\n", port);
+    conn.write_all(request.as_bytes())?;
+    assert!(read_head(&mut conn)?.starts_with("HTTP/1.1 504"));
+
+    Ok(())
+}
+
+#[test]
+fn test_http_proxy_unsupported_method() -> Result<(), Box<dyn std::error::Error>> {
+    let (addr, _dir) = http_proxy(Duration::from_secs(30))?;
+
+    let mut conn = TcpStream::connect(addr)?;
+    conn.write_all(b"POST http://example.com/ HTTP/1.1\r\nContent-Length: 0\r\n\r\n")?;
+    assert!(read_head(&mut conn)?.starts_with("HTTP/1.1 405"));
+
+    Ok(())
+}
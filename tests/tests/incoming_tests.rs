@@ -0,0 +1,121 @@
+//! This is the test file for iterating over the connections accepted by a Listener, as a blocking iterator
+//! and an async Stream, for v0 (plain.wasm) and v1 (echo_client.wasm, one instance per connection).
+//! The instances accepting are created from the WATM compiled for the Listener, its file is removed after `listen()`.
+
+mod common;
+
+use water::*;
+
+use std::net::TcpStream;
+
+use futures::StreamExt;
+use tempfile::TempDir;
+
+/// A Listener with a copy of `wasm` on a port picked by the OS, after `listen()` and with the copy removed
+fn listener(
+    wasm: &str,
+    entry_fn: &str,
+) -> Result<(runtime::client::WATERClient, TempDir), Box<dyn std::error::Error>> {
+    let (config_path, dir) = common::config_file(8088, 0)?;
+    let wasm_path = dir.path().join("listener.wasm");
+    std::fs::copy(wasm, &wasm_path)?;
+
+    let mut conf = config::WATERConfig::init(
+        String::from(wasm_path.to_string_lossy()),
+        String::from(entry_fn),
+        config_path,
+        config::WaterBinType::Listen,
+        true,
+    )
+    .unwrap();
+    // echo_client.wasm takes the pipes of a Listener one at a time, the shared buffer doesn't need them
+    conf.v1_shared_buffer = true;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.listen().unwrap();
+    std::fs::remove_file(&wasm_path)?;
+
+    Ok((water_client, dir))
+}
+
+/// Tunnel an accepted connection to the echo server on its own thread
+fn serve(mut water_client: runtime::client::WATERClient, echo_port: u16) {
+    std::thread::spawn(move || {
+        let conn = TcpStream::connect(("127.0.0.1", echo_port)).unwrap();
+        let _ = water_client.tunnel(conn);
+    });
+}
+
+/// Accept 2 connections with `incoming()`, both served at the same time
+fn iterate(wasm: &str, entry_fn: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let echo_port = common::echo_server()?;
+    let (mut water_client, _dir) = listener(wasm, entry_fn)?;
+    let addr = water_client.local_addr().unwrap();
+
+    let handle = std::thread::spawn(move || {
+        for accepted in water_client.incoming().take(2) {
+            serve(accepted.unwrap(), echo_port);
+        }
+    });
+
+    let mut first = TcpStream::connect(addr)?;
+    common::assert_echoed(&mut first, b"first")?;
+    let mut second = TcpStream::connect(addr)?;
+    common::assert_echoed(&mut second, b"second")?;
+    common::assert_echoed(&mut first, b"first again")?;
+
+    handle.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_incoming_v0() -> Result<(), Box<dyn std::error::Error>> {
+    iterate("./test_wasm/plain.wasm", "_water_worker")
+}
+
+#[test]
+fn test_incoming_v1() -> Result<(), Box<dyn std::error::Error>> {
+    iterate("./test_wasm/echo_client.wasm", "v1_accept")
+}
+
+#[test]
+fn test_incoming_stream() -> Result<(), Box<dyn std::error::Error>> {
+    let echo_port = common::echo_server()?;
+    let (water_client, _dir) = listener("./test_wasm/plain.wasm", "_water_worker")?;
+    let addr = water_client.local_addr().unwrap();
+
+    let mut incoming = water_client.into_incoming_stream();
+    let handle = std::thread::spawn(move || {
+        let mut first = TcpStream::connect(addr).unwrap();
+        common::assert_echoed(&mut first, b"first").unwrap();
+        let mut second = TcpStream::connect(addr).unwrap();
+        common::assert_echoed(&mut second, b"second").unwrap();
+    });
+
+    futures::executor::block_on(async {
+        for _ in 0..2 {
+            let accepted = incoming.next().await.unwrap().unwrap();
+            serve(accepted, echo_port);
+        }
+    });
+
+    handle.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_incoming_not_a_listener() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        String::from("./test_data/config.json"),
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    assert!(water_client.incoming().next().unwrap().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! This is the test file for listening on port 0 and reporting the addresses actually used on the network side,
+//! for the v0 (plain.wasm) Listener and Dialer, and the v1 (echo_client.wasm) Listener.
+
+mod common;
+
+use water::*;
+
+use std::net::{TcpListener, TcpStream};
+
+#[test]
+fn test_local_addr_v0_listener() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, _dir) = common::config_file(0, 0)?;
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Listen,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    assert!(water_client.local_addr().is_err());
+    water_client.listen().unwrap();
+
+    let addr = water_client.local_addr().unwrap();
+    assert_ne!(addr.port(), 0);
+
+    let stream = TcpStream::connect(addr)?;
+    water_client.accept().unwrap();
+    assert_eq!(water_client.peer_addr().unwrap(), stream.local_addr()?);
+
+    // the next instance keeps listening on the same address
+    let next_water_client = water_client.keep_listen().unwrap();
+    assert_eq!(next_water_client.local_addr().unwrap(), addr);
+    assert!(next_water_client.peer_addr().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_local_addr_v0_dialer() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let (config_path, _dir) = common::config_file(listener.local_addr()?.port(), 0)?;
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect().unwrap();
+
+    let (_socket, peer) = listener.accept()?;
+    assert_eq!(water_client.local_addr().unwrap(), peer);
+    assert_eq!(water_client.peer_addr().unwrap(), listener.local_addr()?);
+    assert_eq!(water_client.stats().peer_addr, Some(listener.local_addr()?));
+
+    Ok(())
+}
+
+#[test]
+fn test_local_addr_v1_listener() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, _dir) = common::config_file(0, 0)?;
+    let mut conf = config::WATERConfig::init(
+        String::from("./test_wasm/echo_client.wasm"),
+        String::from("v1_listen"),
+        config_path,
+        config::WaterBinType::Listen,
+        true,
+    )
+    .unwrap();
+    // echo_client.wasm takes the pipes of a Listener one at a time, the shared buffer doesn't need them
+    conf.v1_shared_buffer = true;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.listen().unwrap();
+
+    // bound by the Host before the WATM asks for it in create_listen
+    let addr = water_client.local_addr().unwrap();
+    assert_ne!(addr.port(), 0);
+    TcpStream::connect(addr)?;
+
+    Ok(())
+}
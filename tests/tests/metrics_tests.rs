@@ -0,0 +1,74 @@
+//! This is the test file for the process-wide metrics registry of the WATER runtime,
+//! using the plain.wasm (v0_plus) WATM module.
+
+mod common;
+
+use water::{runtime::metrics, *};
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+#[test]
+fn test_dial_failure_metrics() -> Result<(), Box<dyn std::error::Error>> {
+    // nothing is listening on the remote, so dialing it is refused
+    let (config_path, _dir) = common::config_file(common::closed_port()?, 8088)?;
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    assert!(metrics::registry().active_instances() >= 1);
+
+    // the refused dial is reported back to the WATM and surfaces as an error instead of a panic
+    assert!(water_client.connect().is_err());
+    assert_eq!(water_client.stats().errors, 1);
+
+    let rendered = metrics::registry().render();
+    assert!(rendered.contains("# TYPE water_instantiation_duration_seconds histogram"));
+    assert!(rendered.contains("water_dials_total{result=\"failure\",code=\"connection_refused\"}"));
+    assert!(rendered.contains("water_connections_total{module=\"./test_wasm/plain.wasm\"}"));
+
+    drop(water_client);
+    Ok(())
+}
+
+#[test]
+fn test_metrics_server() -> Result<(), Box<dyn std::error::Error>> {
+    let pool = metrics::registry().pool("test_pool");
+    pool.set_capacity(4);
+    let slot = pool.acquire();
+
+    let server = metrics::serve("127.0.0.1:0")?;
+
+    // a client connecting without sending anything doesn't hold up the scrapes
+    let _idle = TcpStream::connect(server.local_addr)?;
+
+    let mut stream = TcpStream::connect(server.local_addr)?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("water_pool_occupancy{pool=\"test_pool\"} 1"));
+    assert!(response.contains("water_pool_capacity{pool=\"test_pool\"} 4"));
+
+    drop(slot);
+    assert_eq!(pool.occupancy(), 0);
+
+    let sink = metrics::WriterSink::new(Vec::new());
+    metrics::registry().export(&sink)?;
+
+    let local_addr = server.local_addr;
+    server.stop()?;
+    assert!(TcpStream::connect(local_addr).is_err());
+
+    Ok(())
+}
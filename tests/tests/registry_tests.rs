@@ -0,0 +1,83 @@
+//! This is the test file for resolving WATMs from a local registry of packages,
+//! using copies of the plain.wasm (v0_plus) WATM module as the packages.
+
+use water::{config::registry, *};
+
+use std::path::Path;
+
+use tempfile::tempdir;
+
+fn add_package(
+    root: &Path,
+    version: &str,
+    sha256: &str,
+    roles: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = root.join(format!("plain-{}", version));
+    std::fs::create_dir(&dir)?;
+    std::fs::copy("./test_wasm/plain.wasm", dir.join("plain.wasm"))?;
+    std::fs::write(
+        dir.join("config.json"),
+        r#"{"remote_address": "127.0.0.1", "remote_port": 8080, "local_address": "127.0.0.1", "local_port": 8088}"#,
+    )?;
+    std::fs::write(
+        dir.join(registry::MANIFEST_FILE),
+        format!(
+            r#"{{
+                "name": "plain",
+                "version": "{}",
+                "module": "plain.wasm",
+                "sha256": "{}",
+                "roles": {},
+                "entry_fn": "_water_worker",
+                "config": "config.json"
+            }}"#,
+            version, sha256, roles
+        ),
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_registry_resolve() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let sha256 = registry::sha256_hex("./test_wasm/plain.wasm")?;
+
+    add_package(dir.path(), "0.1.0", &sha256, r#"["dial", "listen"]"#)?;
+    add_package(dir.path(), "0.2.0", &sha256, r#"["dial"]"#)?;
+    add_package(dir.path(), "0.3.0", &"0".repeat(64), r#"["dial"]"#)?;
+    std::fs::create_dir(dir.path().join("not-a-package"))?;
+
+    let registry = registry::Registry::open(dir.path())?;
+    assert_eq!(registry.packages().len(), 3);
+
+    // the latest version matching the requirement is picked
+    let conf = registry.resolve("plain", "^0.1", config::WaterBinType::Listen)?;
+    assert!(conf.filepath.ends_with("plain-0.1.0/plain.wasm"));
+    assert!(conf.config_wasm.ends_with("plain-0.1.0/config.json"));
+    assert_eq!(conf.entry_fn, "_water_worker");
+
+    let conf = registry.resolve("plain", ">=0.1, <0.3", config::WaterBinType::Dial)?;
+    assert!(conf.filepath.ends_with("plain-0.2.0/plain.wasm"));
+
+    // the resolved config is ready to be used
+    runtime::client::WATERClient::new(conf)?;
+
+    // role not supported
+    assert!(registry
+        .resolve("plain", "0.2.0", config::WaterBinType::Listen)
+        .is_err());
+
+    // module not matching the pinned hash, refused when loaded
+    let conf = registry.resolve("plain", "*", config::WaterBinType::Dial)?;
+    assert!(conf.filepath.ends_with("plain-0.3.0/plain.wasm"));
+    let err = runtime::client::WATERClient::new(conf).err().unwrap();
+    assert!(err.to_string().contains("sha256 mismatch"), "{}", err);
+
+    assert!(registry
+        .resolve("unknown", "*", config::WaterBinType::Dial)
+        .is_err());
+
+    dir.close()?;
+    Ok(())
+}
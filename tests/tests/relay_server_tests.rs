@@ -0,0 +1,178 @@
+//! This is the test file for the relay server, relaying many clients at once with plain.wasm (v0) in Relay mode,
+//! each with its own WATM instance, with the concurrency limit and the graceful shutdown.
+
+mod common;
+
+use water::*;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use tempfile::TempDir;
+
+/// Start a relay server to `remote_port`, returns its address, shutdown handle and the thread serving
+#[allow(clippy::type_complexity)]
+fn relay_server(
+    remote_port: u16,
+    max_connections: Option<usize>,
+) -> Result<
+    (
+        std::net::SocketAddr,
+        runtime::relay_server::RelayShutdown,
+        std::thread::JoinHandle<Result<(), anyhow::Error>>,
+        TempDir,
+    ),
+    Box<dyn std::error::Error>,
+> {
+    relay_server_with(remote_port, max_connections, |_, _| {})
+}
+
+/// `relay_server` with the config changed by `configure` (with the temp dir of the test)
+#[allow(clippy::type_complexity)]
+fn relay_server_with(
+    remote_port: u16,
+    max_connections: Option<usize>,
+    configure: impl FnOnce(&mut config::WATERConfig, &TempDir),
+) -> Result<
+    (
+        std::net::SocketAddr,
+        runtime::relay_server::RelayShutdown,
+        std::thread::JoinHandle<Result<(), anyhow::Error>>,
+        TempDir,
+    ),
+    Box<dyn std::error::Error>,
+> {
+    let (config_path, dir) = common::config_file(remote_port, 0)?;
+
+    let mut conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Relay,
+        true,
+    )
+    .unwrap();
+    configure(&mut conf, &dir);
+
+    let mut server = runtime::relay_server::RelayServer::listen(conf)?;
+    if let Some(max) = max_connections {
+        server = server.with_max_connections(max);
+    }
+    let addr = server.local_addr()?;
+    let shutdown = server.shutdown_handle();
+    let handle = std::thread::spawn(move || server.serve());
+
+    Ok((addr, shutdown, handle, dir))
+}
+
+#[test]
+fn test_relay_server_concurrent() -> Result<(), Box<dyn std::error::Error>> {
+    let port = common::echo_server()?;
+    let (addr, shutdown, handle, _dir) = relay_server(port, None)?;
+
+    // all the clients are relayed at the same time, each by its own instance
+    let mut conns = Vec::new();
+    for i in 0..3 {
+        let mut conn = TcpStream::connect(addr)?;
+        common::assert_echoed(&mut conn, format!("hello {}", i).as_bytes())?;
+        conns.push(conn);
+    }
+    for (i, conn) in conns.iter_mut().enumerate() {
+        common::assert_echoed(conn, format!("again {}", i).as_bytes())?;
+    }
+
+    shutdown.shutdown()?;
+    handle.join().unwrap()?;
+
+    // the workers still relaying were canceled
+    for conn in conns.iter_mut() {
+        conn.set_read_timeout(Some(Duration::from_secs(10)))?;
+        let mut buf = [0u8; 16];
+        assert!(matches!(conn.read(&mut buf), Ok(0) | Err(_)));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_relay_server_max_connections() -> Result<(), Box<dyn std::error::Error>> {
+    let port = common::echo_server()?;
+    let (addr, shutdown, handle, _dir) = relay_server(port, Some(1))?;
+
+    let mut first = TcpStream::connect(addr)?;
+    common::assert_echoed(&mut first, b"first")?;
+
+    // the second client waits in the backlog while the first one is relayed
+    let mut second = TcpStream::connect(addr)?;
+    second.write_all(b"second")?;
+    second.set_read_timeout(Some(Duration::from_secs(2)))?;
+    let mut buf = [0u8; 16];
+    assert!(second.read(&mut buf).is_err());
+
+    // and is relayed once the first one ends
+    drop(first);
+    second.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut received = [0u8; 6];
+    second.read_exact(&mut received)?;
+    assert_eq!(&received, b"second");
+
+    shutdown.shutdown()?;
+    handle.join().unwrap()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_relay_server_association_failed() -> Result<(), Box<dyn std::error::Error>> {
+    let port = common::echo_server()?;
+    let (addr, shutdown, handle, dir) = relay_server_with(port, None, |conf, dir| {
+        let stdio_dir = dir.path().join("stdio");
+        std::fs::create_dir(&stdio_dir).unwrap();
+        conf.stdio =
+            config::StdioMode::File(String::from(stdio_dir.join("stdio.log").to_string_lossy()));
+    })?;
+    let stdio_dir = dir.path().join("stdio");
+
+    let mut first = TcpStream::connect(addr)?;
+    common::assert_echoed(&mut first, b"first")?;
+
+    // the instances of the next associations can't be created while the directory of their stdio file is gone
+    std::fs::remove_dir_all(&stdio_dir)?;
+    let mut second = TcpStream::connect(addr)?;
+    second.write_all(b"second")?;
+    std::thread::sleep(Duration::from_secs(2));
+
+    // the association already running is not affected, and the server serves again once the instances can be created
+    common::assert_echoed(&mut first, b"first again")?;
+    std::fs::create_dir(&stdio_dir)?;
+    second.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut received = [0u8; 6];
+    second.read_exact(&mut received)?;
+    assert_eq!(&received, b"second");
+    let mut third = TcpStream::connect(addr)?;
+    common::assert_echoed(&mut third, b"third")?;
+
+    shutdown.shutdown()?;
+    handle.join().unwrap()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_relay_server_not_a_relay() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        String::from("./test_data/config.json"),
+        config::WaterBinType::Listen,
+        true,
+    )
+    .unwrap();
+
+    assert!(runtime::relay_server::RelayServer::listen(conf).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,182 @@
+//! This is the test file for stopping a Runner from another thread and supervising it, with a v1 WATM written in WAT
+//! whose entry functions never return (sleeping in poll_oneoff or spinning) or trap.
+
+use water::{runtime::trap::Trap, *};
+
+use std::{
+    fs::File,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tempfile::{tempdir, TempDir};
+
+const RUNNER_WAT: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (global (export "_water_v1") i32 (i32.const 1))
+  (func (export "_water_init"))
+
+  ;; poll_oneoff with a single clock subscription of an hour
+  (func (export "sleep")
+    (i32.store (i32.const 16) (i32.const 1))
+    (i64.store (i32.const 24) (i64.const 3600000000000))
+    (drop (call $poll_oneoff (i32.const 0) (i32.const 64) (i32.const 1) (i32.const 128))))
+
+  ;; loop forever without calling into the Host
+  (func (export "spin")
+    (loop $spin (br $spin)))
+
+  (func (export "trap")
+    unreachable)
+)
+"#;
+
+fn runner_config(
+    entry_fn: &str,
+) -> Result<(config::WATERConfig, TempDir), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let file_path = dir.path().join("runner.wat");
+    let mut file = File::create(&file_path)?;
+    file.write_all(RUNNER_WAT.as_bytes())?;
+
+    let conf = config::WATERConfig::init(
+        String::from(file_path.to_string_lossy()),
+        String::from(entry_fn),
+        String::from("./test_data/config.json"),
+        config::WaterBinType::Runner,
+        true,
+    )
+    .unwrap();
+
+    Ok((conf, dir))
+}
+
+/// Run `entry_fn` on a thread, stop it and check it exits without error
+fn stop_running(entry_fn: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (conf, _dir) = runner_config(entry_fn)?;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    let handle = water_client.runner_handle().unwrap();
+    let runner = std::thread::spawn(move || water_client.execute());
+
+    std::thread::sleep(Duration::from_millis(500));
+    assert!(!runner.is_finished());
+    assert!(!handle.is_stopped());
+
+    handle.stop();
+    runner.join().unwrap()?;
+    assert!(handle.is_stopped());
+
+    Ok(())
+}
+
+#[test]
+fn test_runner_stop_sleeping() -> Result<(), Box<dyn std::error::Error>> {
+    stop_running("sleep")
+}
+
+#[test]
+fn test_runner_stop_spinning() -> Result<(), Box<dyn std::error::Error>> {
+    stop_running("spin")
+}
+
+#[test]
+fn test_runner_handle_not_a_runner() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        String::from("./test_data/config.json"),
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let water_client = runtime::client::WATERClient::new(conf).unwrap();
+    assert!(water_client.runner_handle().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_supervisor_restarts_with_backoff() -> Result<(), Box<dyn std::error::Error>> {
+    let (conf, _dir) = runner_config("trap")?;
+
+    let traps = Arc::new(Mutex::new(Vec::new()));
+    let reported = Arc::clone(&traps);
+    let mut supervisor = runtime::supervisor::RunnerSupervisor::new(conf)?
+        .with_backoff(Duration::from_millis(10), Duration::from_millis(40))
+        .with_max_restarts(3)
+        .on_trap(move |trap| {
+            reported.lock().unwrap().push((
+                trap.restarts,
+                trap.backoff,
+                trap.guest_trap().cloned(),
+            ));
+        });
+
+    // gives up after the 3rd restart fails as well
+    assert!(supervisor.run().is_err());
+
+    let traps = traps.lock().unwrap();
+    let backoffs: Vec<_> = traps.iter().map(|(_, backoff, _)| *backoff).collect();
+    assert_eq!(
+        backoffs,
+        vec![
+            Some(Duration::from_millis(10)),
+            Some(Duration::from_millis(20)),
+            Some(Duration::from_millis(40)),
+            None
+        ]
+    );
+    for (i, (restarts, _, guest_trap)) in traps.iter().enumerate() {
+        assert_eq!(*restarts, i);
+
+        let guest_trap = guest_trap.as_ref().expect("a GuestTrap");
+        assert_eq!(guest_trap.function, "trap");
+        assert_eq!(guest_trap.code, Some(Trap::UnreachableCodeReached));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_supervisor_stop() -> Result<(), Box<dyn std::error::Error>> {
+    let (conf, _dir) = runner_config("sleep")?;
+
+    let traps = Arc::new(Mutex::new(0));
+    let reported = Arc::clone(&traps);
+    let mut supervisor = runtime::supervisor::RunnerSupervisor::new(conf)?.on_trap(move |_| {
+        *reported.lock().unwrap() += 1;
+    });
+    let handle = supervisor.handle();
+    let supervising = std::thread::spawn(move || supervisor.run());
+
+    std::thread::sleep(Duration::from_millis(500));
+    assert!(!supervising.is_finished());
+
+    // the runner stopped is not restarted
+    handle.stop();
+    supervising.join().unwrap()?;
+    assert_eq!(*traps.lock().unwrap(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_supervisor_not_a_runner() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        String::from("./test_data/config.json"),
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    assert!(runtime::supervisor::RunnerSupervisor::new(conf).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! This is the test file for verifying the Ed25519 signatures of the WATM binaries before loading them,
+//! using the plain.wasm (v0_plus) WATM module signed in the test.
+
+mod common;
+
+use water::{
+    runtime::signature::{self, SignatureError},
+    *,
+};
+
+use std::path::Path;
+
+use ed25519_dalek::{Signer, SigningKey};
+
+fn conf_with(
+    filepath: &Path,
+    config_path: &str,
+    trusted_keys: Vec<[u8; 32]>,
+) -> config::WATERConfig {
+    let mut conf = config::WATERConfig::init(
+        String::from(filepath.to_string_lossy()),
+        String::from("_water_worker"),
+        String::from(config_path),
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+    conf.trusted_keys = trusted_keys;
+    conf
+}
+
+fn signature_error(conf: config::WATERConfig) -> SignatureError {
+    match runtime::client::WATERClient::new(conf) {
+        Ok(_) => panic!("module should be refused"),
+        Err(e) => e.downcast::<SignatureError>().unwrap(),
+    }
+}
+
+#[test]
+fn test_module_signatures() -> Result<(), Box<dyn std::error::Error>> {
+    let (config_path, dir) = common::config_file(8080, 8088)?;
+
+    let module = std::fs::read("./test_wasm/plain.wasm")?;
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    let trusted = vec![signing_key.verifying_key().to_bytes()];
+    let untrusted = vec![SigningKey::from_bytes(&[9; 32]).verifying_key().to_bytes()];
+
+    // unsigned module is refused once there are trusted keys, and loaded as before without
+    let unsigned_path = dir.path().join("unsigned.wasm");
+    std::fs::write(&unsigned_path, &module)?;
+    assert!(matches!(
+        signature_error(conf_with(&unsigned_path, &config_path, trusted.clone())),
+        SignatureError::Unsigned(_)
+    ));
+    runtime::client::WATERClient::new(conf_with(&unsigned_path, &config_path, vec![])).unwrap();
+
+    // embedded signature
+    let embedded_path = dir.path().join("embedded.wasm");
+    let signature = signing_key.sign(&module).to_bytes();
+    let signed = signature::embed_signature(&module, &signature);
+    std::fs::write(&embedded_path, &signed)?;
+    runtime::client::WATERClient::new(conf_with(&embedded_path, &config_path, trusted.clone()))
+        .unwrap();
+    assert!(matches!(
+        signature_error(conf_with(&embedded_path, &config_path, untrusted)),
+        SignatureError::Invalid(_)
+    ));
+
+    // tampered module with an embedded signature
+    let tampered_path = dir.path().join("tampered.wasm");
+    let mut tampered = signed.clone();
+    tampered.extend_from_slice(&[0, 4, 3, b'f', b'o', b'o']); // an extra custom section
+    std::fs::write(&tampered_path, &tampered)?;
+    assert!(matches!(
+        signature_error(conf_with(&tampered_path, &config_path, trusted.clone())),
+        SignatureError::Invalid(_)
+    ));
+
+    // detached signature
+    let detached_path = dir.path().join("detached.wasm");
+    std::fs::write(&detached_path, &module)?;
+    std::fs::write(dir.path().join("detached.wasm.sig"), signature)?;
+    runtime::client::WATERClient::new(conf_with(&detached_path, &config_path, trusted)).unwrap();
+
+    dir.close()?;
+    Ok(())
+}
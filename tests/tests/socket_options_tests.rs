@@ -0,0 +1,107 @@
+//! This is the test file for the socket options of the sockets created by the Host,
+//! for the v0 (plain.wasm) Dialer and Listener, and the v1 (echo_client.wasm) Dialer.
+
+mod common;
+
+use water::*;
+
+use std::net::{IpAddr, Ipv4Addr, TcpListener};
+
+/// Options of a dialer bound to 127.0.0.2, with the per-connection options set as well
+fn dialer_options() -> config::SocketOptions {
+    config::SocketOptions {
+        nodelay: Some(true),
+        keepalive_secs: Some(30),
+        keepalive_interval_secs: Some(5),
+        keepalive_retries: Some(3),
+        send_buffer_size: Some(64 * 1024),
+        recv_buffer_size: Some(64 * 1024),
+        source_address: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))),
+        ..Default::default()
+    }
+}
+
+/// Dial with `wasm` and the dialer options, check the connection comes from the source address
+fn dial_from_source_address(wasm: &str, entry_fn: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let (config_path, _dir) = common::config_file(listener.local_addr()?.port(), 0)?;
+    let mut conf = config::WATERConfig::init(
+        String::from(wasm),
+        String::from(entry_fn),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+    conf.socket_options = dialer_options();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect().unwrap();
+
+    let (_stream, peer) = listener.accept()?;
+    assert_eq!(peer.ip(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)));
+    assert_eq!(water_client.local_addr().unwrap(), peer);
+
+    Ok(())
+}
+
+#[test]
+fn test_socket_options_v0_dialer() -> Result<(), Box<dyn std::error::Error>> {
+    dial_from_source_address("./test_wasm/plain.wasm", "_water_worker")
+}
+
+#[test]
+fn test_socket_options_v1_dialer() -> Result<(), Box<dyn std::error::Error>> {
+    dial_from_source_address("./test_wasm/echo_client.wasm", "_water_init")
+}
+
+#[test]
+fn test_socket_options_bad_source_address() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let (config_path, _dir) = common::config_file(listener.local_addr()?.port(), 0)?;
+    let mut conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+    // TEST-NET-1, not an address of this host
+    conf.socket_options.source_address = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    assert!(water_client.connect().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_socket_options_v0_listener_reuse_port() -> Result<(), Box<dyn std::error::Error>> {
+    let listen = |port: u16, reuse_port: bool| {
+        let (config_path, dir) = common::config_file(0, port).unwrap();
+        let mut conf = config::WATERConfig::init(
+            String::from("./test_wasm/plain.wasm"),
+            String::from("_water_worker"),
+            config_path,
+            config::WaterBinType::Listen,
+            true,
+        )
+        .unwrap();
+        conf.socket_options.reuse_port = Some(reuse_port);
+        conf.socket_options.listen_backlog = Some(16);
+
+        let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+        water_client.listen().map(|_| (water_client, dir))
+    };
+
+    let (first, _dir) = listen(0, true).unwrap();
+    let port = first.local_addr().unwrap().port();
+
+    // the port is shared with the listeners setting SO_REUSEPORT too, and only with them
+    let (second, _dir) = listen(port, true).unwrap();
+    assert_eq!(second.local_addr().unwrap().port(), port);
+    assert!(listen(port, false).is_err());
+
+    Ok(())
+}
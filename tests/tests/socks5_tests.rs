@@ -0,0 +1,146 @@
+//! This is the test file for the SOCKS5 front-end, tunneling the SOCKS5 clients through a v0 (plain.wasm)
+//! or v1 (echo_client.wasm) dialer to the destination they requested.
+
+mod common;
+
+use water::*;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+use tempfile::TempDir;
+
+/// Start a SOCKS5 server dialing with `wasm`, returns its address
+fn socks5_server(
+    wasm: &str,
+    entry_fn: &str,
+    auth: Option<(&str, &str)>,
+) -> Result<(std::net::SocketAddr, TempDir), Box<dyn std::error::Error>> {
+    // the remote in the config is not listening, the destination requested by the client is dialed instead
+    let (config_path, dir) = common::config_file(common::closed_port()?, 8088)?;
+
+    let conf = config::WATERConfig::init(
+        String::from(wasm),
+        String::from(entry_fn),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut server = runtime::socks5::Socks5Server::bind(("127.0.0.1", 0), conf)?;
+    if let Some((username, password)) = auth {
+        server = server.with_auth(username.to_string(), password.to_string());
+    }
+    let addr = server.local_addr()?;
+    std::thread::spawn(move || server.serve().unwrap());
+
+    Ok((addr, dir))
+}
+
+/// Do the SOCKS5 handshake and CONNECT to localhost:`port`, returns the reply code
+fn socks5_connect(
+    socks: &mut TcpStream,
+    auth: Option<(&str, &str)>,
+    port: u16,
+) -> Result<u8, Box<dyn std::error::Error>> {
+    let mut resp = [0u8; 2];
+    match auth {
+        Some((username, password)) => {
+            socks.write_all(&[0x05, 0x01, 0x02])?;
+            socks.read_exact(&mut resp)?;
+            assert_eq!(resp, [0x05, 0x02]);
+
+            let mut req = vec![0x01, username.len() as u8];
+            req.extend_from_slice(username.as_bytes());
+            req.push(password.len() as u8);
+            req.extend_from_slice(password.as_bytes());
+            socks.write_all(&req)?;
+            socks.read_exact(&mut resp)?;
+            if resp != [0x01, 0x00] {
+                return Ok(resp[1]);
+            }
+        }
+        None => {
+            socks.write_all(&[0x05, 0x01, 0x00])?;
+            socks.read_exact(&mut resp)?;
+            assert_eq!(resp, [0x05, 0x00]);
+        }
+    }
+
+    let domain = b"localhost";
+    let mut req = vec![0x05, 0x01, 0x00, 0x03, domain.len() as u8];
+    req.extend_from_slice(domain);
+    req.extend_from_slice(&port.to_be_bytes());
+    socks.write_all(&req)?;
+
+    let mut reply = [0u8; 10];
+    socks.read_exact(&mut reply)?;
+    assert_eq!(reply[0], 0x05);
+    Ok(reply[1])
+}
+
+fn assert_echoed(socks: &mut TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+    let test_message: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+    common::assert_echoed(socks, &test_message)
+}
+
+#[test]
+fn test_socks5_v0() -> Result<(), Box<dyn std::error::Error>> {
+    let port = common::echo_server()?;
+    let (addr, _dir) = socks5_server("./test_wasm/plain.wasm", "_water_worker", None)?;
+
+    // each client gets its own dialer
+    for _ in 0..2 {
+        let mut socks = TcpStream::connect(addr)?;
+        assert_eq!(socks5_connect(&mut socks, None, port)?, 0x00);
+        assert_echoed(&mut socks)?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_socks5_v1() -> Result<(), Box<dyn std::error::Error>> {
+    let port = common::echo_server()?;
+    let (addr, _dir) = socks5_server("./test_wasm/echo_client.wasm", "_water_init", None)?;
+
+    let mut socks = TcpStream::connect(addr)?;
+    assert_eq!(socks5_connect(&mut socks, None, port)?, 0x00);
+    assert_echoed(&mut socks)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_socks5_auth() -> Result<(), Box<dyn std::error::Error>> {
+    let port = common::echo_server()?;
+    let auth = Some(("water", "WATERisAwesome!"));
+    let (addr, _dir) = socks5_server("./test_wasm/plain.wasm", "_water_worker", auth)?;
+
+    let mut socks = TcpStream::connect(addr)?;
+    assert_eq!(
+        socks5_connect(&mut socks, Some(("water", "wrong")), port)?,
+        0x01
+    );
+
+    let mut socks = TcpStream::connect(addr)?;
+    assert_eq!(socks5_connect(&mut socks, auth, port)?, 0x00);
+    assert_echoed(&mut socks)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_socks5_dial_failure() -> Result<(), Box<dyn std::error::Error>> {
+    // nothing is listening on the destination anymore
+    let port = common::closed_port()?;
+    let (addr, _dir) = socks5_server("./test_wasm/plain.wasm", "_water_worker", None)?;
+
+    let mut socks = TcpStream::connect(addr)?;
+    assert_eq!(socks5_connect(&mut socks, None, port)?, 0x01);
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! This is the test file for the per-connection traffic statistics of the WATER client,
+//! using the plain.wasm (v0_plus) WATM module which doesn't transform the data.
+
+mod common;
+
+use water::{runtime::stats, *};
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    sync::mpsc,
+};
+
+#[test]
+fn test_dialer_stats() -> Result<(), Box<dyn std::error::Error>> {
+    // start the echo server first, so the WATM can dial to its port
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let remote_port = listener.local_addr()?.port();
+
+    let (config_path, _dir) = common::config_file(remote_port, 8088)?;
+
+    // the echo server keeps its socket open until told to close it, so the connection
+    // isn't ended before the stats are read
+    let test_message = b"hello";
+    let (close, closing) = mpsc::channel::<()>();
+    let handle = std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let read_bytes = socket.read(&mut buf).unwrap();
+        socket.write_all(&buf[..read_bytes]).unwrap();
+        _ = closing.recv();
+    });
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+
+    let before = water_client.stats();
+    assert!(before.started_at.is_none());
+    assert_eq!(before.caller_bytes_written, 0);
+
+    water_client.connect().unwrap();
+    water_client.cancel_with().unwrap();
+
+    let handle_water = water_client.run_worker().unwrap();
+    water_client.write(test_message).unwrap();
+
+    let mut buf = vec![0; 32];
+    let read_bytes = water_client.read(&mut buf).unwrap();
+    assert_eq!(read_bytes as usize, test_message.len());
+
+    let during = water_client.stats();
+    assert!(during.started_at.is_some());
+    assert!(during.ended_at.is_none());
+    assert_eq!(during.caller_bytes_written, test_message.len() as u64);
+    assert_eq!(during.caller_bytes_read, test_message.len() as u64);
+    assert_eq!(during.network_bytes_sent, test_message.len() as u64);
+    assert_eq!(during.network_bytes_received, test_message.len() as u64);
+    assert_eq!(during.errors, 0);
+    // plain.wasm doesn't add any overhead
+    assert_eq!(during.expansion_ratio(), Some(1.0));
+
+    close.send(())?;
+    water_client.cancel().unwrap();
+    handle.join().unwrap();
+    handle_water.join().unwrap()?;
+
+    let after = water_client.stats();
+    assert!(after.ended_at.is_some());
+    assert!(after.duration().is_some());
+
+    let module = stats::module_stats("./test_wasm/plain.wasm").unwrap();
+    assert!(module.connections >= 1);
+    assert!(module.network_bytes_sent >= test_message.len() as u64);
+
+    Ok(())
+}
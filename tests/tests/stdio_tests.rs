@@ -0,0 +1,80 @@
+//! This is the test file for the stdio modes of the WATM instances,
+//! using the plain.wasm (v0_plus) WATM module which prints its progress to stdout.
+
+mod common;
+
+use water::{
+    config::StdioMode,
+    runtime::stdio::{CapturedStdio, RingBuffer},
+    *,
+};
+
+use std::io::Write;
+
+#[test]
+fn test_capture_stdio() -> Result<(), Box<dyn std::error::Error>> {
+    // nothing is listening on the remote, so dialing it is refused
+    let (config_path, _dir) = common::config_file(common::closed_port()?, 8088)?;
+
+    let mut conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+    conf.stdio = StdioMode::Capture(4096);
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    assert!(water_client.connect().is_err());
+
+    let stdout = String::from_utf8(water_client.captured_stdout().unwrap())?;
+    assert!(stdout.contains("Dialer: dialing..."));
+    assert!(stdout.contains("dial failed"));
+
+    Ok(())
+}
+
+#[test]
+fn test_file_stdio() -> Result<(), Box<dyn std::error::Error>> {
+    // nothing is listening on the remote, so dialing it is refused
+    let (config_path, dir) = common::config_file(common::closed_port()?, 8088)?;
+    let stdio_path = dir.path().join("watm.log");
+
+    let mut conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+    conf.stdio = StdioMode::File(String::from(stdio_path.to_string_lossy()));
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    assert!(water_client.connect().is_err());
+    assert!(water_client.captured_stdout().is_none());
+
+    let logged = std::fs::read_to_string(&stdio_path)?;
+    assert!(logged.contains("Dialer: dialing..."));
+
+    Ok(())
+}
+
+#[test]
+fn test_ring_buffer() -> Result<(), Box<dyn std::error::Error>> {
+    let mut stdio = CapturedStdio {
+        stdout: RingBuffer::new(16),
+        stderr: RingBuffer::new(16),
+    };
+
+    stdio.stdout.write_all(b"0123456789")?;
+    stdio.stdout.write_all(b"abcdefghij")?;
+    assert_eq!(stdio.stdout.contents(), b"456789abcdefghij");
+
+    stdio.stderr.write_all(b"first\nsecond\nthird\n")?;
+    assert_eq!(stdio.stderr.tail_lines(2), "second\nthird");
+
+    Ok(())
+}
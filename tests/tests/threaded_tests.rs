@@ -0,0 +1,62 @@
+//! This is the test file for running a WATM built for wasm32-wasi-threads, which needs the `multithread` feature:
+//! `cargo test -p tests --features multithread --test threaded_tests`
+//!
+//! threaded.wasm is a v0 WATM encoding / decoding the two directions of the connection in their own thread.
+
+#![cfg(feature = "multithread")]
+
+mod common;
+
+use water::*;
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+};
+
+#[test]
+fn test_threaded_wasm_dialer() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let (config_path, _dir) = common::config_file(listener.local_addr()?.port(), 0)?;
+
+    let test_message = b"hello";
+    let handle = std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let read_bytes = socket.read(&mut buf).unwrap();
+
+        // the WATM flips all the bits of what is sent to the network
+        let encoded: Vec<u8> = test_message.iter().map(|b| !b).collect();
+        assert_eq!(&buf[..read_bytes], &encoded[..]);
+
+        // echo back, which the WATM decodes
+        socket.write_all(&buf[..read_bytes]).unwrap();
+    });
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/threaded.wasm"),
+        String::from("_water_worker"),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect().unwrap();
+    water_client.cancel_with().unwrap();
+
+    let handle_water = water_client.run_worker().unwrap();
+    water_client.write(test_message).unwrap();
+
+    let mut buf = vec![0; 32];
+    let read_bytes = water_client.read(&mut buf).unwrap();
+    assert_eq!(&buf[..read_bytes as usize], test_message);
+
+    water_client.cancel().unwrap();
+
+    handle.join().unwrap();
+    handle_water.join().unwrap()?;
+
+    Ok(())
+}
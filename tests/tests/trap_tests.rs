@@ -0,0 +1,165 @@
+//! This is the test file for the diagnostics of the WATMs trapping, with v1 WATMs written in WAT
+//! whose functions are named in the name section.
+
+use water::{
+    config::StdioMode,
+    runtime::trap::{GuestTrap, Trap},
+    *,
+};
+
+use std::{fs::File, io::Write};
+
+use tempfile::{tempdir, TempDir};
+
+const RUNNER_WAT: &str = r#"
+(module $watm
+  (memory (export "memory") 1)
+  (global (export "_water_v1") i32 (i32.const 1))
+  (func (export "_water_init"))
+
+  (func $inner
+    unreachable)
+  (func $outer
+    (call $inner))
+  (func $trap (export "trap")
+    (call $outer))
+
+  ;; a load past the single page of memory
+  (func $oob (export "oob")
+    (drop (i32.load (i32.const 0x20000))))
+
+  (func $recurse (export "recurse")
+    (call $recurse))
+)
+"#;
+
+/// A v1 Dialer writing to stderr and trapping in `_water_dial`
+const DIALER_WAT: &str = r#"
+(module $dialer
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 256) "dialing failed\n")
+  (global (export "_water_v1") i32 (i32.const 1))
+  (func (export "_water_init"))
+  (func (export "_water_set_inbound") (param i32))
+  (func (export "_water_read") (result i64) (i64.const 0))
+  (func (export "_water_write") (param i64) (result i64) (local.get 0))
+
+  (func $fail
+    unreachable)
+  (func $dial (export "_water_dial")
+    (i32.store (i32.const 0) (i32.const 256))
+    (i32.store (i32.const 4) (i32.const 15))
+    (drop (call $fd_write (i32.const 2) (i32.const 0) (i32.const 1) (i32.const 8)))
+    (call $fail))
+)
+"#;
+
+fn wat_config(
+    wat: &str,
+    entry_fn: &str,
+    client_type: config::WaterBinType,
+) -> Result<(config::WATERConfig, TempDir), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let file_path = dir.path().join("watm.wat");
+    let mut file = File::create(&file_path)?;
+    file.write_all(wat.as_bytes())?;
+
+    let conf = config::WATERConfig::init(
+        String::from(file_path.to_string_lossy()),
+        String::from(entry_fn),
+        String::from("./test_data/config.json"),
+        client_type,
+        true,
+    )
+    .unwrap();
+
+    Ok((conf, dir))
+}
+
+/// Run `entry_fn` of the Runner and return the trap it failed with
+fn run_trapping(entry_fn: &str) -> Result<GuestTrap, Box<dyn std::error::Error>> {
+    let (conf, _dir) = wat_config(RUNNER_WAT, entry_fn, config::WaterBinType::Runner)?;
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    let err = water_client.execute().unwrap_err();
+    let trap = err.downcast_ref::<GuestTrap>().expect("a GuestTrap");
+
+    Ok(trap.clone())
+}
+
+#[test]
+fn test_trap_unreachable_backtrace() -> Result<(), Box<dyn std::error::Error>> {
+    let trap = run_trapping("trap")?;
+
+    assert_eq!(trap.function, "trap");
+    assert_eq!(trap.code, Some(Trap::UnreachableCodeReached));
+
+    // innermost first, named after the name section
+    let names: Vec<_> = trap
+        .backtrace
+        .iter()
+        .map(|frame| frame.func_name.as_deref())
+        .collect();
+    assert_eq!(names, vec![Some("inner"), Some("outer"), Some("trap")]);
+    assert!(
+        trap.backtrace
+            .iter()
+            .all(|frame| frame.module_name.as_deref() == Some("watm")
+                && frame.module_offset.is_some())
+    );
+
+    let rendered = trap.to_string();
+    assert!(
+        rendered.starts_with("trap function failed: "),
+        "{}",
+        rendered
+    );
+    assert!(rendered.contains("wasm backtrace:"), "{}", rendered);
+    assert!(rendered.contains("watm!inner"), "{}", rendered);
+    assert!(trap.stderr_tail.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_trap_out_of_bounds() -> Result<(), Box<dyn std::error::Error>> {
+    let trap = run_trapping("oob")?;
+
+    assert_eq!(trap.code, Some(Trap::MemoryOutOfBounds));
+    assert_eq!(trap.backtrace[0].func_name.as_deref(), Some("oob"));
+
+    Ok(())
+}
+
+#[test]
+fn test_trap_stack_overflow() -> Result<(), Box<dyn std::error::Error>> {
+    let trap = run_trapping("recurse")?;
+
+    assert_eq!(trap.code, Some(Trap::StackOverflow));
+    assert!(trap
+        .backtrace
+        .iter()
+        .all(|frame| frame.func_name.as_deref() == Some("recurse")));
+
+    Ok(())
+}
+
+#[test]
+fn test_trap_dial_with_stderr() -> Result<(), Box<dyn std::error::Error>> {
+    let (mut conf, _dir) = wat_config(DIALER_WAT, "_water_init", config::WaterBinType::Dial)?;
+    conf.stdio = StdioMode::Capture(4096);
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    let err = water_client.connect().unwrap_err();
+    let trap = err.downcast_ref::<GuestTrap>().expect("a GuestTrap");
+
+    assert_eq!(trap.function, "_water_dial");
+    assert_eq!(trap.code, Some(Trap::UnreachableCodeReached));
+    assert_eq!(trap.backtrace[0].func_name.as_deref(), Some("fail"));
+    assert_eq!(trap.backtrace[1].func_name.as_deref(), Some("dial"));
+    assert_eq!(trap.stderr_tail.as_deref(), Some("dialing failed"));
+    assert!(err.to_string().contains("WATM stderr:\ndialing failed"));
+
+    Ok(())
+}
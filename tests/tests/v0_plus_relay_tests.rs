@@ -19,14 +19,18 @@ use tempfile::tempdir;
 fn test_cross_lang_wasm_relay() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt().with_max_level(Level::INFO).init();
 
-    let cfg_str = r#"
-	{
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let cfg_str = format!(
+        r#"
+	{{
 		"remote_address": "127.0.0.1",
-		"remote_port": 8088,
+		"remote_port": {},
 		"local_address": "127.0.0.1",
-		"local_port": 8080
-	}
-	"#;
+		"local_port": 0
+	}}
+	"#,
+        listener.local_addr()?.port()
+    );
     // Create a directory inside of `std::env::temp_dir()`.
     let dir = tempdir()?;
     let file_path = dir.path().join("temp-config.txt");
@@ -36,8 +40,7 @@ fn test_cross_lang_wasm_relay() -> Result<(), Box<dyn std::error::Error>> {
     let test_message = b"hello";
 
     // starting the listener in another thread it to relay to
-    let handle_remote = std::thread::spawn(|| {
-        let listener = TcpListener::bind(("127.0.0.1", 8088)).unwrap();
+    let handle_remote = std::thread::spawn(move || {
         let (mut socket, _) = listener.accept().unwrap();
         let mut buf = [0; 1024];
         let res = socket.read(&mut buf);
@@ -71,11 +74,10 @@ fn test_cross_lang_wasm_relay() -> Result<(), Box<dyn std::error::Error>> {
 
     water_client.listen().unwrap();
 
-    // connects to the relay, and the relay will connect to the listener
-    let handle_local = std::thread::spawn(|| {
-        // give some time let the listener start to accept
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        let mut stream = TcpStream::connect(("127.0.0.1", 8080)).unwrap();
+    // connects to the relay (on the port picked by the OS), and the relay will connect to the listener
+    let addr = water_client.local_addr().unwrap();
+    let handle_local = std::thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
 
         let res = stream.write(test_message);
         assert!(res.is_ok());
@@ -23,14 +23,18 @@ use tempfile::tempdir;
 fn test_cross_lang_wasm_dialer() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt().with_max_level(Level::INFO).init();
 
-    let cfg_str = r#"
-	{
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let cfg_str = format!(
+        r#"
+	{{
 		"remote_address": "127.0.0.1",
-		"remote_port": 8080,
+		"remote_port": {},
 		"local_address": "127.0.0.1",
-		"local_port": 8088
-	}
-	"#;
+		"local_port": 0
+	}}
+	"#,
+        listener.local_addr()?.port()
+    );
     // Create a directory inside of `std::env::temp_dir()`.
     let dir = tempdir()?;
     let file_path = dir.path().join("temp-config.txt");
@@ -38,8 +42,7 @@ fn test_cross_lang_wasm_dialer() -> Result<(), Box<dyn std::error::Error>> {
     writeln!(file, "{}", cfg_str)?;
 
     let test_message = b"hello";
-    let handle = std::thread::spawn(|| {
-        let listener = TcpListener::bind(("127.0.0.1", 8080)).unwrap();
+    let handle = std::thread::spawn(move || {
         let (mut socket, _) = listener.accept().unwrap();
         let mut buf = [0; 1024];
         let res = socket.read(&mut buf);
@@ -108,7 +111,7 @@ fn test_cross_lang_wasm_listener() -> Result<(), Box<dyn std::error::Error>> {
 		"remote_address": "127.0.0.1",
 		"remote_port": 8088,
 		"local_address": "127.0.0.1",
-		"local_port": 8082
+		"local_port": 0
 	}
 	"#;
     // Create a directory inside of `std::env::temp_dir()`.
@@ -131,10 +134,10 @@ fn test_cross_lang_wasm_listener() -> Result<(), Box<dyn std::error::Error>> {
     let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
     water_client.listen().unwrap();
 
-    let handle = std::thread::spawn(|| {
-        // give some time let the listener start to accept
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        let mut stream = TcpStream::connect(("127.0.0.1", 8082)).unwrap();
+    // the port picked by the OS
+    let addr = water_client.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
         let res = stream.write(test_message);
 
         assert!(res.is_ok());
@@ -183,7 +186,7 @@ fn test_cross_lang_wasm_multi_listener() -> Result<(), Box<dyn std::error::Error
 		"remote_address": "127.0.0.1",
 		"remote_port": 8088,
 		"local_address": "127.0.0.1",
-		"local_port": 10088
+		"local_port": 0
 	}
 	"#;
     // Create a directory inside of `std::env::temp_dir()`.
@@ -205,14 +208,16 @@ fn test_cross_lang_wasm_multi_listener() -> Result<(), Box<dyn std::error::Error
     water_client.listen().unwrap();
 
     let test_message: &'static [u8] = b"hello";
+    // the port picked by the OS
+    let addr = water_client.local_addr().unwrap();
 
     let mut water_handles: Vec<JoinHandle<()>> = Vec::new();
 
     // creating two connections to the listener
     for _i in 0..2 {
         // make a connect to the listener in a separate thread
-        std::thread::spawn(|| {
-            let mut stream = TcpStream::connect(("127.0.0.1", 10088)).unwrap();
+        std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
             let res = stream.write(test_message);
 
             assert!(res.is_ok());
@@ -0,0 +1,141 @@
+//! This is the test file for V2 WATMs, which are components implementing the `water:watm` WIT world
+//! and doing their own networking with wasi-sockets.
+//!
+//! plain_v2.wasm is built from examples/water_bins/plain_v2, relaying the data as is.
+
+mod common;
+
+use water::*;
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use tempfile::tempdir;
+
+#[test]
+fn test_v2_wasm_dialer() -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let (config_path, _dir) = common::config_file(listener.local_addr()?.port(), 0)?;
+
+    let test_message = b"hello";
+    let handle = std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let read_bytes = socket.read(&mut buf).unwrap();
+        assert_eq!(&buf[..read_bytes], test_message);
+
+        socket.write_all(&buf[..read_bytes]).unwrap();
+    });
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain_v2.wasm"),
+        String::from(""),
+        config_path,
+        config::WaterBinType::Dial,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.connect().unwrap();
+
+    // V2 WATMs are called directly, there is no worker to run
+    assert!(water_client.run_worker().is_err());
+
+    water_client.write(test_message).unwrap();
+
+    let mut buf = vec![0; 32];
+    let read_bytes = water_client.read(&mut buf).unwrap();
+    assert_eq!(&buf[..read_bytes as usize], test_message);
+
+    water_client.cancel().unwrap();
+    handle.join().unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_v2_wasm_listener() -> Result<(), Box<dyn std::error::Error>> {
+    // the WATM binds the listener itself, on a port free for it
+    let port = common::closed_port()?;
+    let (config_path, _dir) = common::config_file(8088, port)?;
+
+    let conf = config::WATERConfig::init(
+        String::from("./test_wasm/plain_v2.wasm"),
+        String::from(""),
+        config_path,
+        config::WaterBinType::Listen,
+        true,
+    )
+    .unwrap();
+
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    water_client.listen().unwrap();
+
+    let test_message = b"hello";
+    let handle = std::thread::spawn(move || {
+        // the WATM binds once accepting, connecting until it listens
+        let mut stream = (0..300)
+            .find_map(|_| {
+                TcpStream::connect(("127.0.0.1", port))
+                    .inspect_err(|_| std::thread::sleep(Duration::from_millis(100)))
+                    .ok()
+            })
+            .expect("the WATM is not listening");
+        stream.write_all(test_message).unwrap();
+
+        let mut buf = [0; 1024];
+        let read_bytes = stream.read(&mut buf).unwrap();
+        assert_eq!(&buf[..read_bytes], test_message);
+    });
+
+    water_client.accept().unwrap();
+
+    let mut buf = vec![0; 32];
+    let read_bytes = water_client.read(&mut buf).unwrap();
+    assert_eq!(&buf[..read_bytes as usize], test_message);
+    water_client.write(&buf[..read_bytes as usize]).unwrap();
+
+    // the listener belongs to the WATM, it can't be migrated
+    assert!(water_client.keep_listen().is_err());
+
+    handle.join().unwrap();
+    water_client.cancel().unwrap();
+
+    Ok(())
+}
+
+#[test]
+fn test_v2_wasm_init_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempdir()?;
+    let file_path = dir.path().join("temp-config.txt");
+    std::fs::write(&file_path, "not a config")?;
+
+    let v2_config = |config_wasm: &std::path::Path| {
+        config::WATERConfig::init(
+            String::from("./test_wasm/plain_v2.wasm"),
+            String::from(""),
+            String::from(config_wasm.to_string_lossy()),
+            config::WaterBinType::Dial,
+            true,
+        )
+        .unwrap()
+    };
+
+    // a malformed config file isn't replaced by the default one
+    assert!(runtime::client::WATERClient::new(v2_config(&file_path)).is_err());
+
+    // the stdio of a V2 WATM can't be captured
+    let mut conf = v2_config(&dir.path().join("missing-config.txt"));
+    conf.stdio = config::StdioMode::Capture(4096);
+    assert!(runtime::client::WATERClient::new(conf).is_err());
+
+    // without a config file, the WATM gets the default one
+    runtime::client::WATERClient::new(v2_config(&dir.path().join("missing-config.txt")))?;
+
+    dir.close()?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! This is the test file for running a WATM over a connection provided by the application with `wrap` / `wrap_io`,
+//! instead of dialing, for both v0 (plain.wasm) and v1 (echo_client.wasm).
+
+mod common;
+
+use water::*;
+
+use std::net::{TcpListener, TcpStream};
+
+#[derive(Clone, Copy)]
+enum WrapWith {
+    TcpStream,
+    Duplex,
+}
+
+/// Send a message with `wasm` over a connection to an echo server made by the test, and check it is echoed back
+fn echo_through(
+    wasm: &str,
+    entry_fn: &str,
+    with: WrapWith,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let test_message = b"hello";
+
+    // the echo server, echoing one message
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let addr = listener.local_addr()?;
+    let handle = common::echo_once(listener);
+
+    // the remote in the config is not listening, only the provided connection is used
+    let (config_path, _dir) = common::config_file(common::closed_port()?, 8088)?;
+
+    let conf = config::WATERConfig::init(
+        String::from(wasm),
+        String::from(entry_fn),
+        config_path,
+        config::WaterBinType::Wrap,
+        true,
+    )
+    .unwrap();
+
+    let conn = TcpStream::connect(addr)?;
+    let mut water_client = runtime::client::WATERClient::new(conf).unwrap();
+    match with {
+        WrapWith::TcpStream => water_client.wrap(conn).unwrap(),
+        WrapWith::Duplex => water_client.wrap_io(conn.try_clone()?, conn).unwrap(),
+    }
+
+    let handle_water = match entry_fn {
+        "_water_worker" => {
+            water_client.cancel_with().unwrap();
+            Some(water_client.run_worker().unwrap())
+        }
+        _ => None,
+    };
+
+    water_client.write(test_message).unwrap();
+    let mut buf = vec![0; 32];
+    let n = water_client.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n as usize], test_message);
+
+    // a duplex is pushed as the Unix socket it is, with no address
+    let peer_addr = water_client.stats().peer_addr;
+    match with {
+        WrapWith::TcpStream => assert_eq!(peer_addr, Some(addr)),
+        WrapWith::Duplex => assert_eq!(peer_addr, None),
+    }
+
+    if let Some(handle_water) = handle_water {
+        water_client.cancel().unwrap();
+        handle_water.join().unwrap()?;
+    }
+
+    handle.join().unwrap();
+    Ok(())
+}
+
+#[test]
+fn test_wrap_v0() -> Result<(), Box<dyn std::error::Error>> {
+    echo_through(
+        "./test_wasm/plain.wasm",
+        "_water_worker",
+        WrapWith::TcpStream,
+    )
+}
+
+#[test]
+fn test_wrap_v1() -> Result<(), Box<dyn std::error::Error>> {
+    echo_through(
+        "./test_wasm/echo_client.wasm",
+        "_water_init",
+        WrapWith::TcpStream,
+    )
+}
+
+#[test]
+fn test_wrap_io_v0() -> Result<(), Box<dyn std::error::Error>> {
+    echo_through("./test_wasm/plain.wasm", "_water_worker", WrapWith::Duplex)
+}
+
+#[test]
+fn test_wrap_io_v1() -> Result<(), Box<dyn std::error::Error>> {
+    echo_through(
+        "./test_wasm/echo_client.wasm",
+        "_water_init",
+        WrapWith::Duplex,
+    )
+}